@@ -0,0 +1,411 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const PROTOCOL_FILE: &str = "protocol.toml";
+const PYTHON_CLIENT: &str = "clients/python/hydrogen_client.py";
+const NODE_CLIENT: &str = "clients/node/hydrogen_client.js";
+
+#[derive(Deserialize)]
+struct Protocol {
+    command: Vec<CommandSpec>,
+}
+
+#[derive(Deserialize)]
+struct CommandSpec {
+    name: String,
+    method: String,
+    summary: String,
+    args: Vec<ArgSpec>,
+    #[serde(default)]
+    flags: Vec<String>,
+    reply: String,
+    deprecated: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ArgSpec {
+    name: String,
+    #[serde(default)]
+    quoted: bool,
+    flag: Option<String>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(default)]
+    repeated: bool,
+    keyword: Option<String>,
+}
+
+const REPLY_KINDS: &[&str] = &["status", "value", "boolean", "integer", "keys", "pairs", "text"];
+const FLAGS: &[&str] = &["read", "write", "admin", "slow", "cluster"];
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", PROTOCOL_FILE);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let source = fs::read_to_string(PROTOCOL_FILE).expect("failed to read protocol.toml");
+    let protocol: Protocol = toml::from_str(&source).expect("failed to parse protocol.toml");
+    for command in &protocol.command {
+        if !REPLY_KINDS.contains(&command.reply.as_str()) {
+            panic!("{}: unknown reply kind '{}'", command.name, command.reply);
+        }
+        if let Some(flag) = command.flags.iter().find(|flag| !FLAGS.contains(&flag.as_str())) {
+            panic!("{}: unknown flag '{}'", command.name, flag);
+        }
+        if command.deprecated.as_ref().is_some_and(|message| message.trim().is_empty()) {
+            panic!("{}: deprecated needs a message, such as what to use instead", command.name);
+        }
+        for (i, arg) in command.args.iter().enumerate() {
+            if arg.optional && arg.flag.is_some() {
+                panic!("{}: argument '{}' is both a flag and optional", command.name, arg.name);
+            }
+            if arg.repeated && (arg.optional || arg.flag.is_some() || i + 1 != command.args.len()) {
+                panic!("{}: repeated argument '{}' must be the last and required", command.name, arg.name);
+            }
+            if arg.keyword.is_some() && !arg.optional {
+                panic!("{}: argument '{}' has a keyword but is not optional", command.name, arg.name);
+            }
+        }
+    }
+
+    write_if_changed(PYTHON_CLIENT, &python_client(&protocol));
+    write_if_changed(NODE_CLIENT, &node_client(&protocol));
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is not set");
+    write_if_changed(&format!("{}/command_specs.rs", out_dir), &command_table(&protocol));
+}
+
+// Rewriting an unchanged file would dirty the tree on every build
+fn write_if_changed(path: &str, contents: &str) {
+    if fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return;
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent).expect("failed to create client directory");
+    }
+    fs::write(path, contents).expect("failed to write generated file");
+}
+
+fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+    for ch in name.chars() {
+        if ch == '_' {
+            upper = true;
+        } else if upper {
+            result.push(ch.to_ascii_uppercase());
+            upper = false;
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+fn title_case(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+fn python_client(protocol: &Protocol) -> String {
+    let mut out = String::from(PYTHON_HEADER);
+    for command in &protocol.command {
+        let params = command.args.iter()
+            .map(|arg| match arg.flag {
+                Some(_) => format!(", {}=False", arg.name),
+                None if arg.optional => format!(", {}=None", arg.name),
+                None if arg.repeated => format!(", *{}", arg.name),
+                None => format!(", {}", arg.name),
+            })
+            .collect::<String>();
+        out.push_str(&format!("\n    def {}(self{}):\n", command.method, params));
+        match &command.deprecated {
+            Some(message) => out.push_str(&format!("        \"\"\"{} (deprecated: {})\"\"\"\n", command.summary, message)),
+            None => out.push_str(&format!("        \"\"\"{}\"\"\"\n", command.summary)),
+        }
+        out.push_str(&format!("        parts = [\"{}\"]\n", command.name));
+        for arg in &command.args {
+            match (&arg.flag, arg.quoted) {
+                (Some(flag), _) => {
+                    out.push_str(&format!("        if {}:\n", arg.name));
+                    out.push_str(&format!("            parts.append(\"{}\")\n", flag));
+                }
+                (None, quoted) if arg.repeated => {
+                    let value = if quoted { "_quote(item)" } else { "str(item)" };
+                    out.push_str(&format!("        parts.extend({} for item in {})\n", value, arg.name));
+                }
+                (None, quoted) => {
+                    let indent = if arg.optional {
+                        out.push_str(&format!("        if {} is not None:\n", arg.name));
+                        "            "
+                    } else {
+                        "        "
+                    };
+                    if let Some(keyword) = &arg.keyword {
+                        out.push_str(&format!("{}parts.append(\"{}\")\n", indent, keyword));
+                    }
+                    let value = if quoted { format!("_quote({})", arg.name) } else { format!("str({})", arg.name) };
+                    out.push_str(&format!("{}parts.append({})\n", indent, value));
+                }
+            }
+        }
+        out.push_str(&format!("        return _parse_{}(self.execute(\" \".join(parts)))\n", command.reply));
+    }
+    out
+}
+
+fn node_client(protocol: &Protocol) -> String {
+    let mut out = String::from(NODE_HEADER);
+    for command in &protocol.command {
+        let params = command.args.iter()
+            .map(|arg| match arg.flag {
+                Some(_) => format!("{} = false", camel_case(&arg.name)),
+                None if arg.repeated => format!("...{}", camel_case(&arg.name)),
+                None => camel_case(&arg.name),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        match &command.deprecated {
+            Some(message) => out.push_str(&format!("\n  /**\n   * {}\n   * @deprecated {}\n   */\n", command.summary, message)),
+            None => out.push_str(&format!("\n  /** {} */\n", command.summary)),
+        }
+        out.push_str(&format!("  async {}({}) {{\n", camel_case(&command.method), params));
+        out.push_str(&format!("    const parts = ['{}'];\n", command.name));
+        for arg in &command.args {
+            let name = camel_case(&arg.name);
+            match (&arg.flag, arg.quoted) {
+                (Some(flag), _) => out.push_str(&format!("    if ({}) parts.push('{}');\n", name, flag)),
+                (None, quoted) => {
+                    let value = if quoted { format!("quote({})", name) } else { format!("String({})", name) };
+                    if arg.repeated {
+                        let mapper = if quoted { "quote" } else { "String" };
+                        out.push_str(&format!("    parts.push(...{}.map({}));\n", name, mapper));
+                    } else if let (true, Some(keyword)) = (arg.optional, &arg.keyword) {
+                        out.push_str(&format!("    if ({} !== undefined) parts.push('{}', {});\n", name, keyword, value));
+                    } else if arg.optional {
+                        out.push_str(&format!("    if ({} !== undefined) parts.push({});\n", name, value));
+                    } else {
+                        out.push_str(&format!("    parts.push({});\n", value));
+                    }
+                }
+            }
+        }
+        out.push_str(&format!("    return parse{}(await this.execute(parts.join(' ')));\n", title_case(&command.reply)));
+        out.push_str("  }\n");
+    }
+    out.push_str(NODE_FOOTER);
+    out
+}
+
+// Included by src/command_spec.rs, so the server and the CLI describe
+// commands from the same table the clients are generated from
+fn command_table(protocol: &Protocol) -> String {
+    let mut out = String::from("// Generated from protocol.toml by build.rs, do not edit\n");
+    out.push_str("pub static COMMANDS: &[CommandSpec] = &[\n");
+    for command in &protocol.command {
+        let args = command.args.iter()
+            .map(|arg| format!(
+                "ArgSpec {{ name: {:?}, quoted: {}, flag: {:?}, optional: {}, repeated: {}, keyword: {:?} }}",
+                arg.name, arg.quoted, arg.flag.as_deref(), arg.optional, arg.repeated, arg.keyword.as_deref()
+            ))
+            .collect::<Vec<String>>()
+            .join(", ");
+        out.push_str(&format!(
+            "    CommandSpec {{ name: {:?}, summary: {:?}, args: &[{}], flags: &{:?}, deprecated: {:?} }},\n",
+            command.name, command.summary, args, command.flags, command.deprecated.as_deref()
+        ));
+    }
+    out.push_str("];\n");
+    out
+}
+
+const PYTHON_HEADER: &str = r#"# Generated from protocol.toml by build.rs, do not edit
+import json
+import re
+import socket
+import warnings
+
+_PAIR = re.compile(r'("(?:[^"\\]|\\.)*"|\S+) "((?:[^"\\]|\\.)*)"')
+_ESCAPE = re.compile(r'\\(.)')
+_ESCAPES = {"n": "\n", "r": "\r", "t": "\t", "0": "\0"}
+_DEPRECATED = "\t!DEPRECATED "
+
+
+class HydrogenError(Exception):
+    pass
+
+
+def _quote(value):
+    return '"' + str(value) + '"'
+
+
+def _parse_status(reply):
+    return reply == "OK"
+
+
+def _parse_value(reply):
+    return None if reply == "NULL" else reply
+
+
+def _parse_boolean(reply):
+    return reply == "1"
+
+
+def _parse_integer(reply):
+    return int(reply)
+
+
+def _parse_keys(reply):
+    return [] if reply == "(empty)" else reply.split(" ")
+
+
+def _unescape(text):
+    return _ESCAPE.sub(lambda match: _ESCAPES.get(match.group(1), match.group(1)), text)
+
+
+def _parse_pairs(reply):
+    if reply == "(empty)":
+        return {}
+    pairs = {}
+    for key, value in _PAIR.findall(reply):
+        if key.startswith('"'):
+            key = _unescape(key[1:-1])
+        pairs[key] = _unescape(value)
+    return pairs
+
+
+def _parse_text(reply):
+    return reply
+
+
+class HydrogenClient:
+    def __init__(self, host="127.0.0.1", port=1825, timeout=None):
+        self._sock = socket.create_connection((host, port), timeout=timeout)
+        self._reader = self._sock.makefile("r", encoding="utf-8", newline="\n")
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def close(self):
+        self._reader.close()
+        self._sock.close()
+
+    def execute(self, line):
+        """Send one request line and return the reply, raising on errors"""
+        self._sock.sendall((line + "\n").encode("utf-8"))
+        reply = self._reader.readline()
+        if not reply:
+            raise ConnectionError("connection closed by server")
+        reply = reply.rstrip("\r\n")
+        if _DEPRECATED in reply:
+            reply, warning = reply.rsplit(_DEPRECATED, 1)
+            warning = json.loads(warning)
+            warnings.warn(
+                "{} is deprecated: {}".format(warning["command"], warning["message"]),
+                DeprecationWarning,
+                stacklevel=3,
+            )
+        if reply.startswith("ERROR"):
+            raise HydrogenError(reply)
+        return reply
+"#;
+
+const NODE_HEADER: &str = r#"// Generated from protocol.toml by build.rs, do not edit
+'use strict';
+
+const net = require('net');
+
+class HydrogenError extends Error {}
+
+const DEPRECATED = '\t!DEPRECATED ';
+
+const quote = (value) => `"${value}"`;
+const parseStatus = (reply) => reply === 'OK';
+const parseValue = (reply) => (reply === 'NULL' ? null : reply);
+const parseBoolean = (reply) => reply === '1';
+const parseInteger = (reply) => Number.parseInt(reply, 10);
+const parseKeys = (reply) => (reply === '(empty)' ? [] : reply.split(' '));
+const ESCAPES = { n: '\n', r: '\r', t: '\t', 0: '\0' };
+const unescape = (text) => text.replace(/\\(.)/g, (_, ch) => ESCAPES[ch] ?? ch);
+const parsePairs = (reply) => {
+  const pairs = {};
+  if (reply !== '(empty)') {
+    for (const [, key, value] of reply.matchAll(/("(?:[^"\\]|\\.)*"|\S+) "((?:[^"\\]|\\.)*)"/g)) {
+      pairs[key.startsWith('"') ? unescape(key.slice(1, -1)) : key] = unescape(value);
+    }
+  }
+  return pairs;
+};
+const parseText = (reply) => reply;
+
+class HydrogenClient {
+  constructor(socket) {
+    this.socket = socket;
+    this.buffer = '';
+    this.pending = [];
+    socket.setEncoding('utf8');
+    socket.on('data', (chunk) => this.onData(chunk));
+    socket.on('error', (err) => this.failPending(err));
+    socket.on('close', () => this.failPending(new Error('connection closed by server')));
+  }
+
+  static connect(host = '127.0.0.1', port = 1825) {
+    return new Promise((resolve, reject) => {
+      const socket = net.createConnection({ host, port }, () => {
+        socket.removeListener('error', reject);
+        resolve(new HydrogenClient(socket));
+      });
+      socket.once('error', reject);
+    });
+  }
+
+  close() {
+    this.socket.end();
+  }
+
+  /** Send one request line and resolve with the reply, rejecting on errors */
+  execute(line) {
+    return new Promise((resolve, reject) => {
+      this.pending.push({ resolve, reject });
+      this.socket.write(`${line}\n`);
+    });
+  }
+
+  onData(chunk) {
+    this.buffer += chunk;
+    let index;
+    while ((index = this.buffer.indexOf('\n')) !== -1) {
+      let reply = this.buffer.slice(0, index).replace(/\r$/, '');
+      this.buffer = this.buffer.slice(index + 1);
+      const marker = reply.lastIndexOf(DEPRECATED);
+      if (marker !== -1) {
+        const warning = JSON.parse(reply.slice(marker + DEPRECATED.length));
+        reply = reply.slice(0, marker);
+        process.emitWarning(`${warning.command} is deprecated: ${warning.message}`, 'DeprecationWarning');
+      }
+      const request = this.pending.shift();
+      if (!request) continue;
+      if (reply.startsWith('ERROR')) request.reject(new HydrogenError(reply));
+      else request.resolve(reply);
+    }
+  }
+
+  failPending(err) {
+    for (const request of this.pending.splice(0)) request.reject(err);
+  }
+"#;
+
+const NODE_FOOTER: &str = r#"}
+
+module.exports = { HydrogenClient, HydrogenError };
+"#;
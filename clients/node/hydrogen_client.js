@@ -0,0 +1,496 @@
+// Generated from protocol.toml by build.rs, do not edit
+'use strict';
+
+const net = require('net');
+
+class HydrogenError extends Error {}
+
+const DEPRECATED = '\t!DEPRECATED ';
+
+const quote = (value) => `"${value}"`;
+const parseStatus = (reply) => reply === 'OK';
+const parseValue = (reply) => (reply === 'NULL' ? null : reply);
+const parseBoolean = (reply) => reply === '1';
+const parseInteger = (reply) => Number.parseInt(reply, 10);
+const parseKeys = (reply) => (reply === '(empty)' ? [] : reply.split(' '));
+const ESCAPES = { n: '\n', r: '\r', t: '\t', 0: '\0' };
+const unescape = (text) => text.replace(/\\(.)/g, (_, ch) => ESCAPES[ch] ?? ch);
+const parsePairs = (reply) => {
+  const pairs = {};
+  if (reply !== '(empty)') {
+    for (const [, key, value] of reply.matchAll(/("(?:[^"\\]|\\.)*"|\S+) "((?:[^"\\]|\\.)*)"/g)) {
+      pairs[key.startsWith('"') ? unescape(key.slice(1, -1)) : key] = unescape(value);
+    }
+  }
+  return pairs;
+};
+const parseText = (reply) => reply;
+
+class HydrogenClient {
+  constructor(socket) {
+    this.socket = socket;
+    this.buffer = '';
+    this.pending = [];
+    socket.setEncoding('utf8');
+    socket.on('data', (chunk) => this.onData(chunk));
+    socket.on('error', (err) => this.failPending(err));
+    socket.on('close', () => this.failPending(new Error('connection closed by server')));
+  }
+
+  static connect(host = '127.0.0.1', port = 1825) {
+    return new Promise((resolve, reject) => {
+      const socket = net.createConnection({ host, port }, () => {
+        socket.removeListener('error', reject);
+        resolve(new HydrogenClient(socket));
+      });
+      socket.once('error', reject);
+    });
+  }
+
+  close() {
+    this.socket.end();
+  }
+
+  /** Send one request line and resolve with the reply, rejecting on errors */
+  execute(line) {
+    return new Promise((resolve, reject) => {
+      this.pending.push({ resolve, reject });
+      this.socket.write(`${line}\n`);
+    });
+  }
+
+  onData(chunk) {
+    this.buffer += chunk;
+    let index;
+    while ((index = this.buffer.indexOf('\n')) !== -1) {
+      let reply = this.buffer.slice(0, index).replace(/\r$/, '');
+      this.buffer = this.buffer.slice(index + 1);
+      const marker = reply.lastIndexOf(DEPRECATED);
+      if (marker !== -1) {
+        const warning = JSON.parse(reply.slice(marker + DEPRECATED.length));
+        reply = reply.slice(0, marker);
+        process.emitWarning(`${warning.command} is deprecated: ${warning.message}`, 'DeprecationWarning');
+      }
+      const request = this.pending.shift();
+      if (!request) continue;
+      if (reply.startsWith('ERROR')) request.reject(new HydrogenError(reply));
+      else request.resolve(reply);
+    }
+  }
+
+  failPending(err) {
+    for (const request of this.pending.splice(0)) request.reject(err);
+  }
+
+  /** Check that the server is responding */
+  async ping() {
+    const parts = ['PING'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Set the value of a key */
+  async set(key, value) {
+    const parts = ['SET'];
+    parts.push(String(key));
+    parts.push(quote(value));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Set a key only if it does not exist */
+  async setnx(key, value) {
+    const parts = ['SETNX'];
+    parts.push(String(key));
+    parts.push(quote(value));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Set a key and return its previous value */
+  async getset(key, value) {
+    const parts = ['GETSET'];
+    parts.push(String(key));
+    parts.push(quote(value));
+    return parseValue(await this.execute(parts.join(' ')));
+  }
+
+  /** Set a key only if its value matches */
+  async cas(key, expected, value) {
+    const parts = ['CAS'];
+    parts.push(String(key));
+    parts.push(quote(expected));
+    parts.push(quote(value));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Append to a value and return its new length */
+  async append(key, value) {
+    const parts = ['APPEND'];
+    parts.push(String(key));
+    parts.push(quote(value));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Return the length of a value in bytes */
+  async strlen(key) {
+    const parts = ['STRLEN'];
+    parts.push(String(key));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Return the bytes between two offsets of a value */
+  async getrange(key, start, end) {
+    const parts = ['GETRANGE'];
+    parts.push(String(key));
+    parts.push(String(start));
+    parts.push(String(end));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Get the value of a key */
+  async get(key) {
+    const parts = ['GET'];
+    parts.push(String(key));
+    return parseValue(await this.execute(parts.join(' ')));
+  }
+
+  /** Get several keys at once, from every node in cluster mode */
+  async mget(...keys) {
+    const parts = ['MGET'];
+    parts.push(...keys.map(String));
+    return parsePairs(await this.execute(parts.join(' ')));
+  }
+
+  /** Delete a key */
+  async delete(key) {
+    const parts = ['DEL'];
+    parts.push(String(key));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Delete a key only if its value matches */
+  async deleteIf(key, expected) {
+    const parts = ['DELIF'];
+    parts.push(String(key));
+    parts.push(quote(expected));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Return the version of a key's value, 0 if the key does not exist */
+  async getVersion(key) {
+    const parts = ['GETVERSION'];
+    parts.push(String(key));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Delete a key only if its value is still at the given version */
+  async deleteIfVersion(key, version) {
+    const parts = ['DELIFVERSION'];
+    parts.push(String(key));
+    parts.push(String(version));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Set a key at a Unix timestamp in seconds and return the operation's ID */
+  async setat(timestamp, key, value) {
+    const parts = ['SETAT'];
+    parts.push(String(timestamp));
+    parts.push(String(key));
+    parts.push(quote(value));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Delete a key at a Unix timestamp in seconds and return the operation's ID */
+  async delat(timestamp, key) {
+    const parts = ['DELAT'];
+    parts.push(String(timestamp));
+    parts.push(String(key));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Check whether a key exists */
+  async exists(key) {
+    const parts = ['EXISTS'];
+    parts.push(String(key));
+    return parseBoolean(await this.execute(parts.join(' ')));
+  }
+
+  /** Show the type of a key's value */
+  async type(key) {
+    const parts = ['TYPE'];
+    parts.push(String(key));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Atomically rename a key */
+  async rename(key, newKey) {
+    const parts = ['RENAME'];
+    parts.push(String(key));
+    parts.push(String(newKey));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Count the keys in the selected database */
+  async dbsize() {
+    const parts = ['DBSIZE'];
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Remove every key from every database */
+  async flushall(asynchronous = false) {
+    const parts = ['FLUSHALL'];
+    if (asynchronous) parts.push('ASYNC');
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Remove every key from the selected database */
+  async flushdb(asynchronous = false) {
+    const parts = ['FLUSHDB'];
+    if (asynchronous) parts.push('ASYNC');
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Switch the connection to another database */
+  async select(index) {
+    const parts = ['SELECT'];
+    parts.push(String(index));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** List all keys in the selected database */
+  async keys() {
+    const parts = ['KEYS'];
+    return parseKeys(await this.execute(parts.join(' ')));
+  }
+
+  /** List the keys of a namespace */
+  async nskeys(namespace) {
+    const parts = ['NSKEYS'];
+    parts.push(String(namespace));
+    return parseKeys(await this.execute(parts.join(' ')));
+  }
+
+  /** Remove every key of a namespace */
+  async nsflush(namespace) {
+    const parts = ['NSFLUSH'];
+    parts.push(String(namespace));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Get all key-value pairs under a prefix */
+  async getprefix(prefix) {
+    const parts = ['GETPREFIX'];
+    parts.push(String(prefix));
+    return parsePairs(await this.execute(parts.join(' ')));
+  }
+
+  /** Serialize a key's compressed value */
+  async dump(key) {
+    const parts = ['DUMP'];
+    parts.push(String(key));
+    return parseValue(await this.execute(parts.join(' ')));
+  }
+
+  /** Restore a dumped value */
+  async restore(key, payload, replace = false) {
+    const parts = ['RESTORE'];
+    parts.push(String(key));
+    parts.push(String(payload));
+    if (replace) parts.push('REPLACE');
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Bring back a key that retention moved to the archive file */
+  async archiveRestore(key) {
+    const parts = ['ARCHIVE RESTORE'];
+    parts.push(String(key));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Export the keyspace to a file in the server's snapshot_dir, optionally only the keys matching a pattern */
+  async export(path, pattern) {
+    const parts = ['EXPORT'];
+    parts.push(String(path));
+    if (pattern !== undefined) parts.push('PATTERN', String(pattern));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Import an export file from the server's snapshot_dir, optionally prefixing every key */
+  async importFile(path, prefix) {
+    const parts = ['IMPORT'];
+    parts.push(String(path));
+    if (prefix !== undefined) parts.push('PREFIX', String(prefix));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Report usage per namespace for a period, or write it to a CSV or JSON file next to the server's usage_path */
+  async usageReport(period, path) {
+    const parts = ['USAGE REPORT'];
+    if (period !== undefined) parts.push(String(period));
+    if (path !== undefined) parts.push(String(path));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Show a configuration value */
+  async configGet(param) {
+    const parts = ['CONFIG GET'];
+    parts.push(String(param));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Change a hot-reloadable configuration value */
+  async configSet(param, value) {
+    const parts = ['CONFIG SET'];
+    parts.push(String(param));
+    parts.push(String(value));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Measure set, get and compression throughput on the server */
+  async perfSelftest(iterations) {
+    const parts = ['PERF SELFTEST'];
+    if (iterations !== undefined) parts.push(String(iterations));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Show the most recent slow commands */
+  async slowlogGet(count) {
+    const parts = ['SLOWLOG GET'];
+    if (count !== undefined) parts.push(String(count));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Count the entries in the slow log */
+  async slowlogLen() {
+    const parts = ['SLOWLOG LEN'];
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Clear the slow log */
+  async slowlogReset() {
+    const parts = ['SLOWLOG RESET'];
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Show one section of server statistics: compression, types, memory, tasks or deprecated */
+  async info(section) {
+    const parts = ['INFO'];
+    if (section !== undefined) parts.push(String(section));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Show the syntax of a command, or list every command */
+  async help(command) {
+    const parts = ['HELP'];
+    if (command !== undefined) parts.push(String(command));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Show connected clients */
+  async clientList() {
+    const parts = ['CLIENT LIST'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Disconnect the client with this address */
+  async clientKill(addr) {
+    const parts = ['CLIENT KILL'];
+    parts.push(String(addr));
+    return parseStatus(await this.execute(parts.join(' ')));
+  }
+
+  /** Show the node ID of the server */
+  async clusterMyid() {
+    const parts = ['CLUSTER MYID'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Add a node to the cluster */
+  async clusterMeet(address) {
+    const parts = ['CLUSTER MEET'];
+    parts.push(String(address));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Show which node owns each slot range */
+  async clusterSlots() {
+    const parts = ['CLUSTER SLOTS'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Move a slot range between nodes */
+  async clusterReshard(fromNode, toNode, slots) {
+    const parts = ['CLUSTER RESHARD'];
+    parts.push(String(fromNode));
+    parts.push(String(toNode));
+    parts.push(String(slots));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Count keys and bytes in a slot range */
+  async clusterSlotstats(slots) {
+    const parts = ['CLUSTER SLOTSTATS'];
+    parts.push(String(slots));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Dump several keys of this node only, as MGET asks its peers */
+  async clusterMdump(...keys) {
+    const parts = ['CLUSTER MDUMP'];
+    parts.push(...keys.map(String));
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Export the keys of a slot range to a file in the server's snapshot_dir */
+  async clusterBackup(slots, path) {
+    const parts = ['CLUSTER BACKUP'];
+    parts.push(String(slots));
+    parts.push(String(path));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Load the keys of a slot range from an export file in the server's snapshot_dir */
+  async clusterRestore(slots, path) {
+    const parts = ['CLUSTER RESTORE'];
+    parts.push(String(slots));
+    parts.push(String(path));
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Plan slot moves that even out the cluster */
+  async clusterPlanRebalance() {
+    const parts = ['CLUSTER PLAN REBALANCE'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Apply the pending rebalance plan */
+  async clusterApplyPlan() {
+    const parts = ['CLUSTER APPLY PLAN'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Run NSFLUSH, FLUSHALL, FLUSHDB or CONFIG SET on every cluster node, replying with each node's id and reply */
+  async everywhere(...command) {
+    const parts = ['EVERYWHERE'];
+    parts.push(...command.map(String));
+    return parsePairs(await this.execute(parts.join(' ')));
+  }
+
+  /** List every command with its arity and flags */
+  async command() {
+    const parts = ['COMMAND'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+
+  /** Count the commands the server supports */
+  async commandCount() {
+    const parts = ['COMMAND COUNT'];
+    return parseInteger(await this.execute(parts.join(' ')));
+  }
+
+  /** Show calls, errors and time spent per command */
+  async commandStats() {
+    const parts = ['COMMAND STATS'];
+    return parseText(await this.execute(parts.join(' ')));
+  }
+}
+
+module.exports = { HydrogenClient, HydrogenError };
@@ -0,0 +1,458 @@
+# Generated from protocol.toml by build.rs, do not edit
+import json
+import re
+import socket
+import warnings
+
+_PAIR = re.compile(r'("(?:[^"\\]|\\.)*"|\S+) "((?:[^"\\]|\\.)*)"')
+_ESCAPE = re.compile(r'\\(.)')
+_ESCAPES = {"n": "\n", "r": "\r", "t": "\t", "0": "\0"}
+_DEPRECATED = "\t!DEPRECATED "
+
+
+class HydrogenError(Exception):
+    pass
+
+
+def _quote(value):
+    return '"' + str(value) + '"'
+
+
+def _parse_status(reply):
+    return reply == "OK"
+
+
+def _parse_value(reply):
+    return None if reply == "NULL" else reply
+
+
+def _parse_boolean(reply):
+    return reply == "1"
+
+
+def _parse_integer(reply):
+    return int(reply)
+
+
+def _parse_keys(reply):
+    return [] if reply == "(empty)" else reply.split(" ")
+
+
+def _unescape(text):
+    return _ESCAPE.sub(lambda match: _ESCAPES.get(match.group(1), match.group(1)), text)
+
+
+def _parse_pairs(reply):
+    if reply == "(empty)":
+        return {}
+    pairs = {}
+    for key, value in _PAIR.findall(reply):
+        if key.startswith('"'):
+            key = _unescape(key[1:-1])
+        pairs[key] = _unescape(value)
+    return pairs
+
+
+def _parse_text(reply):
+    return reply
+
+
+class HydrogenClient:
+    def __init__(self, host="127.0.0.1", port=1825, timeout=None):
+        self._sock = socket.create_connection((host, port), timeout=timeout)
+        self._reader = self._sock.makefile("r", encoding="utf-8", newline="\n")
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def close(self):
+        self._reader.close()
+        self._sock.close()
+
+    def execute(self, line):
+        """Send one request line and return the reply, raising on errors"""
+        self._sock.sendall((line + "\n").encode("utf-8"))
+        reply = self._reader.readline()
+        if not reply:
+            raise ConnectionError("connection closed by server")
+        reply = reply.rstrip("\r\n")
+        if _DEPRECATED in reply:
+            reply, warning = reply.rsplit(_DEPRECATED, 1)
+            warning = json.loads(warning)
+            warnings.warn(
+                "{} is deprecated: {}".format(warning["command"], warning["message"]),
+                DeprecationWarning,
+                stacklevel=3,
+            )
+        if reply.startswith("ERROR"):
+            raise HydrogenError(reply)
+        return reply
+
+    def ping(self):
+        """Check that the server is responding"""
+        parts = ["PING"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def set(self, key, value):
+        """Set the value of a key"""
+        parts = ["SET"]
+        parts.append(str(key))
+        parts.append(_quote(value))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def setnx(self, key, value):
+        """Set a key only if it does not exist"""
+        parts = ["SETNX"]
+        parts.append(str(key))
+        parts.append(_quote(value))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def getset(self, key, value):
+        """Set a key and return its previous value"""
+        parts = ["GETSET"]
+        parts.append(str(key))
+        parts.append(_quote(value))
+        return _parse_value(self.execute(" ".join(parts)))
+
+    def cas(self, key, expected, value):
+        """Set a key only if its value matches"""
+        parts = ["CAS"]
+        parts.append(str(key))
+        parts.append(_quote(expected))
+        parts.append(_quote(value))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def append(self, key, value):
+        """Append to a value and return its new length"""
+        parts = ["APPEND"]
+        parts.append(str(key))
+        parts.append(_quote(value))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def strlen(self, key):
+        """Return the length of a value in bytes"""
+        parts = ["STRLEN"]
+        parts.append(str(key))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def getrange(self, key, start, end):
+        """Return the bytes between two offsets of a value"""
+        parts = ["GETRANGE"]
+        parts.append(str(key))
+        parts.append(str(start))
+        parts.append(str(end))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def get(self, key):
+        """Get the value of a key"""
+        parts = ["GET"]
+        parts.append(str(key))
+        return _parse_value(self.execute(" ".join(parts)))
+
+    def mget(self, *keys):
+        """Get several keys at once, from every node in cluster mode"""
+        parts = ["MGET"]
+        parts.extend(str(item) for item in keys)
+        return _parse_pairs(self.execute(" ".join(parts)))
+
+    def delete(self, key):
+        """Delete a key"""
+        parts = ["DEL"]
+        parts.append(str(key))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def delete_if(self, key, expected):
+        """Delete a key only if its value matches"""
+        parts = ["DELIF"]
+        parts.append(str(key))
+        parts.append(_quote(expected))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def get_version(self, key):
+        """Return the version of a key's value, 0 if the key does not exist"""
+        parts = ["GETVERSION"]
+        parts.append(str(key))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def delete_if_version(self, key, version):
+        """Delete a key only if its value is still at the given version"""
+        parts = ["DELIFVERSION"]
+        parts.append(str(key))
+        parts.append(str(version))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def setat(self, timestamp, key, value):
+        """Set a key at a Unix timestamp in seconds and return the operation's ID"""
+        parts = ["SETAT"]
+        parts.append(str(timestamp))
+        parts.append(str(key))
+        parts.append(_quote(value))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def delat(self, timestamp, key):
+        """Delete a key at a Unix timestamp in seconds and return the operation's ID"""
+        parts = ["DELAT"]
+        parts.append(str(timestamp))
+        parts.append(str(key))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def exists(self, key):
+        """Check whether a key exists"""
+        parts = ["EXISTS"]
+        parts.append(str(key))
+        return _parse_boolean(self.execute(" ".join(parts)))
+
+    def type(self, key):
+        """Show the type of a key's value"""
+        parts = ["TYPE"]
+        parts.append(str(key))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def rename(self, key, new_key):
+        """Atomically rename a key"""
+        parts = ["RENAME"]
+        parts.append(str(key))
+        parts.append(str(new_key))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def dbsize(self):
+        """Count the keys in the selected database"""
+        parts = ["DBSIZE"]
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def flushall(self, asynchronous=False):
+        """Remove every key from every database"""
+        parts = ["FLUSHALL"]
+        if asynchronous:
+            parts.append("ASYNC")
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def flushdb(self, asynchronous=False):
+        """Remove every key from the selected database"""
+        parts = ["FLUSHDB"]
+        if asynchronous:
+            parts.append("ASYNC")
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def select(self, index):
+        """Switch the connection to another database"""
+        parts = ["SELECT"]
+        parts.append(str(index))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def keys(self):
+        """List all keys in the selected database"""
+        parts = ["KEYS"]
+        return _parse_keys(self.execute(" ".join(parts)))
+
+    def nskeys(self, namespace):
+        """List the keys of a namespace"""
+        parts = ["NSKEYS"]
+        parts.append(str(namespace))
+        return _parse_keys(self.execute(" ".join(parts)))
+
+    def nsflush(self, namespace):
+        """Remove every key of a namespace"""
+        parts = ["NSFLUSH"]
+        parts.append(str(namespace))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def getprefix(self, prefix):
+        """Get all key-value pairs under a prefix"""
+        parts = ["GETPREFIX"]
+        parts.append(str(prefix))
+        return _parse_pairs(self.execute(" ".join(parts)))
+
+    def dump(self, key):
+        """Serialize a key's compressed value"""
+        parts = ["DUMP"]
+        parts.append(str(key))
+        return _parse_value(self.execute(" ".join(parts)))
+
+    def restore(self, key, payload, replace=False):
+        """Restore a dumped value"""
+        parts = ["RESTORE"]
+        parts.append(str(key))
+        parts.append(str(payload))
+        if replace:
+            parts.append("REPLACE")
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def archive_restore(self, key):
+        """Bring back a key that retention moved to the archive file"""
+        parts = ["ARCHIVE RESTORE"]
+        parts.append(str(key))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def export(self, path, pattern=None):
+        """Export the keyspace to a file in the server's snapshot_dir, optionally only the keys matching a pattern"""
+        parts = ["EXPORT"]
+        parts.append(str(path))
+        if pattern is not None:
+            parts.append("PATTERN")
+            parts.append(str(pattern))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def import_file(self, path, prefix=None):
+        """Import an export file from the server's snapshot_dir, optionally prefixing every key"""
+        parts = ["IMPORT"]
+        parts.append(str(path))
+        if prefix is not None:
+            parts.append("PREFIX")
+            parts.append(str(prefix))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def usage_report(self, period=None, path=None):
+        """Report usage per namespace for a period, or write it to a CSV or JSON file next to the server's usage_path"""
+        parts = ["USAGE REPORT"]
+        if period is not None:
+            parts.append(str(period))
+        if path is not None:
+            parts.append(str(path))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def config_get(self, param):
+        """Show a configuration value"""
+        parts = ["CONFIG GET"]
+        parts.append(str(param))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def config_set(self, param, value):
+        """Change a hot-reloadable configuration value"""
+        parts = ["CONFIG SET"]
+        parts.append(str(param))
+        parts.append(str(value))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def perf_selftest(self, iterations=None):
+        """Measure set, get and compression throughput on the server"""
+        parts = ["PERF SELFTEST"]
+        if iterations is not None:
+            parts.append(str(iterations))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def slowlog_get(self, count=None):
+        """Show the most recent slow commands"""
+        parts = ["SLOWLOG GET"]
+        if count is not None:
+            parts.append(str(count))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def slowlog_len(self):
+        """Count the entries in the slow log"""
+        parts = ["SLOWLOG LEN"]
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def slowlog_reset(self):
+        """Clear the slow log"""
+        parts = ["SLOWLOG RESET"]
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def info(self, section=None):
+        """Show one section of server statistics: compression, types, memory, tasks or deprecated"""
+        parts = ["INFO"]
+        if section is not None:
+            parts.append(str(section))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def help(self, command=None):
+        """Show the syntax of a command, or list every command"""
+        parts = ["HELP"]
+        if command is not None:
+            parts.append(str(command))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def client_list(self):
+        """Show connected clients"""
+        parts = ["CLIENT LIST"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def client_kill(self, addr):
+        """Disconnect the client with this address"""
+        parts = ["CLIENT KILL"]
+        parts.append(str(addr))
+        return _parse_status(self.execute(" ".join(parts)))
+
+    def cluster_myid(self):
+        """Show the node ID of the server"""
+        parts = ["CLUSTER MYID"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_meet(self, address):
+        """Add a node to the cluster"""
+        parts = ["CLUSTER MEET"]
+        parts.append(str(address))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_slots(self):
+        """Show which node owns each slot range"""
+        parts = ["CLUSTER SLOTS"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_reshard(self, from_node, to_node, slots):
+        """Move a slot range between nodes"""
+        parts = ["CLUSTER RESHARD"]
+        parts.append(str(from_node))
+        parts.append(str(to_node))
+        parts.append(str(slots))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def cluster_slotstats(self, slots):
+        """Count keys and bytes in a slot range"""
+        parts = ["CLUSTER SLOTSTATS"]
+        parts.append(str(slots))
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_mdump(self, *keys):
+        """Dump several keys of this node only, as MGET asks its peers"""
+        parts = ["CLUSTER MDUMP"]
+        parts.extend(str(item) for item in keys)
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_backup(self, slots, path):
+        """Export the keys of a slot range to a file in the server's snapshot_dir"""
+        parts = ["CLUSTER BACKUP"]
+        parts.append(str(slots))
+        parts.append(str(path))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def cluster_restore(self, slots, path):
+        """Load the keys of a slot range from an export file in the server's snapshot_dir"""
+        parts = ["CLUSTER RESTORE"]
+        parts.append(str(slots))
+        parts.append(str(path))
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def cluster_plan_rebalance(self):
+        """Plan slot moves that even out the cluster"""
+        parts = ["CLUSTER PLAN REBALANCE"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def cluster_apply_plan(self):
+        """Apply the pending rebalance plan"""
+        parts = ["CLUSTER APPLY PLAN"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def everywhere(self, *command):
+        """Run NSFLUSH, FLUSHALL, FLUSHDB or CONFIG SET on every cluster node, replying with each node's id and reply"""
+        parts = ["EVERYWHERE"]
+        parts.extend(str(item) for item in command)
+        return _parse_pairs(self.execute(" ".join(parts)))
+
+    def command(self):
+        """List every command with its arity and flags"""
+        parts = ["COMMAND"]
+        return _parse_text(self.execute(" ".join(parts)))
+
+    def command_count(self):
+        """Count the commands the server supports"""
+        parts = ["COMMAND COUNT"]
+        return _parse_integer(self.execute(" ".join(parts)))
+
+    def command_stats(self):
+        """Show calls, errors and time spent per command"""
+        parts = ["COMMAND STATS"]
+        return _parse_text(self.execute(" ".join(parts)))
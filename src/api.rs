@@ -1,13 +1,31 @@
 // Copyright (c) 2025, TheByteSlayer, Hydrogen
 // A scalable and lightweight Key Value Cache written in Rust
 
-use crate::cache::{CacheError, Hydrogen};
-use crate::api_log::{log_set_endpoint, log_get_endpoint, log_delete_endpoint, log_keys_endpoint, log_invalid_endpoint};
+use crate::archive::Archive;
+use crate::cache::{namespace_of, CacheError, Databases, Hydrogen};
+use crate::api_log::{log_command_endpoint, log_invalid_endpoint};
+use crate::cluster::Cluster;
+use crate::command_spec::{self, CommandSpec};
+use crate::commands;
+use crate::api_log::{AuditLog, TrafficCapture};
+use crate::configuration::{KeyPolicy, SharedConfig};
+use crate::introspection::{ClientRegistry, CommandStats, DeprecationStats, SlowLog};
+use crate::scheduler::Scheduler;
+use crate::spool::{self, Request, SpoolFile, SpooledRequest};
+use crate::supervisor::Supervisor;
+use crate::usage::UsageMeter;
+use std::borrow::Cow;
+use std::fs;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::AsyncWriteExt;
-use tracing::error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tracing::{error, info};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ApiError {
@@ -21,282 +39,502 @@ pub enum ApiError {
     Utf8Error(#[from] std::string::FromUtf8Error),
 }
 
-type ApiResult<T> = Result<T, ApiError>;
+pub type ApiResult<T> = Result<T, ApiError>;
 
-#[derive(Debug, Clone)]
-pub enum Command {
-    Set { key: String, value: String },
-    Get { key: String },
-    Delete { key: String },
-    Keys,
+type Handler = Box<dyn FnOnce(Context) -> Pin<Box<dyn Future<Output = Reply> + Send>> + Send>;
+
+/// A parsed request, ready to run. The registry in commands.rs parses each
+/// command into its handler, along with the key and value size that
+/// logging, usage metering, the slow log and the audit log record.
+pub struct Command {
+    spec: &'static CommandSpec,
+    key: Option<String>,
+    tenant: Option<String>,
+    value_size: Option<usize>,
+    /// Anything else the request log shows, such as GETRANGE's offsets
+    detail: Option<String>,
+    handler: Handler,
 }
 
-impl Command {
-    pub fn parse(input: &str) -> ApiResult<Self> {
-        let input = input.trim();
-        if input.is_empty() {
-            return Err(ApiError::InvalidCommand("Empty command".to_string()));
-        }
+/// A command being parsed, until `run` or `reply` gives it its handler.
+pub struct CommandBuilder {
+    spec: &'static CommandSpec,
+    key: Option<String>,
+    tenant: Option<String>,
+    value_size: Option<usize>,
+    detail: Option<String>,
+}
 
-        let (command, rest) = match input.find(' ') {
-            Some(pos) => (&input[..pos], input[pos+1..].trim()),
-            None => (input, ""),
-        };
+impl CommandBuilder {
+    pub fn key(self, key: &str) -> Self {
+        Self { key: Some(key.to_string()), ..self }
+    }
 
-        match command.to_uppercase().as_str() {
-            "SET" => {
-                let (key, value) = Self::parse_set_args(rest)?;
-                Self::validate_key(&key)?;
-                Ok(Command::Set { key, value })
-            }
-            "GET" => {
-                if rest.is_empty() {
-                    return Err(ApiError::InvalidCommand(
-                        "GET command requires exactly one key".to_string(),
-                    ));
-                }
-                let key = rest.to_string();
-                Self::validate_key(&key)?;
-                Ok(Command::Get { key })
-            }
-            "DEL" | "DELETE" => {
-                if rest.is_empty() {
-                    return Err(ApiError::InvalidCommand(
-                        "DEL command requires exactly one key".to_string(),
-                    ));
-                }
-                let key = rest.to_string();
-                Self::validate_key(&key)?;
-                Ok(Command::Delete { key })
-            }
-            "KEYS" => {
-                if !rest.is_empty() {
-                    return Err(ApiError::InvalidCommand(
-                        "KEYS command takes no arguments".to_string(),
-                    ));
-                }
-                Ok(Command::Keys)
-            }
-            cmd => Err(ApiError::InvalidCommand(format!(
-                "Unknown command: {}. Supported commands: SET, GET, DEL, KEYS",
-                cmd
-            ))),
-        }
+    /// The namespace usage is metered against, when it is not the key's own
+    pub fn tenant(self, tenant: &str) -> Self {
+        Self { tenant: Some(tenant.to_string()), ..self }
     }
 
-    fn parse_set_args(args: &str) -> ApiResult<(String, String)> {
-        if args.is_empty() {
-            return Err(ApiError::InvalidCommand(
-                "SET command requires key and value".to_string(),
-            ));
-        }
+    pub fn value_size(self, value_size: usize) -> Self {
+        Self { value_size: Some(value_size), ..self }
+    }
 
-        let (key, rest) = match args.find(' ') {
-            Some(pos) => (&args[..pos], args[pos+1..].trim()),
-            None => return Err(ApiError::InvalidCommand(
-                "SET command requires key and value".to_string(),
-            )),
-        };
+    pub fn detail(self, detail: impl ToString) -> Self {
+        Self { detail: Some(detail.to_string()), ..self }
+    }
 
-        if rest.is_empty() {
-            return Err(ApiError::InvalidCommand(
-                "SET command requires key and value".to_string(),
-            ));
+    pub fn run<F, Fut>(self, handler: F) -> Command
+    where
+        F: FnOnce(Context) -> Fut + Send + 'static,
+        Fut: Future<Output = String> + Send + 'static,
+    {
+        self.reply(move |ctx| async move { Reply::Text(handler(ctx).await) })
+    }
+
+    /// Like `run`, for handlers that reply with more than a line of text
+    pub fn reply<F, Fut>(self, handler: F) -> Command
+    where
+        F: FnOnce(Context) -> Fut + Send + 'static,
+        Fut: Future<Output = Reply> + Send + 'static,
+    {
+        Command {
+            spec: self.spec,
+            key: self.key,
+            tenant: self.tenant,
+            value_size: self.value_size,
+            detail: self.detail,
+            handler: Box::new(move |ctx| Box::pin(handler(ctx))),
         }
+    }
+}
 
-        let value = if rest.starts_with('"') && rest.ends_with('"') && rest.len() >= 2 {
-            rest[1..rest.len()-1].to_string()
-        } else {
-            rest.split_whitespace().collect::<Vec<&str>>().join(" ")
-        };
+impl Command {
+    pub fn build(spec: &'static CommandSpec) -> CommandBuilder {
+        CommandBuilder { spec, key: None, tenant: None, value_size: None, detail: None }
+    }
 
-        Ok((key.to_string(), value))
+    pub fn name(&self) -> &'static str {
+        self.spec.name
     }
 
-    fn validate_key(key: &str) -> ApiResult<()> {
-        if key.is_empty() {
-            return Err(ApiError::InvalidCommand("Key cannot be empty".to_string()));
-        }
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
 
-        // Check for spaces
-        if key.contains(' ') {
-            return Err(ApiError::InvalidCommand("Key cannot contain spaces".to_string()));
-        }
+    /// The namespace a command is metered against for usage reports.
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref().or_else(|| self.key().and_then(namespace_of))
+    }
 
-        // Check each character
-        for ch in key.chars() {
-            if !ch.is_ascii_alphanumeric() && ch != '-' && ch != '_' {
-                return Err(ApiError::InvalidCommand(format!(
-                    "Key contains invalid character '{}'. Keys can only contain letters, numbers, hyphens, and underscores",
-                    ch
-                )));
-            }
-        }
+    pub fn value_size(&self) -> Option<usize> {
+        self.value_size
+    }
 
-        // Check that - and _ are not at the beginning, end, or alone
-        let chars: Vec<char> = key.chars().collect();
-        for (i, &ch) in chars.iter().enumerate() {
-            if ch == '-' || ch == '_' {
-                // Cannot be at the beginning or end
-                if i == 0 || i == chars.len() - 1 {
-                    return Err(ApiError::InvalidCommand(format!(
-                        "Key cannot start or end with '{}'. Hyphens and underscores must be between letters or numbers",
-                        ch
-                    )));
-                }
-                
-                // Must be between alphanumeric characters
-                let prev = chars[i - 1];
-                let next = chars[i + 1];
-                if !prev.is_ascii_alphanumeric() || !next.is_ascii_alphanumeric() {
-                    return Err(ApiError::InvalidCommand(format!(
-                        "Invalid key format. '{}' must be between letters or numbers",
-                        ch
-                    )));
-                }
-            }
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    // Commands that only read may be stopped once a client's deadline passes
+    fn is_read_only(&self) -> bool {
+        self.spec.flags.contains(&"read") && !self.spec.flags.contains(&"write")
+    }
+
+    pub fn run(self, ctx: Context) -> Pin<Box<dyn Future<Output = Reply> + Send>> {
+        (self.handler)(ctx)
+    }
+
+    pub fn parse(input: &str, policy: KeyPolicy) -> ApiResult<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(ApiError::InvalidCommand("Empty command".to_string()));
         }
 
-        // Check for consecutive - or _
-        for i in 0..chars.len() - 1 {
-            if (chars[i] == '-' || chars[i] == '_') && (chars[i + 1] == '-' || chars[i + 1] == '_') {
-                return Err(ApiError::InvalidCommand(
-                    "Key cannot have consecutive hyphens or underscores".to_string()
-                ));
+        let Some((registration, spec, args)) = commands::lookup(input) else {
+            let command = input.split_whitespace().next().unwrap_or_default().to_uppercase();
+            if command_spec::group(&command).next().is_some() {
+                return Err(commands::wrong_subcommand(&command));
             }
-        }
+            return Err(ApiError::InvalidCommand(format!(
+                "Unknown command: {}. Supported commands: {}", command, commands::command_words().join(", ")
+            )));
+        };
+        registration.parse(spec, args, policy)
+    }
 
-        Ok(())
+    /// Parses a request that was spooled to disk. Only commands registered
+    /// with a spooled parser, which keep their value in the spool file, may
+    /// be that large.
+    pub fn parse_spooled(request: SpooledRequest, policy: KeyPolicy) -> ApiResult<Self> {
+        let refused = || ApiError::InvalidCommand(format!(
+            "Only {} requests may be longer than spool_threshold_bytes", commands::spooled_names().join(", ")
+        ));
+        let Some((registration, spec, _)) = commands::lookup(request.header.trim_start()) else {
+            return Err(refused());
+        };
+        registration.parse_spooled(spec, request, policy).ok_or_else(refused)?
     }
 }
 
+/// What a command runs against: the state every connection shares and the
+/// connection's selected database.
+#[derive(Clone)]
+pub struct Context {
+    pub state: Arc<ServerState>,
+    pub db: usize,
+    pub cache: Arc<Hydrogen>,
+}
+
+/// The reply when a request's `*TO=` deadline passes first
+const TIMEOUT_REPLY: &str = "ERROR: TIMEOUT";
+
+/// State every connection shares
+pub struct ServerState {
+    pub databases: Arc<Databases>,
+    pub config: Arc<SharedConfig>,
+    pub cluster: Option<Arc<Cluster>>,
+    pub audit_log: Option<Arc<AuditLog>>,
+    pub capture: Arc<TrafficCapture>,
+    pub clients: Arc<ClientRegistry>,
+    pub slow_log: SlowLog,
+    pub command_stats: CommandStats,
+    pub deprecations: DeprecationStats,
+    pub supervisor: Arc<Supervisor>,
+    pub scheduler: Arc<Scheduler>,
+    pub archive: Arc<Archive>,
+    pub usage: Arc<UsageMeter>,
+}
+
 pub struct TcpApiServer {
-    cache: Arc<Hydrogen>,
+    state: Arc<ServerState>,
     listener: TcpListener,
+    unix_listener: Option<(UnixListener, String)>,
+    unix_connections: AtomicU64,
 }
 
 impl TcpApiServer {
-    pub async fn new(bind_addr: &str, cache: Arc<Hydrogen>) -> ApiResult<Self> {
+    pub async fn new(
+        bind_addr: &str,
+        databases: Arc<Databases>,
+        config: Arc<SharedConfig>,
+        cluster: Option<Arc<Cluster>>,
+        audit_log: Option<Arc<AuditLog>>,
+        capture: Arc<TrafficCapture>,
+        supervisor: Arc<Supervisor>,
+    ) -> ApiResult<Self> {
+        commands::check_registry().map_err(ApiError::InvalidCommand)?;
         let listener = TcpListener::bind(bind_addr).await?;
-        Ok(Self { cache, listener })
+        let current = config.current();
+        let unix_listener = if current.unix_socket_path.is_empty() {
+            None
+        } else {
+            // The mode was checked when the config was loaded
+            let mode = current.unix_socket_mode().unwrap_or(0o700);
+            let path = current.unix_socket_path.clone();
+            Some((Self::bind_unix(&path, mode)?, path))
+        };
+        let scheduler = Arc::new(Scheduler::default());
+        let scheduler_task = Arc::clone(&scheduler);
+        let scheduler_databases = Arc::clone(&databases);
+        supervisor.spawn("scheduler", move |task| {
+            Arc::clone(&scheduler_task).run(Arc::clone(&scheduler_databases), task)
+        });
+        let archive = Arc::new(Archive::open(&current.archive_path).await?);
+        let archive_task = Arc::clone(&archive);
+        let archive_databases = Arc::clone(&databases);
+        let archive_config = Arc::clone(&config);
+        supervisor.spawn("retention", move |task| {
+            Arc::clone(&archive_task).run(Arc::clone(&archive_databases), Arc::clone(&archive_config), task)
+        });
+        let usage = Arc::new(UsageMeter::open(&current.usage_path).await?);
+        let usage_task = Arc::clone(&usage);
+        let usage_databases = Arc::clone(&databases);
+        let usage_config = Arc::clone(&config);
+        supervisor.spawn("usage", move |task| {
+            Arc::clone(&usage_task).run(Arc::clone(&usage_databases), Arc::clone(&usage_config), task)
+        });
+        let state = Arc::new(ServerState {
+            databases,
+            config,
+            cluster,
+            audit_log,
+            capture,
+            clients: Arc::new(ClientRegistry::default()),
+            slow_log: SlowLog::default(),
+            command_stats: CommandStats::default(),
+            deprecations: DeprecationStats::default(),
+            supervisor,
+            scheduler,
+            archive,
+            usage,
+        });
+        Ok(Self {
+            state,
+            listener,
+            unix_listener,
+            unix_connections: AtomicU64::new(0),
+        })
+    }
+
+    // A socket file left behind by an unclean shutdown is replaced, but any
+    // other kind of file at the path is an error
+    fn bind_unix(path: &str, mode: u32) -> ApiResult<UnixListener> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(ApiError::NetworkError(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path),
+                )));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        info!("Hydrogen listening on unix socket {} (mode {:o})", path, mode);
+        Ok(listener)
     }
 
     pub async fn run(&self) -> ApiResult<()> {
         loop {
-            match self.listener.accept().await {
-                Ok((stream, client_addr)) => {
-                    let cache = Arc::clone(&self.cache);
-                    tokio::spawn(async move {
-                        if let Err(e) = Self::handle_client(stream, cache, client_addr).await {
-                            error!("Error handling client {}: {}", client_addr, e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    error!("Error accepting TCP connection: {}", e);
-                }
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, client_addr)) => self.spawn_client(stream, client_addr.to_string()),
+                    Err(e) => error!("Error accepting TCP connection: {}", e),
+                },
+                accepted = Self::accept_unix(&self.unix_listener) => match accepted {
+                    Ok(stream) => {
+                        let id = self.unix_connections.fetch_add(1, Ordering::Relaxed) + 1;
+                        self.spawn_client(stream, format!("unix#{}", id));
+                    }
+                    Err(e) => error!("Error accepting unix socket connection: {}", e),
+                },
             }
         }
     }
 
-    async fn handle_client(stream: TcpStream, cache: Arc<Hydrogen>, client_addr: SocketAddr) -> ApiResult<()> {
-        use tokio::io::{AsyncBufReadExt, BufReader};
+    // Never resolves when no unix socket is configured
+    async fn accept_unix(listener: &Option<(UnixListener, String)>) -> std::io::Result<tokio::net::UnixStream> {
+        match listener {
+            Some((listener, _)) => listener.accept().await.map(|(stream, _)| stream),
+            None => std::future::pending().await,
+        }
+    }
+
+    fn spawn_client<S>(&self, stream: S, client_addr: String)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let state = Arc::clone(&self.state);
+        tokio::spawn(async move {
+            if let Err(e) = Self::handle_client(stream, state, &client_addr).await {
+                error!("Error handling client {}: {}", client_addr, e);
+            }
+        });
+    }
+
+    async fn handle_client<S>(
+        stream: S,
+        state: Arc<ServerState>,
+        client_addr: &str,
+    ) -> ApiResult<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        use tokio::io::BufReader;
         
-        let (reader, mut writer) = stream.into_split();
+        let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
-        let mut line = String::new();
+        let mut selected_db = 0;
+        let client = state.clients.register(client_addr);
         
         loop {
-            line.clear();
-            match reader.read_line(&mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
+            let current = state.config.current();
+            let idle_timeout = Duration::from_secs(current.idle_timeout_secs);
+            let max_line_length = current.max_line_length as u64;
+
+            let read = spool::read_request(&mut reader, max_line_length, current.spool_threshold_bytes, &current.spool_dir);
+            let read = async {
+                if idle_timeout.is_zero() {
+                    Some(read.await)
+                } else {
+                    tokio::time::timeout(idle_timeout, read).await.ok()
+                }
+            };
+            let read_result = tokio::select! {
+                result = read => match result {
+                    Some(result) => result,
+                    None => {
+                        info!("Closing idle connection {}", client_addr);
+                        break;
+                    }
+                },
+                _ = client.killed() => {
+                    info!("Closing connection {} on CLIENT KILL", client_addr);
+                    break;
+                }
+            };
+
+            // key_validation is checked when the config is loaded
+            let policy = current.key_policy().unwrap_or(KeyPolicy::Strict);
+            let parsed = match read_result {
+                Ok(Request::Eof) => break,
+                Ok(Request::TooLong) => {
+                    let _ = writer.write_all(b"ERROR: Request line too long\n").await;
+                    break;
+                }
+                Ok(Request::Line(line)) => {
                     let request_str = line.trim();
                     if request_str.is_empty() {
                         continue;
                     }
-                    
-                    let response = match Command::parse(request_str) {
-                        Ok(command) => {
-                            match &command {
-                                Command::Set { key, value } => {
-                                    log_set_endpoint(key, value);
-                                }
-                                Command::Get { key } => {
-                                    log_get_endpoint(key);
-                                }
-                                Command::Delete { key } => {
-                                    log_delete_endpoint(key);
-                                }
-                                Command::Keys => {
-                                    log_keys_endpoint();
-                                }
-                            }
-                            Self::execute_command(command, &cache).await
-                        }
-                        Err(_) => {
-                            log_invalid_endpoint(request_str);
-                            format!("ERROR: Invalid endpoint format")
-                        }
-                    };
-                    
-                    let response_with_newline = format!("{}\n", response);
-                    if let Err(e) = writer.write_all(response_with_newline.as_bytes()).await {
-                        error!("Failed to send response to {}: {}", client_addr, e);
-                        break;
+                    state.capture.record(client_addr, request_str);
+                    let request_bytes = line.len() as u64;
+                    match split_deadline(request_str) {
+                        Ok((deadline, request_str)) => Command::parse(request_str, policy)
+                            .map(|command| (command, deadline, request_bytes))
+                            .map_err(|e| (request_str.to_string(), usage_for(request_str), e)),
+                        Err(e) => Err((request_str.to_string(), None, e)),
                     }
                 }
+                // Spooled requests are too large to capture for replay
+                Ok(Request::Spooled(spooled)) => {
+                    let summary: String = spooled.header.chars().take(64).collect();
+                    let summary = format!("{}... ({} bytes)", summary.trim(), spooled.content_end);
+                    let usage = usage_for(&spooled.header);
+                    let request_bytes = spooled.content_end;
+                    Command::parse_spooled(spooled, policy)
+                        .map(|command| (command, None, request_bytes))
+                        .map_err(|e| (summary, usage, e))
+                }
                 Err(e) => {
                     error!("Error reading from TCP stream {}: {}", client_addr, e);
                     break;
                 }
+            };
+
+            // The tenant and request size, when usage metering is on
+            let mut metered = None;
+            let mut warning = None;
+            let response = match parsed {
+                Ok((command, deadline, request_bytes)) => {
+                    let name = command.name();
+                    if current.usage_enabled {
+                        metered = Some((command.tenant().map(str::to_string), request_bytes));
+                    }
+                    let key = command.key().map(str::to_string);
+                    let value_size = command.value_size();
+
+                    log_command_endpoint(name, key.as_deref(), command.detail(), value_size);
+                    client.record_command(name, selected_db);
+                    let started = Instant::now();
+                    let response = Self::execute_with_timeout(command, &state, selected_db, deadline).await;
+                    if let Reply::Selected(index) = response {
+                        selected_db = index;
+                    }
+                    let elapsed = started.elapsed();
+                    state.command_stats.record(name, elapsed, response.is_error());
+                    if let Some(message) = state.deprecations.record(name, client_addr)
+                        && current.deprecation_warnings
+                    {
+                        let details = serde_json::json!({ "command": name, "message": message });
+                        warning = Some(format!("{}{}", command_spec::DEPRECATED_MARKER, details));
+                    }
+                    let threshold = Duration::from_micros(current.slowlog_threshold_us);
+                    if !threshold.is_zero() && elapsed >= threshold {
+                        state.slow_log.record(client_addr, name, key.as_deref(), elapsed, current.slowlog_max_len);
+                    }
+                    if let Some(audit_log) = &state.audit_log {
+                        let status = if response.is_error() { "error" } else { "ok" };
+                        audit_log.record(client_addr, name, key.as_deref(), value_size, status);
+                    }
+                    response
+                }
+                Err((request_str, usage, e)) => {
+                    log_invalid_endpoint(&request_str);
+                    if let Some(audit_log) = &state.audit_log {
+                        audit_log.record(client_addr, "INVALID", None, None, "invalid");
+                    }
+                    let detail = match e {
+                        ApiError::InvalidCommand(detail) => detail,
+                        e => e.to_string(),
+                    };
+                    match usage {
+                        Some(usage) => Reply::Text(format!("ERROR: {}. usage: {}", detail.trim_end_matches('.'), usage)),
+                        None => Reply::Text(format!("ERROR: {}", detail)),
+                    }
+                }
+            };
+            
+            let written = match response {
+                Reply::Spooled(file) => Self::send_spooled(&mut writer, &file, warning.as_deref().unwrap_or_default()).await,
+                response => {
+                    let mut text = response.into_text();
+                    if let Some(warning) = &warning {
+                        text.push_str(warning);
+                    }
+                    text.push('\n');
+                    writer.write_all(text.as_bytes()).await.map(|()| text.len() as u64)
+                }
+            };
+            match written {
+                Ok(response_bytes) => {
+                    if let Some((tenant, request_bytes)) = metered {
+                        state.usage.record(tenant.as_deref(), request_bytes, response_bytes);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to send response to {}: {}", client_addr, e);
+                    break;
+                }
             }
         }
         
         Ok(())
     }
 
+    // Sends the file, then `suffix` and the newline. Returns the number of
+    // bytes sent
+    async fn send_spooled<W>(writer: &mut W, file: &SpoolFile, suffix: &str) -> std::io::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut spooled = tokio::fs::File::open(file.path()).await?;
+        let copied = tokio::io::copy(&mut spooled, writer).await?;
+        writer.write_all(format!("{}\n", suffix).as_bytes()).await?;
+        Ok(copied + suffix.len() as u64 + 1)
+    }
+
+    /// Runs `command` under `command_timeout_ms`, or under the client's
+    /// `*TO=` deadline when that is shorter. A command that only reads is
+    /// stopped once the client's deadline passes; one that writes is left to
+    /// finish in the background so it never stops halfway.
+    async fn execute_with_timeout(command: Command, state: &Arc<ServerState>, db: usize, deadline: Option<Duration>) -> Reply {
+        let Some(cache) = state.databases.shared(db) else {
+            return Reply::Text(format!("ERROR: Database {} does not exist", db));
+        };
+        let ctx = Context { state: Arc::clone(state), db, cache };
+        let command_timeout = Duration::from_millis(state.config.current().command_timeout_ms);
+        let (timeout, client_deadline) = match deadline {
+            Some(deadline) if command_timeout.is_zero() || deadline < command_timeout => (deadline, true),
+            _ => (command_timeout, false),
+        };
+        if timeout.is_zero() {
+            return command.run(ctx).await;
+        }
 
+        let read_only = command.is_read_only();
+        let mut task = tokio::spawn(command.run(ctx));
 
-    async fn execute_command(command: Command, cache: &Hydrogen) -> String {
-        match command {
-            Command::Set { key, value } => {
-                match cache.set(key.clone(), value).await {
-                    Ok(()) => "OK".to_string(),
-                    Err(e) => format!("ERROR: {}", e)
-                }
-            }
-            Command::Get { key } => {
-                match cache.get(&key).await {
-                    Ok(value) => value,
-                    Err(CacheError::KeyNotFound(_)) => "NULL".to_string(),
-                    Err(e) => format!("ERROR: {}", e)
-                }
-            }
-            Command::Delete { key } => {
-                match cache.delete(&key).await {
-                    Ok(existed) => {
-                        if existed {
-                            "1".to_string()
-                        } else {
-                            "0".to_string()
-                        }
-                    }
-                    Err(e) => format!("ERROR: {}", e)
-                }
-            }
-            Command::Keys => {
-                match cache.keys().await {
-                    Ok(keys) => {
-                        if keys.is_empty() {
-                            "(empty)".to_string()
-                        } else {
-                            keys.join(" ")
-                        }
-                    }
-                    Err(e) => format!("ERROR: {}", e)
+        match tokio::time::timeout(timeout, &mut task).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => Reply::Text(format!("ERROR: {}", e)),
+            Err(_) if client_deadline => {
+                if read_only {
+                    task.abort();
                 }
+                Reply::Text(TIMEOUT_REPLY.to_string())
             }
+            Err(_) => Reply::Text("ERROR: Command timed out".to_string()),
         }
     }
 
@@ -305,3 +543,105 @@ impl TcpApiServer {
     }
 }
 
+impl Drop for TcpApiServer {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.unix_listener {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A reply line, or a value large enough to be sent from a spool file.
+pub enum Reply {
+    Text(String),
+    Spooled(SpoolFile),
+    /// SELECT's "OK", after which the connection uses database `index`
+    Selected(usize),
+}
+
+impl Reply {
+    fn is_error(&self) -> bool {
+        matches!(self, Reply::Text(text) if text.starts_with("ERROR"))
+    }
+
+    /// The reply as one line, for relaying it inside another reply
+    pub fn into_text(self) -> String {
+        match self {
+            Reply::Text(text) => text,
+            Reply::Spooled(_) => "ERROR: Reply is too large to relay".to_string(),
+            Reply::Selected(_) => "OK".to_string(),
+        }
+    }
+}
+
+/// The syntax of the command `request` starts with, for usage errors. A
+/// request that stops before its subcommand, such as "CONFIG", gets the
+/// syntax of each subcommand.
+fn usage_for(request: &str) -> Option<String> {
+    let first = request.split_whitespace().next()?;
+    match command_spec::find(request) {
+        // "COMMAND FOO" finds plain COMMAND, but the subcommand is what is wrong
+        Some(spec) if spec.name.contains(' ') || command_spec::group(first).nth(1).is_none() => {
+            return Some(spec.to_string());
+        }
+        _ => {}
+    }
+    let usages: Vec<String> = command_spec::group(first).map(|spec| spec.to_string()).collect();
+    (!usages.is_empty()).then(|| usages.join(" | "))
+}
+
+/// Splits an optional `*TO=<n><unit>` deadline off the front of a request,
+/// as in `*TO=50ms GET key`. The unit is us, ms or s.
+fn split_deadline(request: &str) -> ApiResult<(Option<Duration>, &str)> {
+    if !request.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("*TO=")) {
+        return Ok((None, request));
+    }
+    let (token, rest) = request[4..].split_once(char::is_whitespace).unwrap_or((&request[4..], ""));
+    let split = token.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(token.len());
+    let (amount, unit) = token.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid_deadline(token))?;
+    let deadline = match unit.to_lowercase().as_str() {
+        "us" => Duration::from_micros(amount),
+        "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        _ => return Err(invalid_deadline(token)),
+    };
+    if deadline.is_zero() {
+        return Err(invalid_deadline(token));
+    }
+    Ok((Some(deadline), rest.trim_start()))
+}
+
+fn invalid_deadline(token: &str) -> ApiError {
+    ApiError::InvalidCommand(format!("Invalid deadline *TO={}, expected a positive number of us, ms or s", token))
+}
+
+// Keys with whitespace or control characters, which only binary-safe
+// validation allows, are quoted in space separated replies the way they are
+// sent. So is a key starting with a quote, which would read as quoted.
+pub fn quote_key(key: &str) -> Cow<'_, str> {
+    if !key.starts_with('"') && !key.chars().any(|ch| ch.is_whitespace() || ch.is_control()) {
+        return Cow::Borrowed(key);
+    }
+    Cow::Owned(quote_value(key))
+}
+
+// Values in key/value replies are always quoted, with the same escapes as
+// keys, so a quote or newline in a value can't end it or the reply early
+pub fn quote_value(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\0' => quoted.push_str("\\0"),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -1,26 +1,240 @@
 // Copyright (c) 2025, TheByteSlayer, Hydrogen
 // A scalable and lightweight Key Value Cache written in Rust
 
-use tracing::info;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+use crate::configuration::HydrogenConfig;
 
-pub fn log_set_endpoint(key: &str, value: &str) {
-    info!("SET {} {}", key, value);
+/// Logs a request as its command, key and anything else the command adds,
+/// with the size of the value it carries.
+pub fn log_command_endpoint(name: &str, key: Option<&str>, detail: Option<&str>, value_size: Option<usize>) {
+    let mut line = name.to_string();
+    for part in [key, detail].into_iter().flatten() {
+        line.push(' ');
+        line.push_str(part);
+    }
+    if let Some(size) = value_size {
+        line.push_str(&format!(" ({} bytes)", size));
+    }
+    info!("{}", line);
 }
 
-pub fn log_get_endpoint(key: &str) {
-    info!("GET {}", key);
+// Only the command word and the size are logged, as the rest of a rejected
+// request can hold values. The word is capped since it may not be a command.
+pub fn log_invalid_endpoint(request: &str) {
+    let command: String = request.split_whitespace().next().unwrap_or("").chars().take(32).collect();
+    info!("Invalid endpoint: {} ({} bytes)", command, request.len());
 }
 
-pub fn log_delete_endpoint(key: &str) {
-    info!("DEL {}", key);
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    client: String,
+    command: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_size: Option<usize>,
+    status: &'a str,
 }
 
-pub fn log_keys_endpoint() {
-    info!("KEYS");
+struct AuditFile {
+    file: File,
+    size: u64,
 }
 
-pub fn log_invalid_endpoint(command: &str) {
-    info!("Invalid endpoint: {}", command);
+/// Machine-readable record of every request, written as JSON lines. Values
+/// are never written, only their size, and keys can be left out as well.
+pub struct AuditLog {
+    path: String,
+    max_bytes: u64,
+    max_files: u32,
+    include_keys: bool,
+    file: Mutex<AuditFile>,
 }
 
- 
\ No newline at end of file
+impl AuditLog {
+    pub fn from_config(config: &HydrogenConfig) -> io::Result<Option<Self>> {
+        if !config.audit_log_enabled {
+            return Ok(None);
+        }
+
+        let file = Self::open(&config.audit_log_path)?;
+        Ok(Some(Self {
+            path: config.audit_log_path.clone(),
+            max_bytes: config.audit_log_max_bytes,
+            max_files: config.audit_log_max_files,
+            include_keys: config.audit_log_keys,
+            file: Mutex::new(file),
+        }))
+    }
+
+    fn open(path: &str) -> io::Result<AuditFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(AuditFile { file, size })
+    }
+
+    pub fn record(&self, client: &str, command: &str, key: Option<&str>, value_size: Option<usize>, status: &str) {
+        let record = AuditRecord {
+            timestamp: format_timestamp(SystemTime::now()),
+            client: client.to_string(),
+            command,
+            key: key.filter(|_| self.include_keys),
+            value_size,
+            status,
+        };
+
+        let mut line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+
+        let mut audit_file = match self.file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(e) = self.write_line(&mut audit_file, line.as_bytes()) {
+            error!("Failed to write audit log {}: {}", self.path, e);
+        }
+    }
+
+    fn write_line(&self, audit_file: &mut AuditFile, line: &[u8]) -> io::Result<()> {
+        if self.max_bytes > 0 && audit_file.size > 0 && audit_file.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+            *audit_file = Self::open(&self.path)?;
+        }
+
+        audit_file.file.write_all(line)?;
+        audit_file.size += line.len() as u64;
+        Ok(())
+    }
+
+    // Shifts audit.log -> audit.log.1 -> audit.log.2 ..., dropping the oldest
+    fn rotate(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for index in (1..self.max_files).rev() {
+            let from = format!("{}.{}", self.path, index);
+            if fs::metadata(&from).is_ok() {
+                fs::rename(&from, format!("{}.{}", self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, format!("{}.1", self.path))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CaptureRecord<'a> {
+    timestamp_us: u64,
+    client: String,
+    line: &'a str,
+}
+
+/// Records every request line exactly as received, with a microsecond
+/// timestamp, so `hydrogen-cli --replay` can re-run the traffic later with
+/// the original timing. Capture can be switched on and off at runtime.
+pub struct TrafficCapture {
+    path: String,
+    file: Mutex<Option<File>>,
+}
+
+impl TrafficCapture {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut file = match self.file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if enabled == file.is_some() {
+            return;
+        }
+
+        if !enabled {
+            *file = None;
+            info!("Traffic capture to {} stopped", self.path);
+            return;
+        }
+        match OpenOptions::new().create(true).append(true).open(&self.path) {
+            Ok(opened) => {
+                *file = Some(opened);
+                info!("Traffic capture to {} started", self.path);
+            }
+            Err(e) => error!("Failed to open capture file {}: {}", self.path, e),
+        }
+    }
+
+    pub fn record(&self, client: &str, line: &str) {
+        let mut file = match self.file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let Some(capture_file) = file.as_mut() else {
+            return;
+        };
+
+        let record = CaptureRecord {
+            timestamp_us: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64,
+            client: client.to_string(),
+            line,
+        };
+        let mut encoded = match serde_json::to_string(&record) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                error!("Failed to serialize capture record: {}", e);
+                return;
+            }
+        };
+        encoded.push('\n');
+
+        if let Err(e) = capture_file.write_all(encoded.as_bytes()) {
+            error!("Failed to write capture file {}: {}", self.path, e);
+        }
+    }
+}
+
+// Formats as RFC 3339 in UTC with millisecond precision
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
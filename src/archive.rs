@@ -0,0 +1,202 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use crate::cache::{CacheEntry, CacheError, Databases, Hydrogen};
+use crate::configuration::{HydrogenConfig, SharedConfig};
+use crate::snapshot;
+use crate::supervisor::TaskHandle;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid archive record at offset {0}")]
+    InvalidRecord(u64),
+    #[error("Key not archived: {0}")]
+    NotArchived(String),
+    #[error("Cache error: {0}")]
+    Cache(#[from] CacheError),
+}
+
+type ArchiveResult<T> = Result<T, ArchiveError>;
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// One line of the archive file. The payload is the compressed value in hex,
+/// as DUMP returns it.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveRecord {
+    db: usize,
+    key: String,
+    payload: String,
+    /// Seconds since the epoch
+    archived_at: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ArchivedKey {
+    offset: u64,
+    archived_at: u64,
+}
+
+/// The archive file that retention moves idle keys into. The file is only
+/// ever appended to, and the latest record of each key is indexed in memory
+/// so ARCHIVE RESTORE can read it back with one seek. The index is rebuilt
+/// from the file at startup.
+#[derive(Debug)]
+pub struct Archive {
+    path: String,
+    index: Mutex<HashMap<(usize, String), ArchivedKey>>,
+}
+
+impl Archive {
+    pub async fn open(path: &str) -> std::io::Result<Self> {
+        let mut index = HashMap::new();
+        match File::open(path).await {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                let mut line = String::new();
+                let mut offset = 0u64;
+                loop {
+                    line.clear();
+                    let read = reader.read_line(&mut line).await?;
+                    if read == 0 {
+                        break;
+                    }
+                    // A line cut short by a crash is skipped, the key is
+                    // still in memory and will be archived again
+                    match serde_json::from_str::<ArchiveRecord>(&line) {
+                        Ok(record) => {
+                            let archived = ArchivedKey { offset, archived_at: record.archived_at };
+                            index.insert((record.db, record.key), archived);
+                        }
+                        Err(_) if line.trim().is_empty() => {}
+                        Err(e) => warn!("Skipping invalid archive record at offset {}: {}", offset, e),
+                    }
+                    offset += read as u64;
+                }
+                info!("Loaded archive index with {} keys from {}", index.len(), path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self { path: path.to_string(), index: Mutex::new(index) })
+    }
+
+    /// Puts the latest archived value of `key` back into `cache`. A key that
+    /// is already in memory is left alone.
+    pub async fn restore(&self, cache: &Hydrogen, db: usize, key: &str) -> ArchiveResult<()> {
+        let archived = self.index.lock().await.get(&(db, key.to_string())).copied();
+        let Some(archived) = archived else {
+            return Err(ArchiveError::NotArchived(key.to_string()));
+        };
+
+        let mut file = File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(archived.offset)).await?;
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line).await?;
+        let record: ArchiveRecord = serde_json::from_str(&line)
+            .map_err(|_| ArchiveError::InvalidRecord(archived.offset))?;
+        let payload = snapshot::decode_payload(&record.payload)
+            .ok_or(ArchiveError::InvalidRecord(archived.offset))?;
+        cache.restore(record.key, payload, false).await?;
+        Ok(())
+    }
+
+    /// Periodically archives keys idle for longer than their retention rule
+    /// allows. Like recompression the settings are re-read before every
+    /// pass, and the supervisor restarts the task if a pass fails.
+    pub async fn run(self: Arc<Self>, databases: Arc<Databases>, config: Arc<SharedConfig>, task: TaskHandle) -> Result<(), String> {
+        loop {
+            let interval = config.current().retention_interval_secs.max(1);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let current = config.current();
+            if current.retention.is_empty() {
+                continue;
+            }
+            self.retention_pass(&databases, &current).await.map_err(|e| e.to_string())?;
+            task.ran();
+        }
+    }
+
+    async fn retention_pass(&self, databases: &Databases, config: &HydrogenConfig) -> ArchiveResult<()> {
+        let idle_limit = |key: &str| {
+            config.retention_rule(key).map(|rule| Duration::from_secs(rule.idle_days * SECS_PER_DAY))
+        };
+        let mut archived = 0usize;
+        let mut removed = 0usize;
+
+        for (db, database) in databases.iter().enumerate() {
+            let now = now_secs();
+            let mut candidates = database.idle_entries(idle_limit).await;
+            {
+                // Kept keys are archived once, then again only after they
+                // have been used and gone idle since
+                let index = self.index.lock().await;
+                candidates.retain(|(key, entry)| {
+                    let last_used = now.saturating_sub(entry.idle_for().as_secs());
+                    index.get(&(db, key.clone())).is_none_or(|archived| archived.archived_at < last_used)
+                });
+            }
+            if candidates.is_empty() {
+                continue;
+            }
+
+            self.append(db, &candidates, now).await?;
+            archived += candidates.len();
+
+            // Only dropped once the archive holds them, and only if no write
+            // came in while they were being archived
+            for (key, entry) in &candidates {
+                let keep = config.retention_rule(key).is_some_and(|rule| rule.keep);
+                if !keep && database.remove_if_unchanged(key, entry).await {
+                    removed += 1;
+                }
+            }
+        }
+
+        if archived > 0 {
+            info!("Archived {} idle keys to {}, removed {} from memory", archived, self.path, removed);
+        }
+        Ok(())
+    }
+
+    async fn append(&self, db: usize, entries: &[(String, CacheEntry)], archived_at: u64) -> ArchiveResult<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        let mut offset = file.metadata().await?.len();
+        let mut buffer = Vec::new();
+        let mut written = Vec::with_capacity(entries.len());
+        for (key, entry) in entries {
+            let record = ArchiveRecord {
+                db,
+                key: key.clone(),
+                payload: snapshot::encode_payload(&entry.compressed()?),
+                archived_at,
+            };
+            let mut line = serde_json::to_string(&record).map_err(std::io::Error::other)?;
+            line.push('\n');
+            written.push(((db, key.clone()), ArchivedKey { offset, archived_at }));
+            offset += line.len() as u64;
+            buffer.extend_from_slice(line.as_bytes());
+        }
+        file.write_all(&buffer).await?;
+        file.sync_data().await?;
+
+        self.index.lock().await.extend(written);
+        Ok(())
+    }
+}
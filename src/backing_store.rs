@@ -0,0 +1,114 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use crate::cache::CacheEntry;
+use crate::configuration::{ConfigError, HydrogenConfig};
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A slower store that Hydrogen sits in front of. Misses are loaded from it
+/// and, with write-through enabled, SET and DEL are applied to it as well.
+pub trait BackingStore: Send + Sync + std::fmt::Debug {
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>>;
+    fn put<'a>(&'a self, key: &'a str, value: &'a str) -> StoreFuture<'a, ()>;
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+
+    /// Stores a value that is at hand only compressed, such as a spooled
+    /// SET. Stores that can write it without decompressing it into memory
+    /// should override this.
+    fn put_compressed<'a>(&'a self, key: &'a str, entry: &'a CacheEntry) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let value = entry.get_value().map_err(io::Error::other)?;
+            self.put(key, &value).await
+        })
+    }
+}
+
+/// Stores one file per key under a root directory. Characters outside the
+/// strict key alphabet are percent-encoded, so keys allowed by relaxed
+/// `key_validation` cannot escape the directory, and strict keys keep their
+/// plain file names.
+#[derive(Debug)]
+pub struct FilesystemStore {
+    root: PathBuf,
+}
+
+impl FilesystemStore {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn file_name(key: &str) -> String {
+        let mut name = String::with_capacity(key.len());
+        for byte in key.bytes() {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b':') {
+                name.push(byte as char);
+            } else {
+                name.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        name
+    }
+}
+
+impl BackingStore for FilesystemStore {
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+        Box::pin(async move {
+            match tokio::fs::read_to_string(self.root.join(Self::file_name(key))).await {
+                Ok(value) => Ok(Some(value)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let name = Self::file_name(key);
+            let temp_path = self.root.join(format!(".{}.tmp", name));
+            tokio::fs::write(&temp_path, value).await?;
+            tokio::fs::rename(&temp_path, self.root.join(name)).await
+        })
+    }
+
+    fn put_compressed<'a>(&'a self, key: &'a str, entry: &'a CacheEntry) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let name = Self::file_name(key);
+            let temp_path = self.root.join(format!(".{}.tmp", name));
+            let compressed = entry.compressed().map_err(io::Error::other)?;
+            let decode_path = temp_path.clone();
+            tokio::task::spawn_blocking(move || -> io::Result<()> {
+                let mut writer = io::BufWriter::new(std::fs::File::create(decode_path)?);
+                zstd::stream::copy_decode(&compressed[..], &mut writer)?;
+                io::Write::flush(&mut writer)
+            })
+            .await
+            .map_err(io::Error::other)??;
+            tokio::fs::rename(&temp_path, self.root.join(name)).await
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.root.join(Self::file_name(key))).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+pub fn from_config(config: &HydrogenConfig) -> Result<Option<Arc<dyn BackingStore>>, ConfigError> {
+    match config.backing_store.as_str() {
+        "none" => Ok(None),
+        "filesystem" => Ok(Some(Arc::new(FilesystemStore::new(&config.backing_store_path)?))),
+        other => Err(ConfigError::InvalidValue(format!("unknown backing_store '{}'", other))),
+    }
+}
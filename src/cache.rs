@@ -1,11 +1,24 @@
 // Copyright (c) 2025, TheByteSlayer, Hydrogen
 // A scalable and lightweight Key Value Cache written in Rust
 
+use bytes::Bytes;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::hash::{BuildHasher, RandomState};
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+use std::sync::RwLock as SyncRwLock;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+use crate::backing_store::BackingStore;
+use crate::configuration::NamespaceQuota;
+use crate::interning::{InternedKey, InterningStats, KeyParts, KeyRef, PrefixTable};
+use crate::spool::{CompressedValue, SpooledValue};
+use crate::supervisor::Supervisor;
+use crate::write_batch::WriteBatcher;
 
-use zstd::{decode_all, encode_all};
+use zstd::stream::read::Decoder;
+use zstd::zstd_safe::get_frame_content_size;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CacheError {
@@ -15,58 +28,850 @@ pub enum CacheError {
     DecompressionError(String),
     #[error("Key not found: {0}")]
     KeyNotFound(String),
+    #[error("Key already exists: {0}")]
+    KeyExists(String),
+    #[error("Backing store error: {0}")]
+    BackingStoreError(String),
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    #[error("Write batching error: {0}")]
+    BatchingError(String),
 }
 
 type CacheResult<T> = Result<T, CacheError>;
 
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+// Upper bound on what a frame header can make us reserve up front, since
+// restored payloads come from clients
+const MAX_DECOMPRESS_RESERVATION: u64 = 64 * 1024 * 1024;
+
+// Largest value a restored payload may decompress to until the server sets
+// its own limit, the default max_line_length
+const DEFAULT_MAX_VALUE_BYTES: usize = 1024 * 1024;
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// What a value looks like, sniffed when it is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Json,
+    Numeric,
+    Text,
+    Binary,
+}
+
+impl ValueType {
+    pub const ALL: [ValueType; 4] = [ValueType::Json, ValueType::Numeric, ValueType::Text, ValueType::Binary];
+
+    pub fn sniff(value: &str) -> Self {
+        let trimmed = value.trim_start();
+        if Self::is_numeric(value) {
+            ValueType::Numeric
+        } else if (trimmed.starts_with('{') || trimmed.starts_with('['))
+            && serde_json::from_str::<serde::de::IgnoredAny>(value).is_ok()
+        {
+            ValueType::Json
+        } else if Self::is_binary(value) {
+            ValueType::Binary
+        } else {
+            ValueType::Text
+        }
+    }
+
+    /// Classifies a value from its first bytes only, for values too large
+    /// to look at whole. JSON is assumed from the opening bracket alone.
+    pub fn sniff_start(start: &str) -> Self {
+        let trimmed = start.trim_start();
+        if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            ValueType::Json
+        } else if Self::is_binary(start) {
+            ValueType::Binary
+        } else {
+            ValueType::Text
+        }
+    }
+
+    // Control characters other than line breaks and tabs
+    fn is_binary(value: &str) -> bool {
+        value.chars().any(|ch| ch.is_control() && !matches!(ch, '\t' | '\n' | '\r'))
+    }
+
+    fn is_numeric(value: &str) -> bool {
+        // f64 parsing also accepts words such as "inf" and "NaN"
+        !value.is_empty()
+            && value.bytes().all(|byte| byte.is_ascii_digit() || b"+-.eE".contains(&byte))
+            && value.parse::<f64>().is_ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::Json => "json",
+            ValueType::Numeric => "numeric",
+            ValueType::Text => "text",
+            ValueType::Binary => "binary",
+        }
+    }
+}
+
+// Longest decimal form of an i64, "-9223372036854775808"
+const MAX_INTEGER_LEN: usize = 20;
+
+// Values up to this many bytes are kept inline in the entry. At 31 bytes
+// plus a length byte the inline form is no larger than a `Bytes` handle.
+const INLINE_CAPACITY: usize = 31;
+
+/// How a value is held in memory.
 #[derive(Debug, Clone)]
+enum Encoding {
+    /// A zstd frame, reference counted
+    Compressed(Bytes),
+    /// A value that is the canonical decimal form of an i64, stored as the
+    /// number itself
+    Integer(i64),
+    /// A short value stored as is, without compression or an allocation
+    Inline { len: u8, data: [u8; INLINE_CAPACITY] },
+}
+
+impl Encoding {
+    // Integers and short values are stored inline, anything else is
+    // compressed
+    fn inline(value: &str) -> Option<Self> {
+        if let Some(integer) = CacheEntry::parse_integer(value) {
+            return Some(Encoding::Integer(integer));
+        }
+        if value.len() > INLINE_CAPACITY {
+            return None;
+        }
+        let mut data = [0; INLINE_CAPACITY];
+        data[..value.len()].copy_from_slice(value.as_bytes());
+        Some(Encoding::Inline { len: value.len() as u8, data })
+    }
+}
+
+/// A stored value, either compressed or, for integers and short values,
+/// inline. The data is
+/// reference counted, so cloning an entry to work on it outside the storage
+/// lock is cheap.
+#[derive(Debug)]
 pub struct CacheEntry {
-    pub compressed_data: Vec<u8>,
+    encoding: Encoding,
+    /// Level the data was compressed at, or 0 when unknown (restored data)
+    pub compression_level: i32,
+    /// Size of the value before compression
+    pub original_size: usize,
+    pub value_type: ValueType,
+    last_access: AtomicU64,
+    /// Set when the entry is stored, so every write to a key gives it a new
+    /// version; 0 until then
+    version: u64,
+}
+
+impl Clone for CacheEntry {
+    fn clone(&self) -> Self {
+        Self {
+            encoding: self.encoding.clone(),
+            compression_level: self.compression_level,
+            original_size: self.original_size,
+            value_type: self.value_type,
+            last_access: AtomicU64::new(self.last_access.load(Ordering::Relaxed)),
+            version: self.version,
+        }
+    }
 }
 
 impl CacheEntry {
-    pub fn new(value: &str) -> CacheResult<Self> {
-        let compressed_data = encode_all(value.as_bytes(), 3)
+    pub fn new(value: &str, compression_level: i32) -> CacheResult<Self> {
+        if let Some(inline) = Encoding::inline(value) {
+            return Ok(Self::with_encoding(inline, compression_level, value));
+        }
+
+        // Single-shot compression records the value size in the frame
+        let compressed_data = zstd::bulk::compress(value.as_bytes(), compression_level)
             .map_err(|e| CacheError::CompressionError(e.to_string()))?;
-        
-        Ok(Self {
-            compressed_data,
-        })
+        Ok(Self::with_encoding(Encoding::Compressed(Bytes::from(compressed_data)), compression_level, value))
+    }
+
+    /// Wraps a zstd frame from outside, such as a RESTORE payload. Frames
+    /// that decompress to more than `max_size` bytes are refused, so a small
+    /// payload cannot expand into an unbounded allocation.
+    pub fn from_compressed(compressed_data: Vec<u8>, max_size: usize) -> CacheResult<Self> {
+        let compressed = Encoding::Compressed(Bytes::from(compressed_data));
+        let value = Self::decode(&compressed, max_size)?;
+        let encoding = Encoding::inline(&value).unwrap_or(compressed);
+        Ok(Self::with_encoding(encoding, 0, &value))
+    }
+
+    /// Wraps data this server has just compressed itself, so unlike
+    /// `from_compressed` it is not decoded again to check it.
+    pub fn from_encoded(compressed: CompressedValue, compression_level: i32) -> Self {
+        Self {
+            encoding: Encoding::Compressed(Bytes::from(compressed.data)),
+            compression_level,
+            original_size: compressed.original_size,
+            value_type: compressed.value_type,
+            last_access: AtomicU64::new(now_secs()),
+            version: 0,
+        }
+    }
+
+    fn with_encoding(encoding: Encoding, compression_level: i32, value: &str) -> Self {
+        Self {
+            encoding,
+            compression_level,
+            original_size: value.len(),
+            value_type: ValueType::sniff(value),
+            last_access: AtomicU64::new(now_secs()),
+            version: 0,
+        }
+    }
+
+    // Only the canonical form is stored as a number, so reading the value
+    // back gives exactly what was written ("007" and "+7" stay strings)
+    fn parse_integer(value: &str) -> Option<i64> {
+        if value.is_empty() || value.len() > MAX_INTEGER_LEN {
+            return None;
+        }
+        value.parse::<i64>().ok().filter(|integer| integer.to_string() == value)
+    }
+
+    /// Whether the value is held as a zstd frame rather than inline.
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.encoding, Encoding::Compressed(_))
+    }
+
+    /// Bytes the value takes in memory.
+    pub fn stored_size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Compressed(data) => data.len(),
+            Encoding::Integer(_) => std::mem::size_of::<i64>(),
+            Encoding::Inline { len, .. } => *len as usize,
+        }
+    }
+
+    /// The value as a zstd frame, as used by DUMP and the backing store.
+    /// Inline values are compressed on demand.
+    pub fn compressed(&self) -> CacheResult<Bytes> {
+        match &self.encoding {
+            Encoding::Compressed(data) => Ok(data.clone()),
+            _ => {
+                zstd::bulk::compress(self.get_value()?.as_bytes(), DEFAULT_COMPRESSION_LEVEL)
+                    .map(Bytes::from)
+                    .map_err(|e| CacheError::CompressionError(e.to_string()))
+            }
+        }
+    }
+
+    /// Whether both entries hold the very same stored data.
+    pub fn same_data(&self, other: &CacheEntry) -> bool {
+        match (&self.encoding, &other.encoding) {
+            (Encoding::Compressed(a), Encoding::Compressed(b)) => a.as_ptr() == b.as_ptr() && a.len() == b.len(),
+            (Encoding::Integer(a), Encoding::Integer(b)) => a == b,
+            (Encoding::Inline { len: a_len, data: a }, Encoding::Inline { len: b_len, data: b }) => {
+                a[..*a_len as usize] == b[..*b_len as usize]
+            }
+            _ => false,
+        }
+    }
+
+    /// Uncompressed size in bytes, if known without decoding.
+    pub fn content_size(&self) -> Option<u64> {
+        match &self.encoding {
+            Encoding::Compressed(data) => get_frame_content_size(data).ok().flatten(),
+            Encoding::Integer(_) | Encoding::Inline { .. } => Some(self.original_size as u64),
+        }
+    }
+
+    /// The same value compressed again at `level`, keeping its access time
+    /// and version.
+    pub fn recompress(&self, level: i32) -> CacheResult<Self> {
+        let mut entry = Self::new(&self.get_value()?, level)?;
+        entry.last_access = AtomicU64::new(self.last_access.load(Ordering::Relaxed));
+        entry.version = self.version;
+        Ok(entry)
+    }
+
+    /// Identifies the write that stored this value. Versions only grow and
+    /// are never reused within a keyspace, so a key deleted and set again
+    /// has a different version even when its value is the same.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn touch(&self) {
+        self.last_access.store(now_secs(), Ordering::Relaxed);
+    }
+
+    /// Time since the value was last read or written.
+    pub fn idle_for(&self) -> Duration {
+        Duration::from_secs(now_secs().saturating_sub(self.last_access.load(Ordering::Relaxed)))
     }
 
     pub fn get_value(&self) -> CacheResult<String> {
-        let decompressed = decode_all(&self.compressed_data[..])
+        Self::decode(&self.encoding, self.original_size)
+    }
+
+    // Decompresses into a buffer sized from the frame header, with one spare
+    // byte so the reply's trailing newline fits without reallocating. Output
+    // past `max_size` bytes is an error rather than more memory.
+    fn decode(encoding: &Encoding, max_size: usize) -> CacheResult<String> {
+        let data = match encoding {
+            Encoding::Compressed(data) => data,
+            Encoding::Integer(integer) => return Ok(integer.to_string()),
+            Encoding::Inline { len, data } => {
+                return String::from_utf8(data[..*len as usize].to_vec())
+                    .map_err(|e| CacheError::DecompressionError(format!("UTF-8 error: {}", e)));
+            }
+        };
+        let too_large = || CacheError::DecompressionError(format!("value is larger than {} bytes", max_size));
+        let content_size = get_frame_content_size(data).ok().flatten().unwrap_or(0);
+        if content_size > max_size as u64 {
+            return Err(too_large());
+        }
+        let mut decompressed = Vec::with_capacity(content_size.min(MAX_DECOMPRESS_RESERVATION) as usize + 1);
+        Decoder::with_buffer(&data[..])
+            .and_then(|decoder| decoder.take(max_size as u64 + 1).read_to_end(&mut decompressed))
             .map_err(|e| CacheError::DecompressionError(e.to_string()))?;
-        
+        if decompressed.len() > max_size {
+            return Err(too_large());
+        }
+
         String::from_utf8(decompressed)
             .map_err(|e| CacheError::DecompressionError(format!("UTF-8 error: {}", e)))
     }
 }
 
+/// Returns the namespace of a `ns:key` style key.
+pub fn namespace_of(key: &str) -> Option<&str> {
+    key.split_once(':').map(|(namespace, _)| namespace)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NamespaceUsage {
+    pub keys: usize,
+    pub bytes: usize,
+}
+
+/// Value bytes before and after compression for a group of keys.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompressionStats {
+    pub keys: usize,
+    pub original_bytes: usize,
+    pub compressed_bytes: usize,
+}
+
+impl CompressionStats {
+    pub fn merge(&mut self, other: &CompressionStats) {
+        self.keys += other.keys;
+        self.original_bytes += other.original_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+    }
+
+    /// Original size divided by compressed size; above 1 means compression
+    /// is saving memory.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.original_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// The entries of one keyspace together with running totals per namespace.
+/// Every change goes through `insert` and `remove` so the totals can't drift.
+#[derive(Debug, Default)]
+struct Keyspace {
+    entries: HashMap<InternedKey, CacheEntry>,
+    prefixes: PrefixTable,
+    namespaces: HashMap<String, NamespaceUsage>,
+    // Keyed by namespace, with "" for keys outside any namespace
+    compression: HashMap<String, CompressionStats>,
+    value_types: HashMap<ValueType, CompressionStats>,
+    // The version given to the last entry stored, kept across flushes
+    last_version: u64,
+}
+
+impl Keyspace {
+    fn get(&self, key: &str) -> Option<&CacheEntry> {
+        self.entries.get(&KeyRef::new(key) as &dyn KeyParts)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(&KeyRef::new(key) as &dyn KeyParts)
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &InternedKey> {
+        self.entries.keys()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&InternedKey, &CacheEntry)> {
+        self.entries.iter()
+    }
+
+    // Entries that were stored before, such as recompressed ones or those
+    // put back after a failed migration, keep their version
+    fn insert(&mut self, key: String, mut entry: CacheEntry) -> Option<CacheEntry> {
+        if entry.version == 0 {
+            self.last_version += 1;
+            entry.version = self.last_version;
+        }
+        if let Some(old) = self.entries.get(&KeyRef::new(&key) as &dyn KeyParts) {
+            Self::account(&mut self.namespaces, &key, old, false);
+            Self::account_stats(&mut self.compression, Self::prefix_of(&key), old, false);
+            Self::account_stats(&mut self.value_types, old.value_type, old, false);
+        }
+        Self::account(&mut self.namespaces, &key, &entry, true);
+        Self::account_stats(&mut self.compression, Self::prefix_of(&key), &entry, true);
+        Self::account_stats(&mut self.value_types, entry.value_type, &entry, true);
+        let interned = self.prefixes.intern(&key);
+        self.entries.insert(interned, entry)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        let (interned, entry) = self.entries.remove_entry(&KeyRef::new(key) as &dyn KeyParts)?;
+        self.prefixes.release(interned);
+        Self::account(&mut self.namespaces, key, &entry, false);
+        Self::account_stats(&mut self.compression, Self::prefix_of(key), &entry, false);
+        Self::account_stats(&mut self.value_types, entry.value_type, &entry, false);
+        Some(entry)
+    }
+
+    fn interning_stats(&self) -> InterningStats {
+        self.prefixes.stats()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.prefixes.clear();
+        self.namespaces.clear();
+        self.compression.clear();
+        self.value_types.clear();
+    }
+
+    fn account(namespaces: &mut HashMap<String, NamespaceUsage>, key: &str, entry: &CacheEntry, added: bool) {
+        let Some(namespace) = namespace_of(key) else {
+            return;
+        };
+        let bytes = key.len() + entry.stored_size();
+        if added {
+            let usage = namespaces.entry(namespace.to_string()).or_default();
+            usage.keys += 1;
+            usage.bytes += bytes;
+        } else if let Some(usage) = namespaces.get_mut(namespace) {
+            usage.keys -= 1;
+            usage.bytes -= bytes;
+            if usage.keys == 0 {
+                namespaces.remove(namespace);
+            }
+        }
+    }
+
+    fn prefix_of(key: &str) -> String {
+        namespace_of(key).unwrap_or("").to_string()
+    }
+
+    // Totals of original and compressed bytes for one group of keys, such as
+    // a namespace or a value type
+    fn account_stats<K: std::hash::Hash + Eq>(stats: &mut HashMap<K, CompressionStats>, group: K, entry: &CacheEntry, added: bool) {
+        if added {
+            let totals = stats.entry(group).or_default();
+            totals.keys += 1;
+            totals.original_bytes += entry.original_size;
+            totals.compressed_bytes += entry.stored_size();
+        } else if let Some(totals) = stats.get_mut(&group) {
+            totals.keys -= 1;
+            totals.original_bytes -= entry.original_size;
+            totals.compressed_bytes -= entry.stored_size();
+            if totals.keys == 0 {
+                stats.remove(&group);
+            }
+        }
+    }
+
+}
+
+// Locks of `KeyLocks`; keys share them by hash
+const KEY_LOCK_COUNT: usize = 256;
+
+/// Orders write-through per key. A write holds its key's lock from deciding
+/// what to write until memory is updated, with the keyspace lock released
+/// while the backing store is written, so slow store I/O stalls only writes
+/// to the same keys. Read-through takes the same lock, so a racing DEL can't
+/// bring back the value it removed.
+#[derive(Debug)]
+struct KeyLocks {
+    locks: Vec<Mutex<()>>,
+    hasher: RandomState,
+}
+
+impl KeyLocks {
+    fn new() -> Self {
+        Self {
+            locks: (0..KEY_LOCK_COUNT).map(|_| Mutex::new(())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    // Takes each lock once and in index order, so writes to several keys,
+    // like RENAME, cannot deadlock
+    async fn lock(&self, keys: &[&str]) -> Vec<MutexGuard<'_, ()>> {
+        let mut indexes: Vec<usize> = keys.iter()
+            .map(|key| self.hasher.hash_one(key) as usize % self.locks.len())
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        let mut guards = Vec::with_capacity(indexes.len());
+        for index in indexes {
+            guards.push(self.locks[index].lock().await);
+        }
+        guards
+    }
+}
+
+/// One backing store write made by a write-through command.
+enum StoreWrite<'a> {
+    Put(&'a str, &'a str),
+    PutCompressed(&'a str, &'a CacheEntry),
+    Delete(&'a str),
+}
+
 #[derive(Debug)]
 pub struct Hydrogen {
-    storage: Arc<RwLock<HashMap<String, CacheEntry>>>,
+    storage: Arc<RwLock<Keyspace>>,
+    backing_store: Option<Arc<dyn BackingStore>>,
+    write_through: bool,
+    key_locks: KeyLocks,
+    compression_level: AtomicI32,
+    max_value_bytes: AtomicUsize,
+    namespace_quotas: SyncRwLock<HashMap<String, NamespaceQuota>>,
 }
 
 impl Hydrogen {
     pub fn new() -> Self {
         Self {
-            storage: Arc::new(RwLock::new(HashMap::new())),
+            storage: Arc::new(RwLock::new(Keyspace::default())),
+            backing_store: None,
+            write_through: false,
+            key_locks: KeyLocks::new(),
+            compression_level: AtomicI32::new(DEFAULT_COMPRESSION_LEVEL),
+            max_value_bytes: AtomicUsize::new(DEFAULT_MAX_VALUE_BYTES),
+            namespace_quotas: SyncRwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_backing_store(backing_store: Arc<dyn BackingStore>, write_through: bool) -> Self {
+        Self {
+            storage: Arc::new(RwLock::new(Keyspace::default())),
+            backing_store: Some(backing_store),
+            write_through,
+            key_locks: KeyLocks::new(),
+            compression_level: AtomicI32::new(DEFAULT_COMPRESSION_LEVEL),
+            max_value_bytes: AtomicUsize::new(DEFAULT_MAX_VALUE_BYTES),
+            namespace_quotas: SyncRwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_compression_level(&self, level: i32) {
+        self.compression_level.store(level, Ordering::Relaxed);
+    }
+
+    /// Largest value a restored payload may decompress to. No request can
+    /// SET a value longer than a request line, so the server passes
+    /// max_line_length.
+    pub fn set_max_value_bytes(&self, max: usize) {
+        self.max_value_bytes.store(max, Ordering::Relaxed);
+    }
+
+    pub fn max_value_bytes(&self) -> usize {
+        self.max_value_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_namespace_quotas(&self, quotas: HashMap<String, NamespaceQuota>) {
+        match self.namespace_quotas.write() {
+            Ok(mut current) => *current = quotas,
+            Err(poisoned) => *poisoned.into_inner() = quotas,
+        }
+    }
+
+    // Checks that writing `entry` under `key` keeps its namespace within quota,
+    // counting a replaced entry as freed
+    fn check_quota(&self, storage: &Keyspace, key: &str, entry: &CacheEntry) -> CacheResult<()> {
+        let Some(namespace) = namespace_of(key) else {
+            return Ok(());
+        };
+        let quotas = match self.namespace_quotas.read() {
+            Ok(quotas) => quotas,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let Some(quota) = quotas.get(namespace) else {
+            return Ok(());
+        };
+
+        let usage = storage.namespaces.get(namespace).copied().unwrap_or_default();
+        let mut keys = usage.keys + 1;
+        let mut bytes = usage.bytes + key.len() + entry.stored_size();
+        if let Some(old) = storage.get(key) {
+            keys -= 1;
+            bytes -= key.len() + old.stored_size();
+        }
+
+        if quota.max_keys > 0 && keys > quota.max_keys {
+            return Err(CacheError::QuotaExceeded(format!(
+                "namespace '{}' is limited to {} keys", namespace, quota.max_keys
+            )));
+        }
+        if quota.max_bytes > 0 && bytes > quota.max_bytes {
+            return Err(CacheError::QuotaExceeded(format!(
+                "namespace '{}' is limited to {} bytes", namespace, quota.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn compress(&self, value: &str) -> CacheResult<CacheEntry> {
+        CacheEntry::new(value, self.compression_level.load(Ordering::Relaxed))
+    }
+
+    fn write_through_store(&self) -> Option<&dyn BackingStore> {
+        self.backing_store.as_deref().filter(|_| self.write_through)
+    }
+
+    // With write-through, writes hold their keys' locks from deciding until
+    // memory is updated, see `KeyLocks`. Without it nothing needs them.
+    async fn lock_keys(&self, keys: &[&str]) -> Vec<MutexGuard<'_, ()>> {
+        if self.write_through_store().is_none() {
+            return Vec::new();
         }
+        self.key_locks.lock(keys).await
+    }
+
+    // Applies `writes` to the write-through store, if there is one, with the
+    // keyspace lock released for the I/O and taken again afterwards. The
+    // caller holds the keys' locks, so no other write to them can come
+    // between its decision and its update in memory.
+    async fn write_through<'a>(
+        &'a self,
+        storage: RwLockWriteGuard<'a, Keyspace>,
+        writes: &[StoreWrite<'_>],
+    ) -> CacheResult<RwLockWriteGuard<'a, Keyspace>> {
+        let Some(store) = self.write_through_store() else {
+            return Ok(storage);
+        };
+        drop(storage);
+        for write in writes {
+            match *write {
+                StoreWrite::Put(key, value) => store.put(key, value).await,
+                StoreWrite::PutCompressed(key, entry) => store.put_compressed(key, entry).await,
+                StoreWrite::Delete(key) => store.delete(key).await,
+            }
+            .map_err(|e| CacheError::BackingStoreError(e.to_string()))?;
+        }
+        Ok(self.storage.write().await)
     }
 
     pub async fn set(&self, key: String, value: String) -> CacheResult<()> {
-        let entry = CacheEntry::new(&value)?;
+        let entry = self.compress(&value)?;
+        self.store_entry(key, &value, entry).await
+    }
+
+    /// Applies already compressed SETs under a single lock acquisition, in
+    /// order. Each write gets its own result, so a write over quota fails
+    /// alone. With write-through each write goes to the store on its own,
+    /// as the lock is released for the I/O anyway.
+    pub async fn set_batch(&self, writes: Vec<(String, String, CacheEntry)>) -> Vec<CacheResult<()>> {
+        let mut results = Vec::with_capacity(writes.len());
+        if self.write_through_store().is_some() {
+            for (key, value, entry) in writes {
+                results.push(self.store_entry(key, &value, entry).await);
+            }
+            return results;
+        }
+
         let mut storage = self.storage.write().await;
-        storage.insert(key.clone(), entry);
+        for (key, _, entry) in writes {
+            let result = self.check_quota(&storage, &key, &entry);
+            if result.is_ok() {
+                storage.insert(key, entry);
+            }
+            results.push(result);
+        }
+        results
+    }
+
+    async fn store_entry(&self, key: String, value: &str, entry: CacheEntry) -> CacheResult<()> {
+        let _keys = self.lock_keys(&[&key]).await;
+        let storage = self.storage.write().await;
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::Put(&key, value)]).await?;
+        storage.insert(key, entry);
         Ok(())
     }
 
+    // Brings a key held only in the backing store into memory, so
+    // conditional writes decide on what GET returns. The store is read
+    // without the keyspace lock; with write-through the caller holds the
+    // key's lock, so the store cannot change meanwhile.
+    async fn load_from_store(&self, key: &str) -> CacheResult<()> {
+        let Some(store) = &self.backing_store else {
+            return Ok(());
+        };
+        if self.storage.read().await.contains_key(key) {
+            return Ok(());
+        }
+        let loaded = store.get(key).await
+            .map_err(|e| CacheError::BackingStoreError(e.to_string()))?;
+        if let Some(value) = loaded {
+            let entry = self.compress(&value)?;
+            let mut storage = self.storage.write().await;
+            if !storage.contains_key(key) {
+                storage.insert(key.to_string(), entry);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn set_nx(&self, key: String, value: String) -> CacheResult<bool> {
+        let _keys = self.lock_keys(&[&key]).await;
+        self.load_from_store(&key).await?;
+        let storage = self.storage.write().await;
+        if storage.contains_key(&key) {
+            return Ok(false);
+        }
+        let entry = self.compress(&value)?;
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::Put(&key, &value)]).await?;
+        storage.insert(key, entry);
+        Ok(true)
+    }
+
+    pub async fn get_set(&self, key: String, value: String) -> CacheResult<Option<String>> {
+        let entry = self.compress(&value)?;
+        let _keys = self.lock_keys(&[&key]).await;
+        self.load_from_store(&key).await?;
+        let storage = self.storage.write().await;
+        self.check_quota(&storage, &key, &entry)?;
+        let old = storage.get(&key).map(CacheEntry::get_value).transpose()?;
+        let mut storage = self.write_through(storage, &[StoreWrite::Put(&key, &value)]).await?;
+        storage.insert(key, entry);
+        Ok(old)
+    }
+
+    pub async fn compare_and_swap(&self, key: String, expected: &str, value: String) -> CacheResult<bool> {
+        let _keys = self.lock_keys(&[&key]).await;
+        self.load_from_store(&key).await?;
+        let storage = self.storage.write().await;
+        let matches = match storage.get(&key) {
+            Some(entry) => entry.get_value()? == expected,
+            None => false,
+        };
+        if !matches {
+            return Ok(false);
+        }
+        let entry = self.compress(&value)?;
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::Put(&key, &value)]).await?;
+        storage.insert(key, entry);
+        Ok(true)
+    }
+
+    /// SET for a value that was spooled to disk. It is compressed straight
+    /// from the file, so the uncompressed value is never held in memory.
+    pub async fn set_spooled(&self, key: String, value: SpooledValue) -> CacheResult<()> {
+        let level = self.compression_level.load(Ordering::Relaxed);
+        let compressed = tokio::task::spawn_blocking(move || value.compress(level))
+            .await
+            .map_err(|e| CacheError::CompressionError(e.to_string()))?
+            .map_err(|e| CacheError::CompressionError(e.to_string()))?;
+        let entry = CacheEntry::from_encoded(compressed, level);
+
+        let _keys = self.lock_keys(&[&key]).await;
+        let storage = self.storage.write().await;
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::PutCompressed(&key, &entry)]).await?;
+        storage.insert(key, entry);
+        Ok(())
+    }
+
+    /// Appends to the value, creating the key if needed, and returns the new
+    /// length in bytes. The value is rewritten under the write lock, or with
+    /// write-through the key's lock, so concurrent appends are never lost.
+    pub async fn append(&self, key: String, suffix: &str) -> CacheResult<usize> {
+        let _keys = self.lock_keys(&[&key]).await;
+        self.load_from_store(&key).await?;
+        let storage = self.storage.write().await;
+        let mut value = match storage.get(&key) {
+            Some(entry) => entry.get_value()?,
+            None => String::new(),
+        };
+        value.push_str(suffix);
+        let entry = self.compress(&value)?;
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::Put(&key, &value)]).await?;
+        storage.insert(key, entry);
+        Ok(value.len())
+    }
+
+    /// Length of the value in bytes, 0 for a missing key.
+    pub async fn strlen(&self, key: &str) -> CacheResult<usize> {
+        match self.get(key).await {
+            Ok(value) => Ok(value.len()),
+            Err(CacheError::KeyNotFound(_)) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Bytes `start..=end` of the value. Negative offsets count from the end
+    /// and out of range offsets are clamped, so a missing key or an empty
+    /// range gives an empty string.
+    pub async fn get_range(&self, key: &str, start: i64, end: i64) -> CacheResult<String> {
+        let value = match self.get(key).await {
+            Ok(value) => value,
+            Err(CacheError::KeyNotFound(_)) => return Ok(String::new()),
+            Err(e) => return Err(e),
+        };
+
+        let len = value.len() as i64;
+        let resolve = |offset: i64| if offset < 0 { (len + offset).max(0) } else { offset };
+        let start = resolve(start);
+        let end = resolve(end).min(len - 1);
+        if start > end {
+            return Ok(String::new());
+        }
+        // A range can split a multi-byte character, which is then replaced
+        Ok(String::from_utf8_lossy(&value.as_bytes()[start as usize..=end as usize]).into_owned())
+    }
+
     pub async fn get(&self, key: &str) -> CacheResult<String> {
-        let storage = self.storage.read().await;
-        match storage.get(key) {
-            Some(entry) => {
-                let value = entry.get_value()?;
+        // Decompress after releasing the lock so large values don't hold up writers
+        let cached = self.storage.read().await.get(key).map(|entry| {
+            entry.touch();
+            entry.clone()
+        });
+        if let Some(entry) = cached {
+            return entry.get_value();
+        }
+
+        let Some(store) = &self.backing_store else {
+            return Err(CacheError::KeyNotFound(key.to_string()));
+        };
+        // With write-through a SET or DEL racing the load waits for the key's
+        // lock, so a value it replaced or removed is never cached again
+        let _keys = self.lock_keys(&[key]).await;
+        let loaded = store.get(key).await
+            .map_err(|e| CacheError::BackingStoreError(e.to_string()))?;
+        match loaded {
+            Some(value) => {
+                let entry = self.compress(&value)?;
+                let mut storage = self.storage.write().await;
+                if !storage.contains_key(key) {
+                    storage.insert(key.to_string(), entry);
+                }
                 Ok(value)
             }
             None => {
@@ -75,21 +880,319 @@ impl Hydrogen {
         }
     }
 
+    /// Reads several keys like `get`, with `None` for the missing ones.
+    pub async fn get_many(&self, keys: &[String]) -> CacheResult<Vec<Option<String>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            match self.get(key).await {
+                Ok(value) => values.push(Some(value)),
+                Err(CacheError::KeyNotFound(_)) => values.push(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(values)
+    }
+
     pub async fn delete(&self, key: &str) -> CacheResult<bool> {
+        let _keys = self.lock_keys(&[key]).await;
+        if let Some(store) = self.write_through_store() {
+            store.delete(key).await
+                .map_err(|e| CacheError::BackingStoreError(e.to_string()))?;
+        }
         let mut storage = self.storage.write().await;
         let existed = storage.remove(key).is_some();
         Ok(existed)
     }
 
+    pub async fn delete_if(&self, key: &str, expected: &str) -> CacheResult<bool> {
+        let _keys = self.lock_keys(&[key]).await;
+        self.load_from_store(key).await?;
+        let storage = self.storage.write().await;
+        let matches = match storage.get(key) {
+            Some(entry) => entry.get_value()? == expected,
+            None => false,
+        };
+        if matches {
+            let mut storage = self.write_through(storage, &[StoreWrite::Delete(key)]).await?;
+            storage.remove(key);
+        }
+        Ok(matches)
+    }
+
+    /// The version of the key's value, or None for a missing key.
+    pub async fn version(&self, key: &str) -> CacheResult<Option<u64>> {
+        self.load_from_store(key).await?;
+        Ok(self.storage.read().await.get(key).map(CacheEntry::version))
+    }
+
+    /// Deletes the key only if its value is still the one stored at
+    /// `version`, so a client can release what it wrote without removing a
+    /// later write.
+    pub async fn delete_if_version(&self, key: &str, version: u64) -> CacheResult<bool> {
+        let _keys = self.lock_keys(&[key]).await;
+        self.load_from_store(key).await?;
+        let storage = self.storage.write().await;
+        let matches = storage.get(key).is_some_and(|entry| entry.version() == version);
+        if matches {
+            let mut storage = self.write_through(storage, &[StoreWrite::Delete(key)]).await?;
+            storage.remove(key);
+        }
+        Ok(matches)
+    }
+
+    /// Whether the key is in memory or, failing that, in the backing store.
+    pub async fn exists(&self, key: &str) -> CacheResult<bool> {
+        if self.storage.read().await.contains_key(key) {
+            return Ok(true);
+        }
+        let Some(store) = &self.backing_store else {
+            return Ok(false);
+        };
+        let loaded = store.get(key).await
+            .map_err(|e| CacheError::BackingStoreError(e.to_string()))?;
+        Ok(loaded.is_some())
+    }
+
+    // Every value is a string today, so the type only says whether the key exists
+    pub async fn key_type(&self, key: &str) -> CacheResult<&'static str> {
+        Ok(if self.exists(key).await? { "string" } else { "none" })
+    }
+
+    pub async fn rename(&self, key: &str, new_key: String) -> CacheResult<()> {
+        let _keys = self.lock_keys(&[key, &new_key]).await;
+        self.load_from_store(key).await?;
+        let storage = self.storage.write().await;
+        let Some(entry) = storage.get(key) else {
+            return Err(CacheError::KeyNotFound(key.to_string()));
+        };
+        if key == new_key {
+            return Ok(());
+        }
+        if namespace_of(key) != namespace_of(&new_key) {
+            self.check_quota(&storage, &new_key, entry)?;
+        }
+        let value = match self.write_through_store() {
+            Some(_) => entry.get_value()?,
+            None => String::new(),
+        };
+        let writes = [StoreWrite::Put(&new_key, &value), StoreWrite::Delete(key)];
+        let mut storage = self.write_through(storage, &writes).await?;
+        if let Some(entry) = storage.remove(key) {
+            storage.insert(new_key, entry);
+        }
+        Ok(())
+    }
+
+    pub async fn len(&self) -> usize {
+        let storage = self.storage.read().await;
+        storage.len()
+    }
+
+    /// Compression totals per namespace, with "" for keys outside any
+    /// namespace.
+    pub async fn compression_stats(&self) -> HashMap<String, CompressionStats> {
+        let storage = self.storage.read().await;
+        storage.compression.clone()
+    }
+
+    pub async fn interning_stats(&self) -> InterningStats {
+        let storage = self.storage.read().await;
+        storage.interning_stats()
+    }
+
+    pub async fn value_type_stats(&self) -> HashMap<ValueType, CompressionStats> {
+        let storage = self.storage.read().await;
+        storage.value_types.clone()
+    }
+
+    /// Clears the keyspace. The backing store is left untouched so a flush
+    /// only drops cached copies.
+    pub async fn flush(&self) -> usize {
+        let mut storage = self.storage.write().await;
+        let removed = storage.len();
+        storage.clear();
+        removed
+    }
+
+    /// Swaps in an empty map under the lock and frees the old one on a
+    /// blocking thread, so large keyspaces don't stall other clients.
+    pub async fn flush_async(&self) -> usize {
+        let old = {
+            let mut storage = self.storage.write().await;
+            let emptied = Keyspace { last_version: storage.last_version, ..Keyspace::default() };
+            std::mem::replace(&mut *storage, emptied)
+        };
+        let removed = old.len();
+        tokio::task::spawn_blocking(move || drop(old));
+        removed
+    }
+
     pub async fn keys(&self) -> CacheResult<Vec<String>> {
         let storage = self.storage.read().await;
-        let keys: Vec<String> = storage.keys().cloned().collect();
+        let keys: Vec<String> = storage.keys().map(InternedKey::to_string).collect();
         Ok(keys)
     }
 
+    pub async fn get_prefix(&self, prefix: &str, limit: usize) -> CacheResult<Vec<(String, String)>> {
+        let entries: Vec<(String, CacheEntry)> = {
+            let storage = self.storage.read().await;
+            let mut keys: Vec<String> = storage.keys()
+                .filter(|k| k.starts_with(prefix))
+                .map(InternedKey::to_string)
+                .collect();
+            keys.sort();
+            keys.into_iter()
+                .take(limit)
+                .filter_map(|key| storage.get(&key).map(|entry| {
+                    entry.touch();
+                    (key.clone(), entry.clone())
+                }))
+                .collect()
+        };
 
+        let mut pairs = Vec::with_capacity(entries.len());
+        for (key, entry) in entries {
+            pairs.push((key, entry.get_value()?));
+        }
+        Ok(pairs)
+    }
+
+    /// Entries of at least `min_size` compressed bytes, stored below `level`
+    /// and not read for `min_idle`. Used by background recompression.
+    pub async fn cold_entries(&self, min_size: usize, min_idle: Duration, level: i32) -> Vec<(String, CacheEntry)> {
+        let storage = self.storage.read().await;
+        storage.iter()
+            .filter(|(_, entry)| {
+                entry.is_compressed()
+                    && entry.stored_size() >= min_size
+                    && entry.compression_level < level
+                    && entry.idle_for() >= min_idle
+            })
+            .map(|(key, entry)| (key.to_string(), entry.clone()))
+            .collect()
+    }
+
+    /// Swaps in `replacement` only if the key still holds `original`, so a
+    /// write that raced with recompression is never undone.
+    pub async fn replace_if_unchanged(&self, key: &str, original: &CacheEntry, replacement: CacheEntry) -> bool {
+        let mut storage = self.storage.write().await;
+        let unchanged = storage.get(key).is_some_and(|current| current.same_data(original));
+        if unchanged {
+            storage.insert(key.to_string(), replacement);
+        }
+        unchanged
+    }
+
+    /// Entries idle for at least the time `idle_limit` gives for their key.
+    /// Keys it returns `None` for are skipped. Used by retention.
+    pub async fn idle_entries(&self, idle_limit: impl Fn(&str) -> Option<Duration>) -> Vec<(String, CacheEntry)> {
+        let storage = self.storage.read().await;
+        storage.iter()
+            .map(|(key, entry)| (key.to_string(), entry))
+            .filter(|(key, entry)| idle_limit(key).is_some_and(|limit| entry.idle_for() >= limit))
+            .map(|(key, entry)| (key, entry.clone()))
+            .collect()
+    }
+
+    /// Drops the key only if it still holds `original`, leaving the backing
+    /// store untouched like `flush_namespace`. Used once retention has
+    /// archived the value.
+    pub async fn remove_if_unchanged(&self, key: &str, original: &CacheEntry) -> bool {
+        let mut storage = self.storage.write().await;
+        let unchanged = storage.get(key).is_some_and(|current| current.same_data(original));
+        if unchanged {
+            storage.remove(key);
+        }
+        unchanged
+    }
 
+    pub async fn namespace_keys(&self, namespace: &str) -> Vec<String> {
+        let storage = self.storage.read().await;
+        storage.keys()
+            .filter(|key| key.namespace() == Some(namespace))
+            .map(InternedKey::to_string)
+            .collect()
+    }
+
+    /// Drops every key of one namespace, leaving the backing store untouched
+    /// like `flush`.
+    pub async fn flush_namespace(&self, namespace: &str) -> usize {
+        let mut storage = self.storage.write().await;
+        let keys: Vec<String> = storage.keys()
+            .filter(|key| key.namespace() == Some(namespace))
+            .map(InternedKey::to_string)
+            .collect();
+        for key in &keys {
+            storage.remove(key);
+        }
+        keys.len()
+    }
+
+    /// The stored entry without decompressing it. Unlike `get` this never
+    /// loads from the backing store.
+    pub async fn get_entry(&self, key: &str) -> Option<CacheEntry> {
+        self.storage.read().await.get(key).map(|entry| {
+            entry.touch();
+            entry.clone()
+        })
+    }
+
+    pub async fn dump(&self, key: &str) -> CacheResult<Bytes> {
+        let storage = self.storage.read().await;
+        match storage.get(key) {
+            Some(entry) => entry.compressed(),
+            None => Err(CacheError::KeyNotFound(key.to_string())),
+        }
+    }
+
+    pub async fn restore(&self, key: String, compressed_data: Vec<u8>, replace: bool) -> CacheResult<()> {
+        let entry = CacheEntry::from_compressed(compressed_data, self.max_value_bytes())?;
+        let _keys = self.lock_keys(&[&key]).await;
+        let storage = self.storage.write().await;
+        if !replace && storage.contains_key(&key) {
+            return Err(CacheError::KeyExists(key));
+        }
+        self.check_quota(&storage, &key, &entry)?;
+        let mut storage = self.write_through(storage, &[StoreWrite::PutCompressed(&key, &entry)]).await?;
+        storage.insert(key, entry);
+        Ok(())
+    }
+
+    pub async fn snapshot(&self) -> Vec<(String, CacheEntry)> {
+        let storage = self.storage.read().await;
+        storage.iter().map(|(key, entry)| (key.to_string(), entry.clone())).collect()
+    }
+
+    pub async fn measure_matching<F: Fn(&str) -> bool>(&self, matches: F) -> (usize, usize) {
+        let storage = self.storage.read().await;
+        storage.iter()
+            .map(|(key, entry)| (key.to_string(), entry))
+            .filter(|(key, _)| matches(key))
+            .fold((0, 0), |(keys, bytes), (key, entry)| {
+                (keys + 1, bytes + key.len() + entry.stored_size())
+            })
+    }
+
+    pub async fn take_matching<F: Fn(&str) -> bool>(&self, matches: F) -> CacheResult<Vec<(String, CacheEntry)>> {
+        let mut storage = self.storage.write().await;
+        let keys: Vec<String> = storage.keys()
+            .map(InternedKey::to_string)
+            .filter(|k| matches(k))
+            .collect();
+        let entries = keys.into_iter()
+            .filter_map(|key| storage.remove(&key).map(|entry| (key, entry)))
+            .collect();
+        Ok(entries)
+    }
 
+    pub async fn restore_entries(&self, entries: Vec<(String, CacheEntry)>) {
+        let mut storage = self.storage.write().await;
+        for (key, entry) in entries {
+            if !storage.contains_key(&key) {
+                storage.insert(key, entry);
+            }
+        }
+    }
 }
 
 impl Default for Hydrogen {
@@ -98,3 +1201,115 @@ impl Default for Hydrogen {
     }
 }
 
+/// The numbered logical databases of one server. Each is an independent
+/// keyspace; connections pick one with SELECT and start on database 0.
+#[derive(Debug)]
+pub struct Databases {
+    databases: Vec<Arc<Hydrogen>>,
+    batchers: OnceLock<Vec<WriteBatcher>>,
+}
+
+impl Databases {
+    // Only database 0 is attached to the backing store, whose keys have no
+    // database component
+    pub fn new(count: usize, backing_store: Option<Arc<dyn BackingStore>>, write_through: bool) -> Self {
+        let databases = (0..count.max(1))
+            .map(|index| match &backing_store {
+                Some(store) if index == 0 => Hydrogen::with_backing_store(Arc::clone(store), write_through),
+                _ => Hydrogen::new(),
+            })
+            .map(Arc::new)
+            .collect();
+        Self { databases, batchers: OnceLock::new() }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Hydrogen> {
+        self.databases.get(index).map(|database| &**database)
+    }
+
+    /// Like `get`, for holding on to the database beyond a borrow, as a
+    /// spawned command does
+    pub fn shared(&self, index: usize) -> Option<Arc<Hydrogen>> {
+        self.databases.get(index).cloned()
+    }
+
+    /// Starts one write applier per database. Only the first call has any
+    /// effect; whether SETs go through them is decided per request.
+    pub fn start_write_batching(&self, queue_len: usize, max_batch: usize, supervisor: &Supervisor) {
+        self.batchers.get_or_init(|| {
+            self.databases
+                .iter()
+                .enumerate()
+                .map(|(index, database)| {
+                    let name = format!("write_batch:{}", index);
+                    WriteBatcher::spawn(Arc::clone(database), queue_len, max_batch, supervisor, name)
+                })
+                .collect()
+        });
+    }
+
+    pub fn batcher(&self, index: usize) -> Option<&WriteBatcher> {
+        self.batchers.get()?.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.databases.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Hydrogen> {
+        self.databases.iter().map(|database| &**database)
+    }
+
+    /// Compression totals per namespace across all databases.
+    pub async fn compression_stats(&self) -> HashMap<String, CompressionStats> {
+        let mut merged: HashMap<String, CompressionStats> = HashMap::new();
+        for database in &self.databases {
+            for (prefix, stats) in database.compression_stats().await {
+                merged.entry(prefix).or_default().merge(&stats);
+            }
+        }
+        merged
+    }
+
+    /// Key prefix sharing summed over all databases.
+    pub async fn interning_stats(&self) -> InterningStats {
+        let mut total = InterningStats::default();
+        for database in &self.databases {
+            let stats = database.interning_stats().await;
+            total.prefixes += stats.prefixes;
+            total.keys += stats.keys;
+            total.bytes_saved += stats.bytes_saved;
+        }
+        total
+    }
+
+    /// How many values of each type are stored across all databases.
+    pub async fn value_type_stats(&self) -> HashMap<ValueType, CompressionStats> {
+        let mut merged: HashMap<ValueType, CompressionStats> = HashMap::new();
+        for database in &self.databases {
+            for (value_type, stats) in database.value_type_stats().await {
+                merged.entry(value_type).or_default().merge(&stats);
+            }
+        }
+        merged
+    }
+
+    pub fn set_compression_level(&self, level: i32) {
+        for database in &self.databases {
+            database.set_compression_level(level);
+        }
+    }
+
+    pub fn set_max_value_bytes(&self, max: usize) {
+        for database in &self.databases {
+            database.set_max_value_bytes(max);
+        }
+    }
+
+    pub fn set_namespace_quotas(&self, quotas: &HashMap<String, NamespaceQuota>) {
+        for database in &self.databases {
+            database.set_namespace_quotas(quotas.clone());
+        }
+    }
+}
+
@@ -2,40 +2,762 @@
 // A scalable and lightweight Key Value Cache written in Rust
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinSet;
+use crate::api::quote_key;
+use crate::cache::{CacheEntry, Hydrogen};
 use crate::configuration::{ConfigError, HydrogenConfig};
 use crate::node_id;
+use crate::slots::{key_slot, SLOT_COUNT};
+use crate::snapshot::{self, SnapshotError};
 
 type ConfigResult<T> = Result<T, ConfigError>;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const CLUSTER_FILE: &str = "cluster.json";
+// Advisory lock held while cluster.json is read or replaced. It is separate
+// from the file itself because writes rename a new file into place.
+const CLUSTER_LOCK_FILE: &str = "cluster.json.lock";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClusterError {
+    #[error("Unknown node: {0}")]
+    UnknownNode(String),
+    #[error("Node already in cluster: {0}")]
+    DuplicateNode(String),
+    #[error("Invalid slot range: {0}")]
+    InvalidSlotRange(String),
+    #[error("Slots {0}-{1} are not owned by {2}")]
+    SlotsNotOwned(u32, u32, String),
+    #[error("RESHARD must be issued on the source node {0}")]
+    NotSourceNode(String),
+    #[error("Handshake with {0} failed: {1}")]
+    HandshakeFailed(String, String),
+    #[error("Migration to {0} failed: {1}")]
+    MigrationFailed(String, String),
+    #[error("No rebalance plan to apply, run CLUSTER PLAN REBALANCE first")]
+    NoPlan,
+    #[error("Slot {0} is not assigned to any node")]
+    UnassignedSlot(u32),
+    #[error("Read from {0} failed: {1}")]
+    ReadFailed(String, String),
+    #[error("Cache error: {0}")]
+    Cache(#[from] crate::cache::CacheError),
+    #[error("Config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] SnapshotError),
+}
+
+type ClusterResult<T> = Result<T, ClusterError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterNode {
     pub node_id: String,
     pub node_validation: u32,
     pub address: String,
-    pub slots: [u32; 2],
+    #[serde(deserialize_with = "deserialize_slots")]
+    pub slots: Vec<[u32; 2]>,
+}
+
+// Files from before nodes could own several ranges hold a single one,
+// `"slots": [0, 16383]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SlotRanges {
+    Single([u32; 2]),
+    Ranges(Vec<[u32; 2]>),
+}
+
+fn deserialize_slots<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<[u32; 2]>, D::Error> {
+    Ok(match SlotRanges::deserialize(deserializer)? {
+        SlotRanges::Single(range) => vec![range],
+        SlotRanges::Ranges(ranges) => ranges,
+    })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterConfig {
     pub cluster_validation: u32,
+    /// Bumped on every write, so a writer holding an older copy is refused
+    /// instead of overwriting a newer one. Files from before versioning
+    /// count as version 0.
+    #[serde(default)]
+    pub version: u64,
     pub nodes: Vec<ClusterNode>,
 }
 
-pub fn generate_cluster_file(config: &HydrogenConfig) -> ConfigResult<()> {
-    let cluster_node = ClusterNode {
-        node_id: node_id::generate_node_id(),
-        node_validation: 0,
-        address: config.bind_address(),
-        slots: [0, 16383],
-    };
+/// Creates cluster.json with the local node owning every slot when it does
+/// not exist yet. An existing file is loaded and checked instead, so the
+/// nodes and slot assignments it holds survive a restart.
+pub fn prepare_cluster_file(config: &HydrogenConfig) -> ConfigResult<()> {
+    let _lock = lock_cluster_file(true)?;
+    if Path::new(CLUSTER_FILE).exists() {
+        return validate_cluster_file(&read_cluster_file()?, &config.bind_address());
+    }
 
     let cluster_config = ClusterConfig {
         cluster_validation: 0,
-        nodes: vec![cluster_node],
+        version: 1,
+        nodes: vec![ClusterNode {
+            node_id: node_id::node_id_for(config)?,
+            node_validation: 0,
+            address: config.bind_address(),
+            slots: vec![[0, SLOT_COUNT - 1]],
+        }],
     };
+    write_cluster_file(&cluster_config)
+}
+
+// Refuses a file this node could not serve from: one it is not part of, or
+// whose slot ranges are out of bounds or owned twice
+fn validate_cluster_file(cluster_config: &ClusterConfig, local_address: &str) -> ConfigResult<()> {
+    if !cluster_config.nodes.iter().any(|node| node.address == local_address) {
+        return Err(ConfigError::InvalidValue(format!(
+            "{} has no node at {}, fix the address or remove the file to start a new cluster",
+            CLUSTER_FILE, local_address
+        )));
+    }
 
-    let content = serde_json::to_string_pretty(&cluster_config)?;
-    fs::write("cluster.json", content)?;
+    let mut ranges: Vec<[u32; 2]> = cluster_config.nodes.iter().flat_map(|node| node.slots.iter().copied()).collect();
+    ranges.sort();
+    if let Some([start, end]) = ranges.iter().find(|[start, end]| start > end || *end >= SLOT_COUNT) {
+        return Err(ConfigError::InvalidValue(format!("{} has an invalid slot range {}-{}", CLUSTER_FILE, start, end)));
+    }
+    if let Some(pair) = ranges.windows(2).find(|pair| pair[1][0] <= pair[0][1]) {
+        return Err(ConfigError::InvalidValue(format!(
+            "{} assigns slots {}-{} to more than one node", CLUSTER_FILE, pair[1][0], pair[0][1].min(pair[1][1])
+        )));
+    }
     Ok(())
-} 
\ No newline at end of file
+}
+
+fn lock_cluster_file(exclusive: bool) -> ConfigResult<fs::File> {
+    let lock = fs::OpenOptions::new().create(true).truncate(false).write(true).open(CLUSTER_LOCK_FILE)?;
+    if exclusive {
+        lock.lock()?;
+    } else {
+        lock.lock_shared()?;
+    }
+    Ok(lock)
+}
+
+fn read_cluster_file() -> ConfigResult<ClusterConfig> {
+    let content = fs::read_to_string(CLUSTER_FILE)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn load_cluster_file() -> ConfigResult<ClusterConfig> {
+    let _lock = lock_cluster_file(false)?;
+    read_cluster_file()
+}
+
+// Writes through a temporary file and a rename, so readers never see a
+// partly written file
+fn write_cluster_file(cluster_config: &ClusterConfig) -> ConfigResult<()> {
+    let content = serde_json::to_string_pretty(cluster_config)?;
+    let temp_path = format!("{}.tmp", CLUSTER_FILE);
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, CLUSTER_FILE)?;
+    Ok(())
+}
+
+// Fails if cluster.json was written by someone else since `state` was read.
+// The newer file replaces `state` in place, so a retry works from it without
+// a restart.
+fn check_cluster_version(state: &mut ClusterConfig) -> ConfigResult<()> {
+    match read_cluster_file() {
+        Ok(on_disk) if on_disk.version != state.version => {
+            let message = format!(
+                "{} is at version {} but this node had version {}, reloaded it, retry the command",
+                CLUSTER_FILE, on_disk.version, state.version
+            );
+            *state = on_disk;
+            Err(ConfigError::Conflict(message))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Applies `update` to a copy of `state` and saves it as the next version.
+/// The save is refused if the file on disk is no longer the version `state`
+/// was loaded from, in which case `state` becomes the file on disk without
+/// `update`. Otherwise `state` is only changed once the write succeeded.
+fn commit_cluster_file<F: FnOnce(&mut ClusterConfig)>(state: &mut ClusterConfig, update: F) -> ConfigResult<()> {
+    let _lock = lock_cluster_file(true)?;
+    check_cluster_version(state)?;
+
+    let mut next = state.clone();
+    update(&mut next);
+    next.version = state.version + 1;
+    write_cluster_file(&next)?;
+    *state = next;
+    Ok(())
+}
+
+pub fn parse_slot_range(range: &str) -> ClusterResult<(u32, u32)> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (range, range),
+    };
+
+    let start: u32 = start.parse().map_err(|_| ClusterError::InvalidSlotRange(range.to_string()))?;
+    let end: u32 = end.parse().map_err(|_| ClusterError::InvalidSlotRange(range.to_string()))?;
+    if start > end || end >= SLOT_COUNT {
+        return Err(ClusterError::InvalidSlotRange(range.to_string()));
+    }
+    Ok((start, end))
+}
+
+fn owns_range(slots: &[[u32; 2]], start: u32, end: u32) -> bool {
+    slots.iter().any(|[s, e]| *s <= start && end <= *e)
+}
+
+fn remove_range(slots: &mut Vec<[u32; 2]>, start: u32, end: u32) {
+    let mut remaining = Vec::with_capacity(slots.len() + 1);
+    for &[s, e] in slots.iter() {
+        if e < start || s > end {
+            remaining.push([s, e]);
+            continue;
+        }
+        if s < start {
+            remaining.push([s, start - 1]);
+        }
+        if e > end {
+            remaining.push([end + 1, e]);
+        }
+    }
+    *slots = remaining;
+}
+
+fn add_range(slots: &mut Vec<[u32; 2]>, start: u32, end: u32) {
+    slots.push([start, end]);
+    slots.sort();
+
+    let mut merged: Vec<[u32; 2]> = Vec::with_capacity(slots.len());
+    for &[s, e] in slots.iter() {
+        match merged.last_mut() {
+            Some(last) if s <= last[1] + 1 => last[1] = last[1].max(e),
+            _ => merged.push([s, e]),
+        }
+    }
+    *slots = merged;
+}
+
+// Hands a slot range from one node to another in the slot table
+fn move_range(state: &mut ClusterConfig, from: &str, to: &str, start: u32, end: u32) {
+    for node in state.nodes.iter_mut() {
+        if node.node_id == from {
+            remove_range(&mut node.slots, start, end);
+        } else if node.node_id == to {
+            add_range(&mut node.slots, start, end);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedMove {
+    pub from: String,
+    pub to: String,
+    pub start: u32,
+    pub end: u32,
+    pub keys: Option<usize>,
+    pub bytes: Option<usize>,
+}
+
+fn slot_count(slots: &[[u32; 2]]) -> u32 {
+    slots.iter().map(|[start, end]| end - start + 1).sum()
+}
+
+/// Computes the slot ranges to move so every node ends up owning an equal
+/// share of the slots, taking from the top of each donor's ranges.
+fn rebalance_moves(nodes: &[ClusterNode]) -> Vec<(String, String, u32, u32)> {
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+
+    let mut nodes: Vec<&ClusterNode> = nodes.iter().collect();
+    nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+
+    let base = SLOT_COUNT / nodes.len() as u32;
+    let remainder = SLOT_COUNT % nodes.len() as u32;
+    let target = |index: usize| base + u32::from((index as u32) < remainder);
+
+    let mut donors: Vec<(String, Vec<[u32; 2]>, u32)> = Vec::new();
+    let mut receivers: Vec<(String, u32)> = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let owned = slot_count(&node.slots);
+        let wanted = target(index);
+        if owned > wanted {
+            donors.push((node.node_id.clone(), node.slots.clone(), owned - wanted));
+        } else if owned < wanted {
+            receivers.push((node.node_id.clone(), wanted - owned));
+        }
+    }
+
+    let mut moves = Vec::new();
+    let mut receivers = receivers.into_iter();
+    let mut current = receivers.next();
+    for (donor, mut ranges, mut surplus) in donors {
+        while surplus > 0 {
+            let Some((receiver, deficit)) = current.as_mut() else {
+                return moves;
+            };
+            let Some(range) = ranges.last_mut() else {
+                break;
+            };
+
+            let available = range[1] - range[0] + 1;
+            let chunk = surplus.min(*deficit).min(available);
+            let start = range[1] + 1 - chunk;
+            moves.push((donor.clone(), receiver.clone(), start, range[1]));
+
+            if chunk == available {
+                ranges.pop();
+            } else {
+                range[1] = start - 1;
+            }
+            surplus -= chunk;
+            *deficit -= chunk;
+            if *deficit == 0 {
+                current = receivers.next();
+            }
+        }
+    }
+    moves
+}
+
+/// Paces a transfer so its average rate stays under a bytes-per-second limit.
+/// A limit of zero disables throttling.
+struct Throttle {
+    bytes_per_second: u64,
+    started: Instant,
+    sent: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            started: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    async fn consume(&mut self, bytes: usize) {
+        if self.bytes_per_second == 0 {
+            return;
+        }
+
+        self.sent += bytes as u64;
+        let expected = Duration::from_secs_f64(self.sent as f64 / self.bytes_per_second as f64);
+        let elapsed = self.started.elapsed();
+        if expected > elapsed {
+            tokio::time::sleep(expected - elapsed).await;
+        }
+    }
+}
+
+pub struct Cluster {
+    local_node_id: String,
+    migration_bandwidth_limit: u64,
+    state: RwLock<ClusterConfig>,
+    pending_plan: Mutex<Option<Vec<PlannedMove>>>,
+}
+
+impl Cluster {
+    pub fn load(config: &HydrogenConfig) -> ConfigResult<Self> {
+        let cluster_config = load_cluster_file()?;
+
+        let local_address = config.bind_address();
+        let local_node_id = cluster_config.nodes.iter()
+            .find(|node| node.address == local_address)
+            .map(|node| node.node_id.clone())
+            .unwrap_or_default();
+
+        Ok(Self {
+            local_node_id,
+            migration_bandwidth_limit: config.migration_bandwidth_limit,
+            state: RwLock::new(cluster_config),
+            pending_plan: Mutex::new(None),
+        })
+    }
+
+    pub fn local_node_id(&self) -> &str {
+        &self.local_node_id
+    }
+
+    pub async fn meet(&self, address: &str) -> ClusterResult<String> {
+        if address.parse::<SocketAddr>().is_err() {
+            return Err(ClusterError::UnknownNode(address.to_string()));
+        }
+
+        let mut state = self.state.write().await;
+        if state.nodes.iter().any(|node| node.address == address) {
+            return Err(ClusterError::DuplicateNode(address.to_string()));
+        }
+
+        let node_id = Self::send_request(address, "CLUSTER MYID").await
+            .map_err(|e| ClusterError::HandshakeFailed(address.to_string(), e.to_string()))?;
+        if state.nodes.iter().any(|node| node.node_id == node_id) {
+            return Err(ClusterError::DuplicateNode(node_id));
+        }
+
+        commit_cluster_file(&mut state, |state| {
+            state.nodes.push(ClusterNode {
+                node_id: node_id.clone(),
+                node_validation: 0,
+                address: address.to_string(),
+                slots: Vec::new(),
+            });
+        })?;
+        Ok(node_id)
+    }
+
+    pub async fn slots(&self) -> Vec<(u32, u32, String, String)> {
+        let state = self.state.read().await;
+        let mut ranges: Vec<(u32, u32, String, String)> = state.nodes.iter()
+            .flat_map(|node| {
+                node.slots.iter().map(move |[start, end]| {
+                    (*start, *end, node.node_id.clone(), node.address.clone())
+                })
+            })
+            .collect();
+        ranges.sort();
+        ranges
+    }
+
+    /// Moves a slot range from one node to another, shipping the affected keys
+    /// to the new owner before the slot table is updated.
+    pub async fn reshard(&self, cache: &Hydrogen, from: &str, to: &str, start: u32, end: u32) -> ClusterResult<usize> {
+        let mut state = self.state.write().await;
+        // Checked up front as well, so keys are not shipped for a slot table
+        // change that could not be saved
+        {
+            let _lock = lock_cluster_file(false)?;
+            check_cluster_version(&mut state)?;
+        }
+
+        let source = state.nodes.iter()
+            .find(|node| node.node_id == from)
+            .ok_or_else(|| ClusterError::UnknownNode(from.to_string()))?;
+        let target = state.nodes.iter()
+            .find(|node| node.node_id == to)
+            .ok_or_else(|| ClusterError::UnknownNode(to.to_string()))?;
+
+        if !owns_range(&source.slots, start, end) {
+            return Err(ClusterError::SlotsNotOwned(start, end, from.to_string()));
+        }
+        if from != self.local_node_id {
+            return Err(ClusterError::NotSourceNode(from.to_string()));
+        }
+        let target_address = target.address.clone();
+        let entries = cache.take_matching(|key| {
+            let slot = key_slot(key);
+            start <= slot && slot <= end
+        }).await?;
+
+        let migrated = entries.len();
+        if let Err(e) = Self::send_entries(&target_address, &entries, self.migration_bandwidth_limit).await {
+            cache.restore_entries(entries).await;
+            return Err(ClusterError::MigrationFailed(target_address, e.to_string()));
+        }
+
+        commit_cluster_file(&mut state, |state| move_range(state, from, to, start, end))?;
+
+        Ok(migrated)
+    }
+
+    /// Writes the keys of a slot range owned by this node to an export file,
+    /// so the range can later be restored on its own.
+    pub async fn backup_slots(&self, cache: &Hydrogen, start: u32, end: u32, path: &str) -> ClusterResult<usize> {
+        self.check_local_range(start, end).await?;
+        let count = snapshot::export_matching(cache, path, |key| {
+            let slot = key_slot(key);
+            start <= slot && slot <= end
+        }).await?;
+        Ok(count)
+    }
+
+    /// Loads the keys of one slot range from an export file, skipping every
+    /// other key in it. Existing keys in the range are overwritten; keys
+    /// outside it are left alone. `check_key` refuses keys the server would
+    /// not accept.
+    pub async fn restore_slots(
+        &self,
+        cache: &Hydrogen,
+        start: u32,
+        end: u32,
+        path: &str,
+        check_key: impl Fn(&str) -> Result<(), String>,
+    ) -> ClusterResult<usize> {
+        self.check_local_range(start, end).await?;
+        let count = snapshot::import_matching(cache, path, |key| {
+            let slot = key_slot(key);
+            start <= slot && slot <= end
+        }, check_key).await?;
+        Ok(count)
+    }
+
+    async fn check_local_range(&self, start: u32, end: u32) -> ClusterResult<()> {
+        let state = self.state.read().await;
+        let owned = state.nodes.iter()
+            .find(|node| node.node_id == self.local_node_id)
+            .is_some_and(|node| owns_range(&node.slots, start, end));
+        if !owned {
+            return Err(ClusterError::SlotsNotOwned(start, end, self.local_node_id.clone()));
+        }
+        Ok(())
+    }
+
+    pub async fn slot_stats(&self, cache: &Hydrogen, start: u32, end: u32) -> (usize, usize) {
+        cache.measure_matching(|key| {
+            let slot = key_slot(key);
+            start <= slot && slot <= end
+        }).await
+    }
+
+    pub async fn plan_rebalance(&self, cache: &Hydrogen) -> Vec<PlannedMove> {
+        let (nodes, moves) = {
+            let state = self.state.read().await;
+            (state.nodes.clone(), rebalance_moves(&state.nodes))
+        };
+
+        let mut plan = Vec::with_capacity(moves.len());
+        for (from, to, start, end) in moves {
+            let (keys, bytes) = if from == self.local_node_id {
+                let (keys, bytes) = self.slot_stats(cache, start, end).await;
+                (Some(keys), Some(bytes))
+            } else {
+                Self::remote_slot_stats(&nodes, &from, start, end).await
+            };
+            plan.push(PlannedMove { from, to, start, end, keys, bytes });
+        }
+
+        *self.pending_plan.lock().await = Some(plan.clone());
+        plan
+    }
+
+    async fn remote_slot_stats(nodes: &[ClusterNode], node_id: &str, start: u32, end: u32) -> (Option<usize>, Option<usize>) {
+        let Some(node) = nodes.iter().find(|node| node.node_id == node_id) else {
+            return (None, None);
+        };
+        let request = format!("CLUSTER SLOTSTATS {}-{}", start, end);
+        match Self::send_request(&node.address, &request).await {
+            Ok(reply) => {
+                let mut parts = reply.split_whitespace().map(|part| part.parse().ok());
+                (parts.next().flatten(), parts.next().flatten())
+            }
+            Err(_) => (None, None),
+        }
+    }
+
+    /// Executes the pending rebalance plan. Moves owned by this node migrate
+    /// their keys directly; the rest are forwarded to the owning node.
+    pub async fn apply_plan(&self, cache: &Hydrogen) -> ClusterResult<Vec<String>> {
+        let plan = self.pending_plan.lock().await.take().ok_or(ClusterError::NoPlan)?;
+
+        let mut results = Vec::with_capacity(plan.len());
+        for planned in plan {
+            let outcome = if planned.from == self.local_node_id {
+                self.reshard(cache, &planned.from, &planned.to, planned.start, planned.end).await
+                    .map(|migrated| migrated.to_string())
+                    .map_err(|e| e.to_string())
+            } else {
+                self.forward_reshard(&planned).await
+            };
+
+            results.push(match outcome {
+                Ok(migrated) => format!("{}-{} {}", planned.start, planned.end, migrated),
+                Err(e) => format!("{}-{} ERROR: {}", planned.start, planned.end, e),
+            });
+        }
+        Ok(results)
+    }
+
+    async fn forward_reshard(&self, planned: &PlannedMove) -> Result<String, String> {
+        let address = {
+            let state = self.state.read().await;
+            state.nodes.iter()
+                .find(|node| node.node_id == planned.from)
+                .map(|node| node.address.clone())
+                .ok_or_else(|| ClusterError::UnknownNode(planned.from.clone()).to_string())?
+        };
+
+        let request = format!("CLUSTER RESHARD {} {} {}-{}", planned.from, planned.to, planned.start, planned.end);
+        let migrated = Self::send_request(&address, &request).await.map_err(|e| e.to_string())?;
+
+        let mut state = self.state.write().await;
+        commit_cluster_file(&mut state, |state| {
+            move_range(state, &planned.from, &planned.to, planned.start, planned.end)
+        })
+        .map_err(|e| e.to_string())?;
+        Ok(migrated)
+    }
+
+    /// Reads keys wherever they live: this node's slots straight from
+    /// `cache`, every other node's with one CLUSTER MDUMP sent to all of them
+    /// at once. Values come back in the order of `keys`, `None` where a key
+    /// does not exist. A key in an unassigned slot or on an unreachable node
+    /// fails the whole read.
+    pub async fn mget(&self, cache: &Hydrogen, keys: &[String]) -> ClusterResult<Vec<Option<String>>> {
+        let mut local = Vec::new();
+        let mut remote: HashMap<String, Vec<usize>> = HashMap::new();
+        {
+            let state = self.state.read().await;
+            for (i, key) in keys.iter().enumerate() {
+                let slot = key_slot(key);
+                let owner = state.nodes.iter()
+                    .find(|node| owns_range(&node.slots, slot, slot))
+                    .ok_or(ClusterError::UnassignedSlot(slot))?;
+                if owner.node_id == self.local_node_id {
+                    local.push(i);
+                } else {
+                    remote.entry(owner.address.clone()).or_default().push(i);
+                }
+            }
+        }
+
+        let mut requests = JoinSet::new();
+        for (address, positions) in remote {
+            let quoted: Vec<String> = positions.iter().map(|&i| quote_key(&keys[i]).into_owned()).collect();
+            let request = format!("CLUSTER MDUMP {}", quoted.join(" "));
+            requests.spawn(async move {
+                let reply = Self::send_request(&address, &request).await;
+                (address, positions, reply)
+            });
+        }
+
+        let mut values = vec![None; keys.len()];
+        let local_keys: Vec<String> = local.iter().map(|&i| keys[i].clone()).collect();
+        for (i, value) in local.into_iter().zip(cache.get_many(&local_keys).await?) {
+            values[i] = value;
+        }
+        while let Some(joined) = requests.join_next().await {
+            let (address, positions, reply) = joined
+                .map_err(|e| ClusterError::ReadFailed(String::new(), e.to_string()))?;
+            let reply = reply.map_err(|e| ClusterError::ReadFailed(address.clone(), e.to_string()))?;
+            let payloads: Vec<&str> = reply.split(' ').collect();
+            if payloads.len() != positions.len() {
+                return Err(ClusterError::ReadFailed(address, format!("expected {} values", positions.len())));
+            }
+            for (i, payload) in positions.into_iter().zip(payloads) {
+                values[i] = Self::decode_dump(payload, cache.max_value_bytes()).map_err(|e| ClusterError::ReadFailed(address.clone(), e))?;
+            }
+        }
+        Ok(values)
+    }
+
+    // One value of a CLUSTER MDUMP reply: NULL or a DUMP payload
+    fn decode_dump(payload: &str, max_size: usize) -> Result<Option<String>, String> {
+        if payload == "NULL" {
+            return Ok(None);
+        }
+        let data = snapshot::decode_payload(payload).ok_or_else(|| "invalid payload".to_string())?;
+        let entry = CacheEntry::from_compressed(data, max_size).map_err(|e| e.to_string())?;
+        entry.get_value().map(Some).map_err(|e| e.to_string())
+    }
+
+    /// Sends `request` to every other node at once. Each node's reply, or
+    /// why it could not be reached, comes back with its node id, sorted by
+    /// node id.
+    pub async fn broadcast(&self, request: &str) -> Vec<(String, String)> {
+        let mut requests = JoinSet::new();
+        for node in self.state.read().await.nodes.iter().filter(|node| node.node_id != self.local_node_id) {
+            let (node_id, address, request) = (node.node_id.clone(), node.address.clone(), request.to_string());
+            requests.spawn(async move {
+                let reply = Self::send_line(&address, &request).await;
+                (node_id, reply.unwrap_or_else(|e| format!("ERROR: {} unreachable: {}", address, e)))
+            });
+        }
+
+        let mut replies = Vec::new();
+        while let Some(joined) = requests.join_next().await {
+            replies.extend(joined.ok());
+        }
+        replies.sort();
+        replies
+    }
+
+    async fn send_request(address: &str, request: &str) -> std::io::Result<String> {
+        let reply = Self::send_line(address, request).await?;
+        if reply.starts_with("ERROR") {
+            return Err(std::io::Error::other(format!("unexpected reply: {}", reply)));
+        }
+        Ok(reply)
+    }
+
+    // One request and its reply line, whatever the reply says
+    async fn send_line(address: &str, request: &str) -> std::io::Result<String> {
+        let mut stream = TcpStream::connect(address).await?;
+        stream.write_all(format!("{}\n", request).as_bytes()).await?;
+
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).await?;
+        let reply = response.trim();
+        if reply.is_empty() {
+            return Err(std::io::Error::other("no reply"));
+        }
+        Ok(reply.to_string())
+    }
+
+    async fn send_entries(address: &str, entries: &[(String, CacheEntry)], bandwidth_limit: u64) -> std::io::Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let stream = TcpStream::connect(address).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut response = String::new();
+        let mut throttle = Throttle::new(bandwidth_limit);
+
+        for (key, entry) in entries {
+            let value = entry.get_value().map_err(std::io::Error::other)?;
+            let request = format!("SET {} \"{}\"\n", quote_key(key), value);
+            throttle.consume(request.len()).await;
+            writer.write_all(request.as_bytes()).await?;
+
+            response.clear();
+            reader.read_line(&mut response).await?;
+            if response.trim() != "OK" {
+                return Err(std::io::Error::other(format!("SET {} rejected: {}", key, response.trim())));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_a_cluster_file_with_single_slot_ranges() {
+        let content = r#"{
+  "cluster_validation": 0,
+  "nodes": [
+    {
+      "node_id": "a1b2c3",
+      "node_validation": 0,
+      "address": "127.0.0.1:1825",
+      "slots": [0, 16383]
+    }
+  ]
+}"#;
+        let cluster_config: ClusterConfig = serde_json::from_str(content).unwrap();
+        assert_eq!(cluster_config.version, 0);
+        assert_eq!(cluster_config.nodes[0].slots, vec![[0, SLOT_COUNT - 1]]);
+        assert!(validate_cluster_file(&cluster_config, "127.0.0.1:1825").is_ok());
+
+        let saved = serde_json::to_string(&cluster_config).unwrap();
+        let reloaded: ClusterConfig = serde_json::from_str(&saved).unwrap();
+        assert_eq!(reloaded.nodes[0].slots, vec![[0, SLOT_COUNT - 1]]);
+    }
+}
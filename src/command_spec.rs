@@ -0,0 +1,80 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::fmt;
+
+/// Separates a reply from the warning the server adds when a deprecated
+/// command is used, a JSON object with the command and the deprecation
+/// message: `OK\t!DEPRECATED {"command":"...","message":"..."}`.
+pub const DEPRECATED_MARKER: &str = "\t!DEPRECATED ";
+
+/// One command of protocol.toml, as generated into `COMMANDS` by build.rs.
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// Upper case, with subcommands separated by spaces ("CLUSTER MEET")
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub args: &'static [ArgSpec],
+    /// Any of read, write, admin, slow and cluster
+    pub flags: &'static [&'static str],
+    /// Why the command is going away and what to use instead
+    pub deprecated: Option<&'static str>,
+}
+
+#[derive(Debug)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    /// Sent in double quotes, so the value may contain spaces
+    pub quoted: bool,
+    /// Keyword sent when a boolean argument is set, such as "ASYNC"
+    pub flag: Option<&'static str>,
+    pub optional: bool,
+    /// Takes one or more values; only ever the last argument
+    pub repeated: bool,
+    /// Sent before the value of an optional argument, as in "PREFIX staging:"
+    pub keyword: Option<&'static str>,
+}
+
+include!(concat!(env!("OUT_DIR"), "/command_specs.rs"));
+
+/// Finds the command a request starts with. "CLUSTER MEET host:port" finds
+/// CLUSTER MEET; "CLUSTER" alone finds nothing, see `group`.
+pub fn find(request: &str) -> Option<&'static CommandSpec> {
+    let words: Vec<&str> = request.split_whitespace().collect();
+    COMMANDS
+        .iter()
+        .filter(|spec| {
+            let name: Vec<&str> = spec.name.split(' ').collect();
+            name.len() <= words.len() && name.iter().zip(&words).all(|(part, word)| part.eq_ignore_ascii_case(word))
+        })
+        .max_by_key(|spec| spec.name.len())
+}
+
+/// Every command whose first word is `word`, so "CONFIG" gives CONFIG GET
+/// and CONFIG SET.
+pub fn group(word: &str) -> impl Iterator<Item = &'static CommandSpec> + '_ {
+    COMMANDS
+        .iter()
+        .filter(move |spec| spec.name.split(' ').next().is_some_and(|first| first.eq_ignore_ascii_case(word)))
+}
+
+/// The wire syntax, such as "FLUSHALL [ASYNC]", "SLOWLOG GET [count]",
+/// "IMPORT path [PREFIX prefix]", "MGET keys..." or "SET key \"value\"".
+impl fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in self.args {
+            match arg.flag {
+                Some(flag) => write!(f, " [{}]", flag)?,
+                None if arg.optional => match arg.keyword {
+                    Some(keyword) => write!(f, " [{} {}]", keyword, arg.name)?,
+                    None => write!(f, " [{}]", arg.name)?,
+                },
+                None if arg.repeated => write!(f, " {}...", arg.name)?,
+                None if arg.quoted => write!(f, " \"{}\"", arg.name)?,
+                None => write!(f, " {}", arg.name)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1423 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use crate::api::{quote_key, quote_value, ApiError, ApiResult, Command, Reply};
+use crate::cache::CacheError;
+use crate::cluster;
+use crate::command_spec::{self, CommandSpec};
+use crate::configuration::KeyPolicy;
+use crate::introspection::InfoSection;
+use crate::perf;
+use crate::scheduler::ScheduledAction;
+use crate::snapshot;
+use crate::spool::{self, SpooledRequest, SpooledValue};
+use crate::usage::UsagePeriod;
+
+type Parse = fn(&'static CommandSpec, &str, KeyPolicy) -> ApiResult<Command>;
+type ParseSpooled = fn(&'static CommandSpec, SpooledRequest, KeyPolicy) -> ApiResult<Command>;
+
+/// How the server runs one command of protocol.toml. `parse` checks the
+/// arguments, which follow the command's name, and returns the command
+/// ready to run.
+pub struct Registration {
+    name: &'static str,
+    /// Other names the command answers to, such as DELETE for DEL
+    aliases: &'static [&'static str],
+    parse: Parse,
+    /// Parses a request longer than `spool_threshold_bytes`, whose value
+    /// stays in the spool file
+    spooled: Option<ParseSpooled>,
+}
+
+const fn register(name: &'static str, parse: Parse) -> Registration {
+    Registration { name, aliases: &[], parse, spooled: None }
+}
+
+impl Registration {
+    const fn aliases(self, aliases: &'static [&'static str]) -> Self {
+        Registration { aliases, ..self }
+    }
+
+    const fn spooled(self, spooled: ParseSpooled) -> Self {
+        Registration { spooled: Some(spooled), ..self }
+    }
+
+    fn spec(&self) -> Option<&'static CommandSpec> {
+        command_spec::COMMANDS.iter().find(|spec| spec.name == self.name)
+    }
+
+    pub fn parse(&self, spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+        (self.parse)(spec, args, policy)
+    }
+
+    pub fn parse_spooled(&self, spec: &'static CommandSpec, request: SpooledRequest, policy: KeyPolicy) -> Option<ApiResult<Command>> {
+        self.spooled.map(|spooled| spooled(spec, request, policy))
+    }
+}
+
+/// Every command the server runs, by its name in protocol.toml. Adding a
+/// command takes its entry there and one registration here.
+static REGISTRY: &[Registration] = &[
+    register("PING", ping),
+    register("SET", set).spooled(set_spooled),
+    register("SETNX", set_nx),
+    register("GETSET", get_set),
+    register("CAS", cas),
+    register("APPEND", append),
+    register("STRLEN", strlen),
+    register("GETRANGE", get_range),
+    register("GET", get),
+    register("MGET", mget),
+    register("DEL", delete).aliases(&["DELETE"]),
+    register("DELIF", delete_if),
+    register("GETVERSION", get_version),
+    register("DELIFVERSION", delete_if_version),
+    register("SETAT", set_at),
+    register("DELAT", delete_at),
+    register("EXISTS", exists),
+    register("TYPE", key_type),
+    register("RENAME", rename),
+    register("DBSIZE", dbsize),
+    register("FLUSHALL", flushall),
+    register("FLUSHDB", flushdb),
+    register("SELECT", select),
+    register("KEYS", keys),
+    register("NSKEYS", nskeys),
+    register("NSFLUSH", nsflush),
+    register("GETPREFIX", getprefix),
+    register("DUMP", dump),
+    register("RESTORE", restore),
+    register("ARCHIVE RESTORE", archive_restore),
+    register("EXPORT", export),
+    register("IMPORT", import),
+    register("USAGE REPORT", usage_report),
+    register("CONFIG GET", config_get),
+    register("CONFIG SET", config_set),
+    register("PERF SELFTEST", perf_selftest),
+    register("SLOWLOG GET", slowlog_get),
+    register("SLOWLOG LEN", slowlog_len),
+    register("SLOWLOG RESET", slowlog_reset),
+    register("INFO", info),
+    register("HELP", help),
+    register("COMMAND", command),
+    register("COMMAND COUNT", command_count),
+    register("COMMAND STATS", command_stats),
+    register("CLIENT LIST", client_list),
+    register("CLIENT KILL", client_kill),
+    register("CLUSTER MYID", cluster_myid),
+    register("CLUSTER MEET", cluster_meet),
+    register("CLUSTER SLOTS", cluster_slots),
+    register("CLUSTER RESHARD", cluster_reshard),
+    register("CLUSTER SLOTSTATS", cluster_slotstats),
+    register("CLUSTER MDUMP", cluster_mdump),
+    register("CLUSTER BACKUP", cluster_backup),
+    register("CLUSTER RESTORE", cluster_restore),
+    register("CLUSTER PLAN REBALANCE", cluster_plan_rebalance),
+    register("CLUSTER APPLY PLAN", cluster_apply_plan),
+    register("EVERYWHERE", everywhere),
+];
+
+/// Finds the registration a request starts with, the longest name winning
+/// so "CLUSTER PLAN REBALANCE" is not read as a shorter command. Also
+/// returns the command's entry in protocol.toml and the arguments after its
+/// name.
+pub fn lookup(request: &str) -> Option<(&'static Registration, &'static CommandSpec, &str)> {
+    let mut found: Option<(&'static Registration, usize, &str)> = None;
+    for registration in REGISTRY {
+        for name in std::iter::once(registration.name).chain(registration.aliases.iter().copied()) {
+            let words = name.split(' ').count();
+            if let Some(args) = strip_name(request, name)
+                && found.is_none_or(|(_, longest, _)| words > longest)
+            {
+                found = Some((registration, words, args));
+            }
+        }
+    }
+    let (registration, _, args) = found?;
+    Some((registration, registration.spec()?, args))
+}
+
+// The rest of the request when it starts with every word of `name`
+fn strip_name<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = request;
+    for part in name.split(' ') {
+        let (word, remainder) = rest.split_once(' ').unwrap_or((rest, ""));
+        if !word.eq_ignore_ascii_case(part) {
+            return None;
+        }
+        rest = remainder.trim_start();
+    }
+    Some(rest)
+}
+
+/// Checks at startup that protocol.toml and the registry list the same
+/// commands.
+pub fn check_registry() -> Result<(), String> {
+    for spec in command_spec::COMMANDS {
+        if !REGISTRY.iter().any(|registration| registration.name == spec.name) {
+            return Err(format!("{} is in protocol.toml but the server does not register it", spec.name));
+        }
+    }
+    for registration in REGISTRY {
+        if registration.spec().is_none() {
+            return Err(format!("{} is registered but missing from protocol.toml", registration.name));
+        }
+    }
+    Ok(())
+}
+
+/// The names of the commands that may be sent longer than
+/// `spool_threshold_bytes`.
+pub fn spooled_names() -> Vec<&'static str> {
+    REGISTRY.iter().filter(|registration| registration.spooled.is_some()).map(|registration| registration.name).collect()
+}
+
+const DEFAULT_SLOWLOG_GET: usize = 10;
+
+const CLUSTER_DISABLED: &str = "ERROR: Cluster mode is disabled";
+
+/// The commands EVERYWHERE runs, which change every node the same way
+const EVERYWHERE_COMMANDS: [&str; 4] = ["NSFLUSH", "FLUSHALL", "FLUSHDB", "CONFIG SET"];
+
+fn no_args(spec: &CommandSpec, args: &str) -> ApiResult<()> {
+    if !args.is_empty() {
+        return Err(ApiError::InvalidCommand(format!("{} command takes no arguments", spec.name)));
+    }
+    Ok(())
+}
+
+fn ping(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    no_args(spec, args)?;
+    Ok(Command::build(spec).run(|_| async { "PONG".to_string() }))
+}
+
+fn set(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, value) = parse_key_value_args(spec.name, args, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).run(move |ctx| async move {
+        let batcher = ctx.state.databases.batcher(ctx.db).filter(|_| ctx.state.config.current().write_batching_enabled);
+        let result = match batcher {
+            Some(batcher) => batcher.set(key, value).await,
+            None => ctx.cache.set(key, value).await,
+        };
+        match result {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn set_spooled(spec: &'static CommandSpec, request: SpooledRequest, policy: KeyPolicy) -> ApiResult<Command> {
+    let header = request.header.as_str();
+    let args = lookup(header.trim_start()).map_or("", |(_, _, args)| args);
+    let (key, value) = take_key(args, policy)?;
+    let start = (header.len() - value.len()) as u64;
+    if value.is_empty() || start >= request.content_end {
+        return Err(ApiError::InvalidCommand("SET command requires key and value".to_string()));
+    }
+    validate_key(&key, policy)?;
+
+    let quoted = value.starts_with('"') && request.last_byte == b'"' && request.content_end - start >= 2;
+    let value = SpooledValue::new(request.file, start, request.content_end, quoted);
+    Ok(Command::build(spec).key(&key).value_size(value.len() as usize).detail("spooled").run(move |ctx| async move {
+        match ctx.cache.set_spooled(key, value).await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn set_nx(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, value) = parse_key_value_args(spec.name, args, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).run(move |ctx| async move {
+        match ctx.cache.set_nx(key, value).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn get_set(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, value) = parse_key_value_args(spec.name, args, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).run(move |ctx| async move {
+        match ctx.cache.get_set(key, value).await {
+            Ok(Some(old)) => old,
+            Ok(None) => "NULL".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn cas(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, rest) = take_key(args, policy)?;
+    let args = split_quoted_args(rest)?;
+    if key.is_empty() || args.len() != 2 {
+        return Err(ApiError::InvalidCommand(
+            "CAS command requires key, expected value and new value".to_string(),
+        ));
+    }
+    let mut args = args.into_iter();
+    let expected = args.next().unwrap_or_default();
+    let value = args.next().unwrap_or_default();
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).run(move |ctx| async move {
+        match ctx.cache.compare_and_swap(key, &expected, value).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn append(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, value) = parse_key_value_args(spec.name, args, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).run(move |ctx| async move {
+        match ctx.cache.append(key, &value).await {
+            Ok(len) => len.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn strlen(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.strlen(&key).await {
+            Ok(len) => len.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn get_range(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let usage = || ApiError::InvalidCommand(
+        "GETRANGE command requires a key and numeric start and end offsets".to_string(),
+    );
+    let (key, rest) = take_key(args, policy)?;
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    if parts.len() != 2 {
+        return Err(usage());
+    }
+    validate_key(&key, policy)?;
+    let start: i64 = parts[0].parse().map_err(|_| usage())?;
+    let end: i64 = parts[1].parse().map_err(|_| usage())?;
+    Ok(Command::build(spec).key(&key).detail(format!("{} {}", start, end)).run(move |ctx| async move {
+        match ctx.cache.get_range(&key, start, end).await {
+            Ok(range) => range,
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+// Values larger than spool_threshold_bytes are decompressed into a spool
+// file and copied to the client from there. Smaller values, and keys that
+// are only in the backing store, take the normal GET path.
+fn get(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).reply(move |ctx| async move {
+        let config = ctx.state.config.current();
+        if config.spool_threshold_bytes > 0 {
+            let threshold = config.spool_threshold_bytes as u64;
+            let entry = ctx.cache.get_entry(&key).await
+                .filter(|entry| entry.content_size().is_none_or(|size| size > threshold));
+            if let Some(entry) = entry {
+                let compressed = match entry.compressed() {
+                    Ok(compressed) => compressed,
+                    Err(e) => return Reply::Text(format!("ERROR: {}", e)),
+                };
+                let dir = config.spool_dir.clone();
+                return match tokio::task::spawn_blocking(move || spool::decode_to_file(&compressed, &dir)).await {
+                    Ok(Ok(file)) => Reply::Spooled(file),
+                    Ok(Err(e)) => Reply::Text(format!("ERROR: Failed to spool value: {}", e)),
+                    Err(e) => Reply::Text(format!("ERROR: Failed to spool value: {}", e)),
+                };
+            }
+        }
+        Reply::Text(match ctx.cache.get(&key).await {
+            Ok(value) => value,
+            Err(CacheError::KeyNotFound(_)) => "NULL".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        })
+    }))
+}
+
+fn mget(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let keys = parse_keys(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&keys[0]).detail(format!("({} keys)", keys.len())).run(move |ctx| async move {
+        let values = match ctx.state.cluster.as_deref() {
+            Some(cluster) => cluster.mget(&ctx.cache, &keys).await.map_err(|e| e.to_string()),
+            None => ctx.cache.get_many(&keys).await.map_err(|e| e.to_string()),
+        };
+        match values {
+            Ok(values) => {
+                let pairs: Vec<String> = keys.iter()
+                    .zip(values)
+                    .filter_map(|(key, value)| Some(format!("{} {}", quote_key(key), quote_value(&value?))))
+                    .collect();
+                if pairs.is_empty() {
+                    "(empty)".to_string()
+                } else {
+                    pairs.join(" ")
+                }
+            }
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn delete(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.delete(&key).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn delete_if(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, expected) = parse_key_value_args(spec.name, args, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.delete_if(&key, &expected).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn get_version(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.version(&key).await {
+            Ok(version) => version.unwrap_or(0).to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn delete_if_version(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let usage = || ApiError::InvalidCommand("DELIFVERSION command requires a key and a numeric version".to_string());
+    let (key, rest) = take_key(args, policy)?;
+    let version: u64 = rest.parse().map_err(|_| usage())?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).detail(version).run(move |ctx| async move {
+        match ctx.cache.delete_if_version(&key, version).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn set_at(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (at, rest) = take_timestamp(spec.name, args)?;
+    let (key, value) = parse_key_value_args(spec.name, rest, policy)?;
+    validate_key(&key, policy)?;
+    Ok(Command::build(spec).key(&key).value_size(value.len()).detail(at).run(move |ctx| async move {
+        ctx.state.scheduler.schedule(at, ctx.db, ScheduledAction::Set { key, value }).to_string()
+    }))
+}
+
+fn delete_at(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (at, rest) = take_timestamp(spec.name, args)?;
+    let key = parse_single_key(spec.name, rest, policy)?;
+    Ok(Command::build(spec).key(&key).detail(at).run(move |ctx| async move {
+        ctx.state.scheduler.schedule(at, ctx.db, ScheduledAction::Delete { key }).to_string()
+    }))
+}
+
+fn exists(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.exists(&key).await {
+            Ok(true) => "1".to_string(),
+            Ok(false) => "0".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn key_type(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.key_type(&key).await {
+            Ok(key_type) => key_type.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn rename(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, rest) = take_key(args, policy)?;
+    let (new_key, rest) = take_key(rest, policy)?;
+    if new_key.is_empty() || !rest.is_empty() {
+        return Err(ApiError::InvalidCommand(
+            "RENAME command requires a key and a new key".to_string(),
+        ));
+    }
+    validate_key(&key, policy)?;
+    validate_key(&new_key, policy)?;
+    Ok(Command::build(spec).key(&key).detail(&new_key).run(move |ctx| async move {
+        match ctx.cache.rename(&key, new_key).await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn dbsize(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    no_args(spec, args)?;
+    Ok(Command::build(spec).run(|ctx| async move { ctx.cache.len().await.to_string() }))
+}
+
+// SYNC, the default, may be given explicitly
+fn parse_async(spec: &CommandSpec, args: &str) -> ApiResult<bool> {
+    match args.to_uppercase().as_str() {
+        "" | "SYNC" => Ok(false),
+        "ASYNC" => Ok(true),
+        _ => Err(ApiError::InvalidCommand(format!("{} command takes only ASYNC or SYNC", spec.name))),
+    }
+}
+
+fn flushall(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let asynchronous = parse_async(spec, args)?;
+    let command = Command::build(spec);
+    let command = if asynchronous { command.detail("ASYNC") } else { command };
+    Ok(command.run(move |ctx| async move {
+        if !ctx.state.config.current().flushall_enabled {
+            return "ERROR: FLUSHALL is disabled by configuration".to_string();
+        }
+        for database in ctx.state.databases.iter() {
+            if asynchronous {
+                database.flush_async().await;
+            } else {
+                database.flush().await;
+            }
+        }
+        "OK".to_string()
+    }))
+}
+
+fn flushdb(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let asynchronous = parse_async(spec, args)?;
+    let command = Command::build(spec);
+    let command = if asynchronous { command.detail("ASYNC") } else { command };
+    Ok(command.run(move |ctx| async move {
+        if !ctx.state.config.current().flushall_enabled {
+            return "ERROR: FLUSHDB is disabled by configuration".to_string();
+        }
+        if asynchronous {
+            ctx.cache.flush_async().await;
+        } else {
+            ctx.cache.flush().await;
+        }
+        "OK".to_string()
+    }))
+}
+
+// Cluster slots only map keys of database 0, so switching is refused in
+// cluster mode. The connection switches once the reply says so.
+fn select(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let index: usize = args.parse().map_err(|_| {
+        ApiError::InvalidCommand("SELECT command requires a database number".to_string())
+    })?;
+    Ok(Command::build(spec).detail(index).reply(move |ctx| async move {
+        let databases = &ctx.state.databases;
+        if index >= databases.len() {
+            return Reply::Text(format!(
+                "ERROR: Database index out of range, this server has {} databases", databases.len()
+            ));
+        }
+        if ctx.state.cluster.is_some() && index != 0 {
+            return Reply::Text("ERROR: SELECT is not allowed in cluster mode".to_string());
+        }
+        Reply::Selected(index)
+    }))
+}
+
+fn keys(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    no_args(spec, args)?;
+    Ok(Command::build(spec).run(|ctx| async move {
+        match ctx.cache.keys().await {
+            Ok(keys) => {
+                if keys.is_empty() {
+                    "(empty)".to_string()
+                } else {
+                    keys.iter().map(|key| quote_key(key)).collect::<Vec<_>>().join(" ")
+                }
+            }
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn parse_namespace(spec: &CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<String> {
+    if args.is_empty() || args.contains(' ') {
+        return Err(ApiError::InvalidCommand(format!(
+            "{} command requires exactly one namespace", spec.name
+        )));
+    }
+    validate_key(args, policy)?;
+    if args.contains(':') {
+        return Err(ApiError::InvalidCommand("Namespace cannot contain ':'".to_string()));
+    }
+    Ok(args.to_string())
+}
+
+fn nskeys(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let namespace = parse_namespace(spec, args, policy)?;
+    Ok(Command::build(spec).key(&namespace).tenant(&namespace).run(move |ctx| async move {
+        let mut keys = ctx.cache.namespace_keys(&namespace).await;
+        if keys.is_empty() {
+            "(empty)".to_string()
+        } else {
+            keys.sort();
+            keys.iter().map(|key| quote_key(key)).collect::<Vec<_>>().join(" ")
+        }
+    }))
+}
+
+fn nsflush(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let namespace = parse_namespace(spec, args, policy)?;
+    Ok(Command::build(spec).key(&namespace).tenant(&namespace).run(move |ctx| async move {
+        ctx.cache.flush_namespace(&namespace).await.to_string()
+    }))
+}
+
+fn getprefix(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (prefix, remainder) = take_key(args, policy)?;
+    if prefix.is_empty() || !remainder.is_empty() {
+        return Err(ApiError::InvalidCommand(
+            "GETPREFIX command requires exactly one prefix".to_string(),
+        ));
+    }
+    validate_prefix(&prefix, policy)?;
+    Ok(Command::build(spec).key(&prefix).run(move |ctx| async move {
+        match ctx.cache.get_prefix(&prefix, ctx.state.config.current().getprefix_max_results).await {
+            Ok(pairs) => {
+                if pairs.is_empty() {
+                    "(empty)".to_string()
+                } else {
+                    pairs.iter()
+                        .map(|(key, value)| format!("{} {}", quote_key(key), quote_value(value)))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                }
+            }
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn dump(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.cache.dump(&key).await {
+            Ok(payload) => snapshot::encode_payload(&payload),
+            Err(CacheError::KeyNotFound(_)) => "NULL".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn restore(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let (key, rest) = take_key(args, policy)?;
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    let replace = match parts.as_slice() {
+        [_] => false,
+        [_, flag] if flag.eq_ignore_ascii_case("REPLACE") => true,
+        _ => return Err(ApiError::InvalidCommand(
+            "RESTORE command requires a key, a payload and optionally REPLACE".to_string(),
+        )),
+    };
+    validate_key(&key, policy)?;
+    let payload = snapshot::decode_payload(parts[0]).ok_or_else(|| {
+        ApiError::InvalidCommand("RESTORE payload must be a hex string".to_string())
+    })?;
+    Ok(Command::build(spec).key(&key).value_size(payload.len()).run(move |ctx| async move {
+        match ctx.cache.restore(key, payload, replace).await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn archive_restore(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let key = parse_single_key(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&key).run(move |ctx| async move {
+        match ctx.state.archive.restore(&ctx.cache, ctx.db, &key).await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn export(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split(' ').collect();
+    let pattern = match parts.as_slice() {
+        [path] if !path.is_empty() => None,
+        [path, keyword, pattern] if !path.is_empty() && !pattern.is_empty() && keyword.eq_ignore_ascii_case("PATTERN") => {
+            Some(pattern.to_string())
+        }
+        _ => return Err(ApiError::InvalidCommand(
+            "EXPORT command requires a file path and optionally PATTERN and a key pattern".to_string(),
+        )),
+    };
+    let path = parts[0].to_string();
+    let detail = match &pattern {
+        Some(pattern) => format!("{} PATTERN {}", path, pattern),
+        None => path.clone(),
+    };
+    Ok(Command::build(spec).detail(detail).run(move |ctx| async move {
+        let path = match snapshot::resolve_path(&ctx.state.config.current().snapshot_dir, &path) {
+            Ok(path) => path,
+            Err(e) => return format!("ERROR: {}", e),
+        };
+        let exported = match pattern {
+            Some(pattern) => snapshot::export_matching(&ctx.cache, &path, |key| snapshot::matches_pattern(&pattern, key)).await,
+            None => snapshot::export_to_file(&ctx.cache, &path).await,
+        };
+        match exported {
+            Ok(count) => count.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn import(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split(' ').collect();
+    let prefix = match parts.as_slice() {
+        [path] if !path.is_empty() => None,
+        [_, keyword, prefix] if keyword.eq_ignore_ascii_case("PREFIX") => {
+            validate_prefix(prefix, policy)?;
+            Some(prefix.to_string())
+        }
+        _ => return Err(ApiError::InvalidCommand(
+            "IMPORT command requires a file path and optionally PREFIX and a key prefix".to_string(),
+        )),
+    };
+    let path = parts[0].to_string();
+    let detail = match &prefix {
+        Some(prefix) => format!("{} PREFIX {}", path, prefix),
+        None => path.clone(),
+    };
+    Ok(Command::build(spec).detail(detail).run(move |ctx| async move {
+        let config = ctx.state.config.current();
+        let path = match snapshot::resolve_path(&config.snapshot_dir, &path) {
+            Ok(path) => path,
+            Err(e) => return format!("ERROR: {}", e),
+        };
+        let check_key = imported_key_check(config.key_policy().unwrap_or(KeyPolicy::Strict));
+        let imported = match prefix {
+            Some(prefix) => snapshot::import_with_prefix(&ctx.cache, &path, &prefix, check_key).await,
+            None => snapshot::import_from_file(&ctx.cache, &path, check_key).await,
+        };
+        match imported {
+            Ok(count) => count.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+// Keys read from an export file are checked like the keys of a SET
+fn imported_key_check(policy: KeyPolicy) -> impl Fn(&str) -> Result<(), String> {
+    move |key| validate_key(key, policy).map_err(|e| match e {
+        ApiError::InvalidCommand(detail) => detail,
+        e => e.to_string(),
+    })
+}
+
+fn usage_report(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    if parts.len() > 2 {
+        return Err(wrong_subcommand("USAGE"));
+    }
+    let period = match parts.first() {
+        Some(period) => UsagePeriod::parse(period).ok_or_else(|| ApiError::InvalidCommand(format!(
+            "Invalid period '{}', expected YYYY-MM, YYYY-MM-DD, today or all", period
+        )))?,
+        None => UsagePeriod::current_month(),
+    };
+    let path = parts.get(1).map(|path| path.to_string());
+    let detail = match &path {
+        Some(path) => format!("{} {}", period.label(), path),
+        None => period.label(),
+    };
+    Ok(Command::build(spec).detail(detail).run(move |ctx| async move {
+        let rows = ctx.state.usage.report(period);
+        match path {
+            Some(path) => match ctx.state.usage.export_report(&rows, &path).await {
+                Ok(()) => rows.len().to_string(),
+                Err(e) => format!("ERROR: {}", e),
+            },
+            None => serde_json::to_string(&rows).unwrap_or_else(|e| format!("ERROR: {}", e)),
+        }
+    }))
+}
+
+fn config_get(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [param] = parts.as_slice() else {
+        return Err(wrong_subcommand("CONFIG"));
+    };
+    let param = param.to_string();
+    Ok(Command::build(spec).detail(&param).run(move |ctx| async move {
+        match ctx.state.config.current().get_param(&param) {
+            Ok(value) => value,
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn config_set(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [param, value] = parts.as_slice() else {
+        return Err(wrong_subcommand("CONFIG"));
+    };
+    let (param, value) = (param.to_string(), value.to_string());
+    Ok(Command::build(spec).detail(&param).run(move |ctx| async move {
+        match ctx.state.config.set(&param, &value) {
+            Ok(()) => "OK".to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn perf_selftest(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let iterations = if args.is_empty() {
+        perf::DEFAULT_SELFTEST_ITERATIONS
+    } else {
+        args.parse().ok()
+            .filter(|n| (1..=perf::MAX_SELFTEST_ITERATIONS).contains(n))
+            .ok_or_else(|| ApiError::InvalidCommand(format!(
+                "PERF SELFTEST takes a number of iterations up to {}", perf::MAX_SELFTEST_ITERATIONS
+            )))?
+    };
+    Ok(Command::build(spec).detail(iterations).run(move |ctx| async move {
+        match perf::selftest(iterations, ctx.state.config.current().compression_level).await {
+            Ok(report) => report.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn slowlog_get(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let count = match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => DEFAULT_SLOWLOG_GET,
+        [count] => count.parse().map_err(|_| {
+            ApiError::InvalidCommand("SLOWLOG GET count must be a number".to_string())
+        })?,
+        _ => return Err(wrong_subcommand("SLOWLOG")),
+    };
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let entries = ctx.state.slow_log.get(count);
+        if entries.is_empty() {
+            "(empty)".to_string()
+        } else {
+            entries.join(", ")
+        }
+    }))
+}
+
+fn slowlog_len(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("SLOWLOG"));
+    }
+    Ok(Command::build(spec).run(|ctx| async move { ctx.state.slow_log.len().to_string() }))
+}
+
+fn slowlog_reset(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("SLOWLOG"));
+    }
+    Ok(Command::build(spec).run(|ctx| async move {
+        ctx.state.slow_log.reset();
+        "OK".to_string()
+    }))
+}
+
+fn info(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let section = match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => None,
+        [name] => match InfoSection::parse(name) {
+            Some(section) => Some(section),
+            None => return Err(ApiError::InvalidCommand(format!(
+                "Unknown INFO section '{}', expected compression, types, memory, tasks or deprecated", name
+            ))),
+        },
+        _ => return Err(ApiError::InvalidCommand("INFO command takes at most one section".to_string())),
+    };
+    let command = Command::build(spec);
+    let command = match section {
+        Some(section) => command.detail(section.name()),
+        None => command,
+    };
+    Ok(command.run(move |ctx| async move {
+        let sections = match section {
+            Some(section) => vec![section],
+            None => InfoSection::ALL.to_vec(),
+        };
+        let mut lines = Vec::new();
+        for section in sections {
+            lines.extend(section.render(&ctx.state.databases, &ctx.state.supervisor, &ctx.state.deprecations).await);
+        }
+        lines.join(", ")
+    }))
+}
+
+fn help(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if args.is_empty() {
+        return Ok(Command::build(spec).run(|_| async { command_words().join(", ") }));
+    }
+    let first = args.split_whitespace().next().unwrap_or_default();
+    if command_spec::find(args).is_none() && command_spec::group(first).next().is_none() {
+        return Err(ApiError::InvalidCommand(format!("Unknown command: {}", args)));
+    }
+    let topic = args.to_uppercase();
+    Ok(Command::build(spec).detail(&topic).run(move |_| async move {
+        let specs: Vec<_> = match command_spec::find(&topic) {
+            Some(spec) => vec![spec],
+            None => command_spec::group(topic.split(' ').next().unwrap_or_default()).collect(),
+        };
+        specs.iter()
+            .map(|spec| match spec.deprecated {
+                Some(message) => format!("{} - {} (deprecated: {})", spec, spec.summary, message),
+                None => format!("{} - {}", spec, spec.summary),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }))
+}
+
+fn command(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    // "COMMAND FOO" is a wrong subcommand rather than arguments to COMMAND
+    if !args.is_empty() {
+        return Err(wrong_subcommand("COMMAND"));
+    }
+    Ok(Command::build(spec).run(|_| async {
+        command_spec::COMMANDS
+            .iter()
+            .map(|spec| {
+                let flags = if spec.flags.is_empty() { "none".to_string() } else { spec.flags.join("|") };
+                format!("cmd={} arity={} flags={}", spec.name, arity(spec), flags)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }))
+}
+
+fn command_count(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("COMMAND"));
+    }
+    Ok(Command::build(spec).run(|_| async { command_spec::COMMANDS.len().to_string() }))
+}
+
+fn command_stats(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("COMMAND"));
+    }
+    Ok(Command::build(spec).run(|ctx| async move {
+        let lines = ctx.state.command_stats.lines();
+        if lines.is_empty() {
+            "(empty)".to_string()
+        } else {
+            lines.join(", ")
+        }
+    }))
+}
+
+fn client_list(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("CLIENT"));
+    }
+    Ok(Command::build(spec).run(|ctx| async move { ctx.state.clients.list().join(", ") }))
+}
+
+fn client_kill(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if args.is_empty() || args.contains(' ') {
+        return Err(wrong_subcommand("CLIENT"));
+    }
+    let addr = args.to_string();
+    Ok(Command::build(spec).detail(&addr).run(move |ctx| async move {
+        if ctx.state.clients.kill(&addr) {
+            "OK".to_string()
+        } else {
+            format!("ERROR: No client connected from {}", addr)
+        }
+    }))
+}
+
+// Subcommands of CLUSTER without arguments of their own
+fn cluster_no_args(args: &str) -> ApiResult<()> {
+    if !args.is_empty() {
+        return Err(wrong_subcommand("CLUSTER"));
+    }
+    Ok(())
+}
+
+fn slot_range(range: &str) -> ApiResult<(u32, u32)> {
+    cluster::parse_slot_range(range).map_err(|e| ApiError::InvalidCommand(e.to_string()))
+}
+
+fn cluster_myid(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    cluster_no_args(args)?;
+    Ok(Command::build(spec).run(|ctx| async move {
+        match ctx.state.cluster.as_deref() {
+            Some(cluster) => cluster.local_node_id().to_string(),
+            None => CLUSTER_DISABLED.to_string(),
+        }
+    }))
+}
+
+fn cluster_meet(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if args.is_empty() || args.contains(' ') {
+        return Err(wrong_subcommand("CLUSTER"));
+    }
+    let address = args.to_string();
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        match cluster.meet(&address).await {
+            Ok(node_id) => node_id,
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn cluster_slots(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    cluster_no_args(args)?;
+    Ok(Command::build(spec).run(|ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let ranges = cluster.slots().await;
+        if ranges.is_empty() {
+            "(empty)".to_string()
+        } else {
+            ranges.iter()
+                .map(|(start, end, node_id, address)| format!("{}-{} {} {}", start, end, node_id, address))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+    }))
+}
+
+fn cluster_reshard(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [from, to, range] = parts.as_slice() else {
+        return Err(wrong_subcommand("CLUSTER"));
+    };
+    let (start, end) = slot_range(range)?;
+    let (from, to) = (from.to_string(), to.to_string());
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        match cluster.reshard(&ctx.cache, &from, &to, start, end).await {
+            Ok(migrated) => migrated.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn cluster_slotstats(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    if args.is_empty() || args.contains(' ') {
+        return Err(wrong_subcommand("CLUSTER"));
+    }
+    let (start, end) = slot_range(args)?;
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let (keys, bytes) = cluster.slot_stats(&ctx.cache, start, end).await;
+        format!("{} {}", keys, bytes)
+    }))
+}
+
+fn cluster_mdump(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let keys = parse_keys(spec.name, args, policy)?;
+    Ok(Command::build(spec).key(&keys[0]).run(move |ctx| async move {
+        if ctx.state.cluster.is_none() {
+            return CLUSTER_DISABLED.to_string();
+        }
+        let cache = &ctx.cache;
+        let mut payloads = Vec::with_capacity(keys.len());
+        for key in &keys {
+            // A miss may still be in the backing store, which GET loads
+            let dumped = match cache.dump(key).await {
+                Err(CacheError::KeyNotFound(_)) if cache.get(key).await.is_ok() => cache.dump(key).await,
+                dumped => dumped,
+            };
+            match dumped {
+                Ok(payload) => payloads.push(snapshot::encode_payload(&payload)),
+                Err(CacheError::KeyNotFound(_)) => payloads.push("NULL".to_string()),
+                Err(e) => return format!("ERROR: {}", e),
+            }
+        }
+        payloads.join(" ")
+    }))
+}
+
+// The slot range and file of CLUSTER BACKUP and CLUSTER RESTORE
+fn parse_slot_file(args: &str) -> ApiResult<(u32, u32, String)> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [range, path] = parts.as_slice() else {
+        return Err(wrong_subcommand("CLUSTER"));
+    };
+    let (start, end) = slot_range(range)?;
+    Ok((start, end, path.to_string()))
+}
+
+fn cluster_backup(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let (start, end, path) = parse_slot_file(args)?;
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let path = match snapshot::resolve_path(&ctx.state.config.current().snapshot_dir, &path) {
+            Ok(path) => path,
+            Err(e) => return format!("ERROR: {}", e),
+        };
+        match cluster.backup_slots(&ctx.cache, start, end, &path).await {
+            Ok(count) => count.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn cluster_restore(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    let (start, end, path) = parse_slot_file(args)?;
+    Ok(Command::build(spec).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let config = ctx.state.config.current();
+        let path = match snapshot::resolve_path(&config.snapshot_dir, &path) {
+            Ok(path) => path,
+            Err(e) => return format!("ERROR: {}", e),
+        };
+        let check_key = imported_key_check(config.key_policy().unwrap_or(KeyPolicy::Strict));
+        match cluster.restore_slots(&ctx.cache, start, end, &path, check_key).await {
+            Ok(count) => count.to_string(),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+fn cluster_plan_rebalance(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    cluster_no_args(args)?;
+    Ok(Command::build(spec).run(|ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let plan = cluster.plan_rebalance(&ctx.cache).await;
+        if plan.is_empty() {
+            "(balanced)".to_string()
+        } else {
+            let estimate = |value: Option<usize>| value.map_or("?".to_string(), |v| v.to_string());
+            plan.iter()
+                .map(|m| format!(
+                    "{} -> {} {}-{} keys={} bytes={}",
+                    m.from, m.to, m.start, m.end, estimate(m.keys), estimate(m.bytes)
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+    }))
+}
+
+fn cluster_apply_plan(spec: &'static CommandSpec, args: &str, _: KeyPolicy) -> ApiResult<Command> {
+    cluster_no_args(args)?;
+    Ok(Command::build(spec).run(|ctx| async move {
+        let Some(cluster) = ctx.state.cluster.as_deref() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        match cluster.apply_plan(&ctx.cache).await {
+            Ok(results) if results.is_empty() => "(balanced)".to_string(),
+            Ok(results) => results.join(", "),
+            Err(e) => format!("ERROR: {}", e)
+        }
+    }))
+}
+
+// Runs the command here and sends its text to every other node, replying
+// with each node's id and reply, this node first
+fn everywhere(spec: &'static CommandSpec, args: &str, policy: KeyPolicy) -> ApiResult<Command> {
+    let command = Command::parse(args, policy)?;
+    if !EVERYWHERE_COMMANDS.contains(&command.name()) {
+        return Err(ApiError::InvalidCommand(
+            "EVERYWHERE only runs NSFLUSH, FLUSHALL, FLUSHDB and CONFIG SET".to_string(),
+        ));
+    }
+    let request = args.to_string();
+    Ok(Command::build(spec).detail(command.name()).run(move |ctx| async move {
+        let Some(cluster) = ctx.state.cluster.clone() else {
+            return CLUSTER_DISABLED.to_string();
+        };
+        let (local, remote) = tokio::join!(command.run(ctx), cluster.broadcast(&request));
+        std::iter::once((cluster.local_node_id().to_string(), local.into_text()))
+            .chain(remote)
+            .map(|(node_id, reply)| format!("{} \"{}\"", node_id, reply))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }))
+}
+
+// The number of arguments, as a range for commands with optional ones
+fn arity(spec: &CommandSpec) -> String {
+    let required = spec.args.iter().filter(|arg| arg.flag.is_none() && !arg.optional).count();
+    if spec.args.iter().any(|arg| arg.repeated) {
+        format!("{}+", required)
+    } else if required == spec.args.len() {
+        required.to_string()
+    } else {
+        format!("{}-{}", required, spec.args.len())
+    }
+}
+
+fn parse_key_value_args(command: &str, args: &str, policy: KeyPolicy) -> ApiResult<(String, String)> {
+    if args.is_empty() {
+        return Err(ApiError::InvalidCommand(format!(
+            "{} command requires key and value", command
+        )));
+    }
+
+    let (key, rest) = take_key(args, policy)?;
+    let rest = rest.trim();
+
+    if rest.is_empty() {
+        return Err(ApiError::InvalidCommand(format!(
+            "{} command requires key and value", command
+        )));
+    }
+
+    let value = if rest.starts_with('"') && rest.ends_with('"') && rest.len() >= 2 {
+        rest[1..rest.len()-1].to_string()
+    } else {
+        rest.split_whitespace().collect::<Vec<&str>>().join(" ")
+    };
+
+    Ok((key, value))
+}
+
+// Splits off a leading Unix timestamp in seconds
+fn take_timestamp<'a>(command: &str, args: &'a str) -> ApiResult<(u64, &'a str)> {
+    let (timestamp, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let at = timestamp.parse().map_err(|_| {
+        ApiError::InvalidCommand(format!("{} command requires a Unix timestamp in seconds", command))
+    })?;
+    Ok((at, rest.trim_start()))
+}
+
+fn parse_single_key(command: &str, args: &str, policy: KeyPolicy) -> ApiResult<String> {
+    let (key, rest) = take_key(args, policy)?;
+    if key.is_empty() || !rest.is_empty() {
+        return Err(ApiError::InvalidCommand(format!(
+            "{} command requires exactly one key", command
+        )));
+    }
+    validate_key(&key, policy)?;
+    Ok(key)
+}
+
+fn parse_keys(command: &str, args: &str, policy: KeyPolicy) -> ApiResult<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut rest = args;
+    while !rest.is_empty() {
+        let (key, remainder) = take_key(rest, policy)?;
+        validate_key(&key, policy)?;
+        keys.push(key);
+        rest = remainder;
+    }
+    if keys.is_empty() {
+        return Err(ApiError::InvalidCommand(format!("{} command requires at least one key", command)));
+    }
+    Ok(keys)
+}
+
+// Splits off the leading key. Binary-safe keys may be double-quoted, with
+// \" \\ \n \r \t and \0 escapes; any other key ends at the first space.
+fn take_key(args: &str, policy: KeyPolicy) -> ApiResult<(String, &str)> {
+    if policy == KeyPolicy::BinarySafe && let Some(quoted) = args.strip_prefix('"') {
+        let mut key = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    let rest = &quoted[i + 1..];
+                    if !rest.is_empty() && !rest.starts_with(' ') {
+                        return Err(ApiError::InvalidCommand("Quoted key must be followed by a space".to_string()));
+                    }
+                    return Ok((key, rest.trim_start()));
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => key.push('\n'),
+                    Some((_, 'r')) => key.push('\r'),
+                    Some((_, 't')) => key.push('\t'),
+                    Some((_, '0')) => key.push('\0'),
+                    Some((_, escaped @ ('"' | '\\'))) => key.push(escaped),
+                    _ => return Err(ApiError::InvalidCommand("Invalid escape in quoted key".to_string())),
+                },
+                _ => key.push(ch),
+            }
+        }
+        return Err(ApiError::InvalidCommand("Unterminated quoted key".to_string()));
+    }
+
+    let end = args.find(' ').unwrap_or(args.len());
+    Ok((args[..end].to_string(), args[end..].trim_start()))
+}
+
+// Splits on whitespace, keeping double-quoted arguments together
+fn split_quoted_args(args: &str) -> ApiResult<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut rest = args.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or_else(|| {
+                ApiError::InvalidCommand("Unterminated quoted argument".to_string())
+            })?;
+            parts.push(quoted[..end].to_string());
+            rest = quoted[end + 1..].trim_start();
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            parts.push(rest[..end].to_string());
+            rest = rest[end..].trim_start();
+        }
+    }
+
+    Ok(parts)
+}
+
+pub fn wrong_subcommand(command: &str) -> ApiError {
+    ApiError::InvalidCommand(format!("Unknown {} subcommand or wrong number of arguments", command))
+}
+
+// The first word of every command in protocol.toml, in order
+pub fn command_words() -> Vec<&'static str> {
+    let mut words: Vec<&str> = Vec::new();
+    for spec in command_spec::COMMANDS {
+        let word = spec.name.split(' ').next().unwrap_or(spec.name);
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+// Keys may carry one namespace, as in `ns:key`, where both halves follow
+// the plain key rules
+fn validate_strict_key(key: &str) -> ApiResult<()> {
+    if let Some((namespace, name)) = key.split_once(':') {
+        if name.contains(':') {
+            return Err(ApiError::InvalidCommand("Key can only have one namespace".to_string()));
+        }
+        validate_strict_key(namespace)?;
+        return validate_strict_key(name);
+    }
+
+    if key.is_empty() {
+        return Err(ApiError::InvalidCommand("Key cannot be empty".to_string()));
+    }
+
+    // Check for spaces
+    if key.contains(' ') {
+        return Err(ApiError::InvalidCommand("Key cannot contain spaces".to_string()));
+    }
+
+    // Check each character
+    for ch in key.chars() {
+        if !ch.is_ascii_alphanumeric() && ch != '-' && ch != '_' {
+            return Err(ApiError::InvalidCommand(format!(
+                "Key contains invalid character '{}'. Keys can only contain letters, numbers, hyphens, and underscores",
+                ch
+            )));
+        }
+    }
+
+    // Check that - and _ are not at the beginning, end, or alone
+    let chars: Vec<char> = key.chars().collect();
+    for (i, &ch) in chars.iter().enumerate() {
+        if ch == '-' || ch == '_' {
+            // Cannot be at the beginning or end
+            if i == 0 || i == chars.len() - 1 {
+                return Err(ApiError::InvalidCommand(format!(
+                    "Key cannot start or end with '{}'. Hyphens and underscores must be between letters or numbers",
+                    ch
+                )));
+            }
+            
+            // Must be between alphanumeric characters
+            let prev = chars[i - 1];
+            let next = chars[i + 1];
+            if !prev.is_ascii_alphanumeric() || !next.is_ascii_alphanumeric() {
+                return Err(ApiError::InvalidCommand(format!(
+                    "Invalid key format. '{}' must be between letters or numbers",
+                    ch
+                )));
+            }
+        }
+    }
+
+    // Check for consecutive - or _
+    for i in 0..chars.len() - 1 {
+        if (chars[i] == '-' || chars[i] == '_') && (chars[i + 1] == '-' || chars[i + 1] == '_') {
+            return Err(ApiError::InvalidCommand(
+                "Key cannot have consecutive hyphens or underscores".to_string()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_key(key: &str, policy: KeyPolicy) -> ApiResult<()> {
+    match policy {
+        KeyPolicy::Strict => validate_strict_key(key),
+        KeyPolicy::RedisLike => {
+            if key.is_empty() {
+                return Err(ApiError::InvalidCommand("Key cannot be empty".to_string()));
+            }
+            if let Some(ch) = key.chars().find(|ch| ch.is_whitespace() || ch.is_control()) {
+                return Err(ApiError::InvalidCommand(format!(
+                    "Key contains invalid character {:?}. Keys cannot contain whitespace or control characters",
+                    ch
+                )));
+            }
+            Ok(())
+        }
+        KeyPolicy::BinarySafe => {
+            if key.is_empty() {
+                return Err(ApiError::InvalidCommand("Key cannot be empty".to_string()));
+            }
+            Ok(())
+        }
+    }
+}
+
+// A prefix may end anywhere inside a key, so only the character set is checked
+fn validate_prefix(prefix: &str, policy: KeyPolicy) -> ApiResult<()> {
+    if policy != KeyPolicy::Strict {
+        return validate_key(prefix, policy);
+    }
+    for ch in prefix.chars() {
+        if !ch.is_ascii_alphanumeric() && ch != '-' && ch != '_' && ch != ':' {
+            return Err(ApiError::InvalidCommand(format!(
+                "Prefix contains invalid character '{}'. Prefixes can only contain letters, numbers, hyphens, underscores, and colons",
+                ch
+            )));
+        }
+    }
+
+    Ok(())
+}
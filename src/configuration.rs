@@ -2,9 +2,15 @@
 // A scalable and lightweight Key Value Cache written in Rust
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use crate::cache::{namespace_of, DEFAULT_COMPRESSION_LEVEL};
 use crate::cluster;
 
 #[derive(Debug, Error)]
@@ -17,18 +23,150 @@ pub enum ConfigError {
     TomlSerialize(#[from] toml::ser::Error),
     #[error("JSON serialization error: {0}")]
     JsonSerialize(#[from] serde_json::Error),
+    #[error("Invalid configuration value: {0}")]
+    InvalidValue(String),
+    #[error("Concurrent update: {0}")]
+    Conflict(String),
 }
 
 type ConfigResult<T> = Result<T, ConfigError>;
 
+pub const CONFIG_FILE: &str = "hydrogen.toml";
+
+/// Settings that can change while the server is running, through CONFIG SET
+/// or a SIGHUP reload. Everything else needs a restart.
+pub const HOT_RELOADABLE: &[&str] = &[
+    "compression_level",
+    "log_level",
+    "getprefix_max_results",
+    "idle_timeout_secs",
+    "command_timeout_ms",
+    "max_line_length",
+    "flushall_enabled",
+    "capture_enabled",
+    "slowlog_threshold_us",
+    "slowlog_max_len",
+    "recompress_enabled",
+    "recompress_level",
+    "recompress_interval_secs",
+    "recompress_min_idle_secs",
+    "recompress_min_size",
+    "recompress_cpu_budget_ms",
+    "write_batching_enabled",
+    "spool_threshold_bytes",
+    "retention_interval_secs",
+    "usage_enabled",
+    "usage_sample_secs",
+    "deprecation_warnings",
+];
+
+/// Which keys the server accepts, set by `key_validation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Letters, digits, inner hyphens and underscores, with one optional
+    /// `ns:` prefix
+    Strict,
+    /// Any printable characters except whitespace, as in `user:123:session`
+    /// or `assets/img/logo.png`
+    RedisLike,
+    /// Any string. Keys with spaces, quotes or control characters are sent
+    /// double-quoted with backslash escapes
+    BinarySafe,
+}
+
+/// How a node picks its ID when cluster.json is generated, set by
+/// `node_id_mode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeIdMode {
+    Random,
+    /// Derived from the machine's hostname and `bind-port`
+    Hostname,
+    /// Derived from `node_id_seed`
+    Seed(String),
+}
+
+/// Limits for one key namespace. Zero leaves a limit off.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NamespaceQuota {
+    #[serde(default)]
+    pub max_keys: usize,
+    #[serde(default)]
+    pub max_bytes: usize,
+}
+
+/// When keys of one namespace are moved to the archive file. The rule under
+/// "*" covers keys whose namespace has no rule of its own.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetentionRule {
+    /// Days a key may go without a read or write before it is archived
+    pub idle_days: u64,
+    /// Keeps archived keys in memory as well, so the archive only holds a copy
+    #[serde(default)]
+    pub keep: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HydrogenConfig {
     #[serde(rename = "bind-ip")]
     pub bind_ip: String,
     #[serde(rename = "bind-port")]
     pub bind_port: u16,
+    /// Runs without any cluster machinery or cluster.json, whatever
+    /// `cluster_enabled` says
+    pub standalone: bool,
     pub cluster_enabled: bool,
+    pub node_id_mode: String,
+    pub node_id_seed: String,
     pub whisper_timeout: u32,
+    pub migration_bandwidth_limit: u64,
+    pub getprefix_max_results: usize,
+    pub backing_store: String,
+    pub backing_store_path: String,
+    pub write_through: bool,
+    pub idle_timeout_secs: u64,
+    pub command_timeout_ms: u64,
+    pub max_line_length: usize,
+    pub audit_log_enabled: bool,
+    pub audit_log_path: String,
+    pub audit_log_max_bytes: u64,
+    pub audit_log_max_files: u32,
+    pub audit_log_keys: bool,
+    pub compression_level: i32,
+    pub log_level: String,
+    pub flushall_enabled: bool,
+    pub databases: usize,
+    pub namespace_quotas: HashMap<String, NamespaceQuota>,
+    pub capture_enabled: bool,
+    pub capture_path: String,
+    pub unix_socket_path: String,
+    pub unix_socket_permissions: String,
+    pub slowlog_threshold_us: u64,
+    pub slowlog_max_len: usize,
+    pub recompress_enabled: bool,
+    pub recompress_level: i32,
+    pub recompress_interval_secs: u64,
+    pub recompress_min_idle_secs: u64,
+    pub recompress_min_size: usize,
+    pub recompress_cpu_budget_ms: u64,
+    pub write_batching_enabled: bool,
+    pub write_batch_queue_len: usize,
+    pub write_batch_max: usize,
+    pub key_validation: String,
+    pub health_port: u16,
+    pub spool_threshold_bytes: usize,
+    pub spool_dir: String,
+    pub retention: HashMap<String, RetentionRule>,
+    pub retention_interval_secs: u64,
+    pub archive_path: String,
+    /// Directory EXPORT, IMPORT and CLUSTER BACKUP/RESTORE paths are
+    /// resolved in. Not changeable at runtime, as it bounds what clients can
+    /// read and write.
+    pub snapshot_dir: String,
+    pub usage_enabled: bool,
+    pub usage_path: String,
+    pub usage_sample_secs: u64,
+    /// Ends replies to deprecated commands with a warning, see protocol.toml
+    pub deprecation_warnings: bool,
 }
 
 impl Default for HydrogenConfig {
@@ -36,8 +174,56 @@ impl Default for HydrogenConfig {
         Self {
             bind_ip: "0.0.0.0".to_string(),
             bind_port: 1825,
+            standalone: false,
             cluster_enabled: false,
+            node_id_mode: "random".to_string(),
+            node_id_seed: String::new(),
             whisper_timeout: 1,
+            migration_bandwidth_limit: 0,
+            getprefix_max_results: 1000,
+            backing_store: "none".to_string(),
+            backing_store_path: "data".to_string(),
+            write_through: false,
+            idle_timeout_secs: 300,
+            command_timeout_ms: 5000,
+            max_line_length: 1024 * 1024,
+            audit_log_enabled: false,
+            audit_log_path: "audit.log".to_string(),
+            audit_log_max_bytes: 10 * 1024 * 1024,
+            audit_log_max_files: 5,
+            audit_log_keys: true,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            log_level: "info".to_string(),
+            flushall_enabled: true,
+            databases: 16,
+            namespace_quotas: HashMap::new(),
+            capture_enabled: false,
+            capture_path: "capture.log".to_string(),
+            unix_socket_path: String::new(),
+            unix_socket_permissions: "700".to_string(),
+            slowlog_threshold_us: 10_000,
+            slowlog_max_len: 128,
+            recompress_enabled: false,
+            recompress_level: 19,
+            recompress_interval_secs: 60,
+            recompress_min_idle_secs: 300,
+            recompress_min_size: 4096,
+            recompress_cpu_budget_ms: 50,
+            write_batching_enabled: false,
+            write_batch_queue_len: 1024,
+            write_batch_max: 256,
+            key_validation: "strict".to_string(),
+            health_port: 0,
+            spool_threshold_bytes: 0,
+            spool_dir: String::new(),
+            retention: HashMap::new(),
+            retention_interval_secs: 3600,
+            archive_path: "archive.jsonl".to_string(),
+            snapshot_dir: "snapshots".to_string(),
+            usage_enabled: false,
+            usage_path: "usage.json".to_string(),
+            usage_sample_secs: 60,
+            deprecation_warnings: true,
         }
     }
 }
@@ -47,8 +233,13 @@ impl HydrogenConfig {
         format!("{}:{}", self.bind_ip, self.bind_port)
     }
 
+    /// Whether this node takes part in a cluster.
+    pub fn cluster_active(&self) -> bool {
+        self.cluster_enabled && !self.standalone
+    }
+
     pub fn load_or_create() -> ConfigResult<Self> {
-        let config_path = "hydrogen.toml";
+        let config_path = CONFIG_FILE;
         
         let config = if Path::new(config_path).exists() {
             Self::load_and_heal(config_path)?
@@ -58,31 +249,145 @@ impl HydrogenConfig {
             default_config
         };
         
-        if config.cluster_enabled {
-            cluster::generate_cluster_file(&config)?;
+        config.validate()?;
+
+        if config.cluster_active() {
+            cluster::prepare_cluster_file(&config)?;
         }
         
         Ok(config)
     }
 
     fn load_and_heal(path: &str) -> ConfigResult<Self> {
+        let healed_config = Self::read_from_file(path)?;
+        healed_config.save_to_file(path)?;
+        Ok(healed_config)
+    }
+
+    pub fn read_from_file(path: &str) -> ConfigResult<Self> {
         let content = fs::read_to_string(path)?;
         
         match toml::from_str::<HydrogenConfig>(&content) {
-            Ok(config) => {
-                let healed_config = Self::heal_config(config);
-                healed_config.save_to_file(path)?;
-                Ok(healed_config)
-            }
+            Ok(config) => Ok(Self::heal_config(config)),
             Err(_) => {
                 let partial_config = Self::parse_partial_config(&content)?;
-                let healed_config = Self::heal_config(partial_config);
-                healed_config.save_to_file(path)?;
-                Ok(healed_config)
+                Ok(Self::heal_config(partial_config))
             }
         }
     }
 
+    pub fn validate(&self) -> ConfigResult<()> {
+        if !(1..=22).contains(&self.compression_level) {
+            return Err(ConfigError::InvalidValue(format!(
+                "compression_level must be between 1 and 22, got {}", self.compression_level
+            )));
+        }
+        if !(1..=22).contains(&self.recompress_level) {
+            return Err(ConfigError::InvalidValue(format!(
+                "recompress_level must be between 1 and 22, got {}", self.recompress_level
+            )));
+        }
+        self.level_filter()?;
+        self.key_policy()?;
+        self.node_id_mode()?;
+        if self.databases == 0 {
+            return Err(ConfigError::InvalidValue("databases must be at least 1".to_string()));
+        }
+        self.unix_socket_mode()?;
+        if let Some((namespace, _)) = self.retention.iter().find(|(_, rule)| rule.idle_days == 0) {
+            return Err(ConfigError::InvalidValue(format!(
+                "retention rule '{}' needs idle_days of at least 1", namespace
+            )));
+        }
+        if self.write_batch_queue_len == 0 || self.write_batch_max == 0 {
+            return Err(ConfigError::InvalidValue(
+                "write_batch_queue_len and write_batch_max must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn node_id_mode(&self) -> ConfigResult<NodeIdMode> {
+        match self.node_id_mode.as_str() {
+            "random" => Ok(NodeIdMode::Random),
+            "hostname" => Ok(NodeIdMode::Hostname),
+            "seed" if self.node_id_seed.is_empty() => Err(ConfigError::InvalidValue(
+                "node_id_mode \"seed\" needs a node_id_seed".to_string(),
+            )),
+            "seed" => Ok(NodeIdMode::Seed(self.node_id_seed.clone())),
+            other => Err(ConfigError::InvalidValue(format!(
+                "node_id_mode must be random, hostname or seed, got '{}'", other
+            ))),
+        }
+    }
+
+    /// Parses `unix_socket_permissions`, an octal mode such as "770".
+    pub fn unix_socket_mode(&self) -> ConfigResult<u32> {
+        u32::from_str_radix(&self.unix_socket_permissions, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+            .ok_or_else(|| ConfigError::InvalidValue(format!(
+                "unix_socket_permissions must be an octal mode like \"770\", got '{}'",
+                self.unix_socket_permissions
+            )))
+    }
+
+    pub fn key_policy(&self) -> ConfigResult<KeyPolicy> {
+        match self.key_validation.as_str() {
+            "strict" => Ok(KeyPolicy::Strict),
+            "redis-like" => Ok(KeyPolicy::RedisLike),
+            "binary-safe" => Ok(KeyPolicy::BinarySafe),
+            other => Err(ConfigError::InvalidValue(format!(
+                "key_validation must be strict, redis-like or binary-safe, got '{}'", other
+            ))),
+        }
+    }
+
+    /// The retention rule for `key`: its namespace's own rule, else the "*"
+    /// rule.
+    pub fn retention_rule(&self, key: &str) -> Option<&RetentionRule> {
+        namespace_of(key)
+            .and_then(|namespace| self.retention.get(namespace))
+            .or_else(|| self.retention.get("*"))
+    }
+
+    pub fn level_filter(&self) -> ConfigResult<LevelFilter> {
+        LevelFilter::from_str(&self.log_level)
+            .map_err(|_| ConfigError::InvalidValue(format!("unknown log_level '{}'", self.log_level)))
+    }
+
+    pub fn get_param(&self, name: &str) -> ConfigResult<String> {
+        let table = toml::Value::try_from(self)?;
+        match table.get(name) {
+            Some(toml::Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(ConfigError::InvalidValue(format!("unknown parameter '{}'", name))),
+        }
+    }
+
+    pub fn set_param(&mut self, name: &str, value: &str) -> ConfigResult<()> {
+        if !HOT_RELOADABLE.contains(&name) {
+            return Err(ConfigError::InvalidValue(format!("'{}' cannot be changed at runtime", name)));
+        }
+
+        let mut table = toml::Value::try_from(&*self)?;
+        let invalid = || ConfigError::InvalidValue(format!("invalid value '{}' for {}", value, name));
+        let parsed = match table.get(name) {
+            Some(toml::Value::Integer(_)) => toml::Value::Integer(value.parse().map_err(|_| invalid())?),
+            Some(toml::Value::Boolean(_)) => toml::Value::Boolean(value.parse().map_err(|_| invalid())?),
+            Some(toml::Value::String(_)) => toml::Value::String(value.to_string()),
+            _ => return Err(ConfigError::InvalidValue(format!("unknown parameter '{}'", name))),
+        };
+        if let Some(table) = table.as_table_mut() {
+            table.insert(name.to_string(), parsed);
+        }
+
+        let updated: HydrogenConfig = table.try_into()?;
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+
     fn parse_partial_config(content: &str) -> ConfigResult<Self> {
         let toml_value: toml::Value = toml::from_str(content)?;
         
@@ -95,12 +400,156 @@ impl HydrogenConfig {
             if let Some(toml::Value::Integer(port)) = table.get("bind-port") {
                 config.bind_port = *port as u16;
             }
+            if let Some(toml::Value::Boolean(standalone)) = table.get("standalone") {
+                config.standalone = *standalone;
+            }
             if let Some(toml::Value::Boolean(enabled)) = table.get("cluster_enabled") {
                 config.cluster_enabled = *enabled;
             }
+            if let Some(toml::Value::String(mode)) = table.get("node_id_mode") {
+                config.node_id_mode = mode.clone();
+            }
+            if let Some(toml::Value::String(seed)) = table.get("node_id_seed") {
+                config.node_id_seed = seed.clone();
+            }
             if let Some(toml::Value::Integer(timeout)) = table.get("whisper_timeout") {
                 config.whisper_timeout = *timeout as u32;
             }
+            if let Some(toml::Value::Integer(limit)) = table.get("migration_bandwidth_limit") {
+                config.migration_bandwidth_limit = *limit as u64;
+            }
+            if let Some(toml::Value::Integer(max)) = table.get("getprefix_max_results") {
+                config.getprefix_max_results = *max as usize;
+            }
+            if let Some(toml::Value::String(store)) = table.get("backing_store") {
+                config.backing_store = store.clone();
+            }
+            if let Some(toml::Value::String(path)) = table.get("backing_store_path") {
+                config.backing_store_path = path.clone();
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("write_through") {
+                config.write_through = *enabled;
+            }
+            if let Some(toml::Value::Integer(timeout)) = table.get("idle_timeout_secs") {
+                config.idle_timeout_secs = *timeout as u64;
+            }
+            if let Some(toml::Value::Integer(timeout)) = table.get("command_timeout_ms") {
+                config.command_timeout_ms = *timeout as u64;
+            }
+            if let Some(toml::Value::Integer(length)) = table.get("max_line_length") {
+                config.max_line_length = *length as usize;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("audit_log_enabled") {
+                config.audit_log_enabled = *enabled;
+            }
+            if let Some(toml::Value::String(path)) = table.get("audit_log_path") {
+                config.audit_log_path = path.clone();
+            }
+            if let Some(toml::Value::Integer(max)) = table.get("audit_log_max_bytes") {
+                config.audit_log_max_bytes = *max as u64;
+            }
+            if let Some(toml::Value::Integer(max)) = table.get("audit_log_max_files") {
+                config.audit_log_max_files = *max as u32;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("audit_log_keys") {
+                config.audit_log_keys = *enabled;
+            }
+            if let Some(toml::Value::Integer(level)) = table.get("compression_level") {
+                config.compression_level = *level as i32;
+            }
+            if let Some(toml::Value::String(level)) = table.get("log_level") {
+                config.log_level = level.clone();
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("flushall_enabled") {
+                config.flushall_enabled = *enabled;
+            }
+            if let Some(toml::Value::Integer(count)) = table.get("databases") {
+                config.databases = *count as usize;
+            }
+            if let Some(quotas) = table.get("namespace_quotas") {
+                config.namespace_quotas = quotas.clone().try_into()?;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("capture_enabled") {
+                config.capture_enabled = *enabled;
+            }
+            if let Some(toml::Value::String(path)) = table.get("capture_path") {
+                config.capture_path = path.clone();
+            }
+            if let Some(toml::Value::String(path)) = table.get("unix_socket_path") {
+                config.unix_socket_path = path.clone();
+            }
+            if let Some(toml::Value::String(mode)) = table.get("unix_socket_permissions") {
+                config.unix_socket_permissions = mode.clone();
+            }
+            if let Some(toml::Value::Integer(threshold)) = table.get("slowlog_threshold_us") {
+                config.slowlog_threshold_us = *threshold as u64;
+            }
+            if let Some(toml::Value::Integer(max)) = table.get("slowlog_max_len") {
+                config.slowlog_max_len = *max as usize;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("recompress_enabled") {
+                config.recompress_enabled = *enabled;
+            }
+            if let Some(toml::Value::Integer(level)) = table.get("recompress_level") {
+                config.recompress_level = *level as i32;
+            }
+            if let Some(toml::Value::Integer(interval)) = table.get("recompress_interval_secs") {
+                config.recompress_interval_secs = *interval as u64;
+            }
+            if let Some(toml::Value::Integer(idle)) = table.get("recompress_min_idle_secs") {
+                config.recompress_min_idle_secs = *idle as u64;
+            }
+            if let Some(toml::Value::Integer(size)) = table.get("recompress_min_size") {
+                config.recompress_min_size = *size as usize;
+            }
+            if let Some(toml::Value::Integer(budget)) = table.get("recompress_cpu_budget_ms") {
+                config.recompress_cpu_budget_ms = *budget as u64;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("write_batching_enabled") {
+                config.write_batching_enabled = *enabled;
+            }
+            if let Some(toml::Value::Integer(len)) = table.get("write_batch_queue_len") {
+                config.write_batch_queue_len = *len as usize;
+            }
+            if let Some(toml::Value::Integer(max)) = table.get("write_batch_max") {
+                config.write_batch_max = *max as usize;
+            }
+            if let Some(toml::Value::String(policy)) = table.get("key_validation") {
+                config.key_validation = policy.clone();
+            }
+            if let Some(toml::Value::Integer(port)) = table.get("health_port") {
+                config.health_port = *port as u16;
+            }
+            if let Some(toml::Value::Integer(threshold)) = table.get("spool_threshold_bytes") {
+                config.spool_threshold_bytes = *threshold as usize;
+            }
+            if let Some(toml::Value::String(dir)) = table.get("spool_dir") {
+                config.spool_dir = dir.clone();
+            }
+            if let Some(rules) = table.get("retention") {
+                config.retention = rules.clone().try_into()?;
+            }
+            if let Some(toml::Value::Integer(interval)) = table.get("retention_interval_secs") {
+                config.retention_interval_secs = *interval as u64;
+            }
+            if let Some(toml::Value::String(path)) = table.get("archive_path") {
+                config.archive_path = path.clone();
+            }
+            if let Some(toml::Value::String(dir)) = table.get("snapshot_dir") {
+                config.snapshot_dir = dir.clone();
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("usage_enabled") {
+                config.usage_enabled = *enabled;
+            }
+            if let Some(toml::Value::String(path)) = table.get("usage_path") {
+                config.usage_path = path.clone();
+            }
+            if let Some(toml::Value::Integer(interval)) = table.get("usage_sample_secs") {
+                config.usage_sample_secs = *interval as u64;
+            }
+            if let Some(toml::Value::Boolean(enabled)) = table.get("deprecation_warnings") {
+                config.deprecation_warnings = *enabled;
+            }
         }
         
         Ok(config)
@@ -115,4 +564,102 @@ impl HydrogenConfig {
         fs::write(path, content)?;
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+type ChangeListener = Box<dyn Fn(&HydrogenConfig) + Send + Sync>;
+
+/// The running configuration. Readers take a cheap snapshot with `current`,
+/// and CONFIG SET or a reload swap in a new one and notify listeners.
+pub struct SharedConfig {
+    current: RwLock<Arc<HydrogenConfig>>,
+    listeners: Vec<ChangeListener>,
+    // Held from reading the config to replacing it, so concurrent CONFIG SETs
+    // and reloads apply one after another instead of losing updates
+    writer: Mutex<()>,
+}
+
+impl SharedConfig {
+    pub fn new(config: HydrogenConfig) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(config)),
+            listeners: Vec::new(),
+            writer: Mutex::new(()),
+        }
+    }
+
+    pub fn on_change(&mut self, listener: impl Fn(&HydrogenConfig) + Send + Sync + 'static) {
+        listener(&self.current());
+        self.listeners.push(Box::new(listener));
+    }
+
+    pub fn current(&self) -> Arc<HydrogenConfig> {
+        match self.current.read() {
+            Ok(current) => Arc::clone(&current),
+            Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+        }
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> ConfigResult<()> {
+        let _writer = self.lock_writer();
+        let mut updated = (*self.current()).clone();
+        updated.set_param(name, value)?;
+        self.replace(updated);
+        Ok(())
+    }
+
+    /// Re-reads the config file and applies the hot-reloadable settings that
+    /// changed. Other changes are reported and left for the next restart.
+    pub fn reload(&self, path: &str) -> ConfigResult<Vec<String>> {
+        let _writer = self.lock_writer();
+        let current = self.current();
+        let from_file = HydrogenConfig::read_from_file(path)?;
+        from_file.validate()?;
+
+        let current_table = toml::Value::try_from(&*current)?;
+        let file_table = toml::Value::try_from(&from_file)?;
+        let (Some(current_table), Some(file_table)) = (current_table.as_table(), file_table.as_table()) else {
+            return Ok(Vec::new());
+        };
+
+        let mut updated = (*current).clone();
+        let mut changed = Vec::new();
+        for (name, value) in file_table {
+            if current_table.get(name) == Some(value) {
+                continue;
+            }
+            if HOT_RELOADABLE.contains(&name.as_str()) {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                updated.set_param(name, &value)?;
+                changed.push(name.clone());
+            } else {
+                warn!("Config change to '{}' requires a restart to take effect", name);
+            }
+        }
+
+        if !changed.is_empty() {
+            self.replace(updated);
+        }
+        Ok(changed)
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, ()> {
+        match self.writer.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn replace(&self, updated: HydrogenConfig) {
+        for listener in &self.listeners {
+            listener(&updated);
+        }
+        let updated = Arc::new(updated);
+        match self.current.write() {
+            Ok(mut current) => *current = updated,
+            Err(poisoned) => *poisoned.into_inner() = updated,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+const STARTING: u8 = 0;
+const READY: u8 = 1;
+const SHUTTING_DOWN: u8 = 2;
+
+// A probe that sends nothing, or a request line longer than any real probe's,
+// is dropped instead of holding a task and a buffer open
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LINE: u64 = 8 * 1024;
+
+/// Where the server is in its lifecycle, as reported to health probes.
+#[derive(Debug, Default)]
+pub struct Health {
+    state: AtomicU8,
+}
+
+impl Health {
+    pub fn set_ready(&self) {
+        self.state.store(READY, Ordering::Relaxed);
+    }
+
+    pub fn set_shutting_down(&self) {
+        self.state.store(SHUTTING_DOWN, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == READY
+    }
+
+    fn state_name(&self) -> &'static str {
+        match self.state.load(Ordering::Relaxed) {
+            STARTING => "starting",
+            READY => "ready",
+            _ => "shutting down",
+        }
+    }
+}
+
+/// Serves `GET /healthz` (liveness, 200 while the process is up) and
+/// `GET /readyz` (readiness, 200 only once the listener accepts clients and
+/// until shutdown starts) for orchestrators such as Kubernetes.
+pub async fn serve_http(bind_addr: &str, health: Arc<Health>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(bind_addr).await?;
+    info!("Health endpoint listening on http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let health = Arc::clone(&health);
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream, &health).await {
+                            error!("Health probe failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept health probe: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn respond(stream: TcpStream, health: &Health) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LINE));
+    tokio::time::timeout(REQUEST_TIMEOUT, reader.read_line(&mut request_line))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "no request line"))??;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/healthz")) => ("200 OK", "ok"),
+        (Some("GET"), Some("/readyz")) if health.is_ready() => ("200 OK", "ready"),
+        (Some("GET"), Some("/readyz")) => ("503 Service Unavailable", health.state_name()),
+        _ => ("404 Not Found", "not found"),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
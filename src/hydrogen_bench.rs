@@ -0,0 +1,365 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use rand::Rng;
+use serde::Deserialize;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const USAGE: &str = "Usage: hydrogen-bench [--profile file.yaml] [-h host] [-p port] [-c concurrency] [-k keyspace] [-d value-size] [-r read-ratio] [-t seconds]";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum KeyDistribution {
+    Uniform,
+    /// Key `bench-0` is the hottest, with popularity falling off as rank^-exponent
+    Zipfian {
+        #[serde(default = "default_zipf_exponent")]
+        exponent: f64,
+    },
+}
+
+fn default_zipf_exponent() -> f64 {
+    0.99
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct WeightedSize {
+    size: usize,
+    weight: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ValueSize {
+    Fixed { size: usize },
+    Uniform { min: usize, max: usize },
+    Weighted { sizes: Vec<WeightedSize> },
+}
+
+impl ValueSize {
+    fn max(&self) -> usize {
+        match self {
+            ValueSize::Fixed { size } => *size,
+            ValueSize::Uniform { max, .. } => *max,
+            ValueSize::Weighted { sizes } => sizes.iter().map(|s| s.size).max().unwrap_or(0),
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        match self {
+            ValueSize::Fixed { size } => *size,
+            ValueSize::Uniform { min, max } => rng.gen_range(*min..=*max),
+            ValueSize::Weighted { sizes } => {
+                let total: u32 = sizes.iter().map(|s| s.weight).sum();
+                let mut pick = rng.gen_range(0..total);
+                for size in sizes {
+                    if pick < size.weight {
+                        return size.size;
+                    }
+                    pick -= size.weight;
+                }
+                0
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ValueSize::Fixed { size } => format!("{} byte values", size),
+            ValueSize::Uniform { min, max } => format!("{}-{} byte values", min, max),
+            ValueSize::Weighted { sizes } => {
+                let parts: Vec<String> = sizes.iter().map(|s| format!("{}b x{}", s.size, s.weight)).collect();
+                format!("weighted values ({})", parts.join(", "))
+            }
+        }
+    }
+}
+
+/// A workload shape loaded with --profile. Every field is optional and
+/// falls back to the defaults, and flags after --profile override it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    name: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    concurrency: Option<usize>,
+    keyspace: Option<usize>,
+    duration_secs: Option<u64>,
+    read_ratio: Option<f64>,
+    key_distribution: Option<KeyDistribution>,
+    value_size: Option<ValueSize>,
+}
+
+#[derive(Debug, Clone)]
+struct BenchOptions {
+    profile: Option<String>,
+    host: String,
+    port: u16,
+    concurrency: usize,
+    keyspace: usize,
+    key_distribution: KeyDistribution,
+    value_size: ValueSize,
+    read_ratio: f64,
+    duration: Duration,
+}
+
+impl BenchOptions {
+    fn apply_profile(&mut self, path: &str) -> Result<(), String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let profile: Profile = serde_yaml::from_str(&content).map_err(|e| format!("Invalid profile {}: {}", path, e))?;
+
+        self.profile = Some(profile.name.unwrap_or_else(|| path.to_string()));
+        if let Some(host) = profile.host {
+            self.host = host;
+        }
+        if let Some(port) = profile.port {
+            self.port = port;
+        }
+        if let Some(concurrency) = profile.concurrency {
+            self.concurrency = concurrency;
+        }
+        if let Some(keyspace) = profile.keyspace {
+            self.keyspace = keyspace;
+        }
+        if let Some(seconds) = profile.duration_secs {
+            self.duration = Duration::from_secs(seconds);
+        }
+        if let Some(read_ratio) = profile.read_ratio {
+            self.read_ratio = read_ratio;
+        }
+        if let Some(key_distribution) = profile.key_distribution {
+            self.key_distribution = key_distribution;
+        }
+        if let Some(value_size) = profile.value_size {
+            self.value_size = value_size;
+        }
+        Ok(())
+    }
+}
+
+/// Draws key indexes. Zipfian draws binary search a cumulative table built
+/// once up front and shared by all workers.
+enum KeySampler {
+    Uniform(usize),
+    Zipfian(Vec<f64>),
+}
+
+impl KeySampler {
+    fn new(keyspace: usize, distribution: &KeyDistribution) -> Self {
+        match distribution {
+            KeyDistribution::Uniform => KeySampler::Uniform(keyspace),
+            KeyDistribution::Zipfian { exponent } => {
+                let mut cumulative = Vec::with_capacity(keyspace);
+                let mut total = 0.0;
+                for rank in 1..=keyspace {
+                    total += 1.0 / (rank as f64).powf(*exponent);
+                    cumulative.push(total);
+                }
+                for weight in &mut cumulative {
+                    *weight /= total;
+                }
+                KeySampler::Zipfian(cumulative)
+            }
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        match self {
+            KeySampler::Uniform(keyspace) => rng.gen_range(0..*keyspace),
+            KeySampler::Zipfian(cumulative) => {
+                let point: f64 = rng.r#gen();
+                cumulative.partition_point(|&weight| weight < point).min(cumulative.len() - 1)
+            }
+        }
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<BenchOptions, String> {
+    let mut options = BenchOptions {
+        profile: None,
+        host: "127.0.0.1".to_string(),
+        port: 1825,
+        concurrency: 50,
+        keyspace: 10_000,
+        key_distribution: KeyDistribution::Uniform,
+        value_size: ValueSize::Fixed { size: 100 },
+        read_ratio: 0.8,
+        duration: Duration::from_secs(10),
+    };
+
+    let mut args = args;
+    while let Some(flag) = args.next() {
+        if flag == "--help" {
+            return Err(USAGE.to_string());
+        }
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--profile" => options.apply_profile(&value)?,
+            "-h" | "--host" => options.host = value.clone(),
+            "-p" | "--port" => options.port = value.parse().map_err(|_| invalid())?,
+            "-c" | "--concurrency" => options.concurrency = value.parse().map_err(|_| invalid())?,
+            "-k" | "--keyspace" => options.keyspace = value.parse().map_err(|_| invalid())?,
+            "-d" | "--value-size" => options.value_size = ValueSize::Fixed { size: value.parse().map_err(|_| invalid())? },
+            "-r" | "--read-ratio" => options.read_ratio = value.parse().map_err(|_| invalid())?,
+            "-t" | "--duration" => options.duration = Duration::from_secs(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option: {}", flag)),
+        }
+    }
+
+    if options.concurrency == 0 || options.keyspace == 0 {
+        return Err("Concurrency and keyspace must be greater than zero".to_string());
+    }
+    if !(0.0..=1.0).contains(&options.read_ratio) {
+        return Err("Read ratio must be between 0.0 and 1.0".to_string());
+    }
+    match &options.value_size {
+        ValueSize::Fixed { size } if *size == 0 => Err("Value size must be greater than zero".to_string()),
+        ValueSize::Uniform { min, max } if *min == 0 || min > max => {
+            Err("Uniform value sizes need 0 < min <= max".to_string())
+        }
+        ValueSize::Weighted { sizes } if sizes.is_empty() || sizes.iter().any(|s| s.size == 0) || sizes.iter().all(|s| s.weight == 0) => {
+            Err("Weighted value sizes need non-zero sizes and at least one non-zero weight".to_string())
+        }
+        _ => Ok(options),
+    }
+}
+
+#[derive(Debug, Default)]
+struct WorkerStats {
+    reads: u64,
+    writes: u64,
+    errors: u64,
+    latencies_us: Vec<u64>,
+}
+
+async fn run_worker(options: BenchOptions, keys: Arc<KeySampler>, deadline: Instant) -> std::io::Result<WorkerStats> {
+    let stream = TcpStream::connect((options.host.as_str(), options.port)).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut response = String::new();
+    let mut stats = WorkerStats::default();
+    let value = "x".repeat(options.value_size.max());
+
+    while Instant::now() < deadline {
+        let (key_index, is_read, value_size) = {
+            let mut rng = rand::thread_rng();
+            (keys.sample(&mut rng), rng.gen_bool(options.read_ratio), options.value_size.sample(&mut rng))
+        };
+        let request = if is_read {
+            format!("GET bench-{}\n", key_index)
+        } else {
+            format!("SET bench-{} {}\n", key_index, &value[..value_size])
+        };
+
+        let started = Instant::now();
+        writer.write_all(request.as_bytes()).await?;
+        response.clear();
+        if reader.read_line(&mut response).await? == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "server closed the connection"));
+        }
+        stats.latencies_us.push(started.elapsed().as_micros() as u64);
+
+        if response.starts_with("ERROR") {
+            stats.errors += 1;
+        } else if is_read {
+            stats.reads += 1;
+        } else {
+            stats.writes += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+fn percentile(sorted: &[u64], percent: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((percent / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+fn report(options: &BenchOptions, elapsed: Duration, stats: WorkerStats) {
+    let mut latencies = stats.latencies_us;
+    latencies.sort_unstable();
+    let total = stats.reads + stats.writes + stats.errors;
+    let seconds = elapsed.as_secs_f64();
+
+    let distribution = match &options.key_distribution {
+        KeyDistribution::Uniform => "uniform".to_string(),
+        KeyDistribution::Zipfian { exponent } => format!("zipfian s={}", exponent),
+    };
+
+    if let Some(profile) = &options.profile {
+        println!("Profile:      {}", profile);
+    }
+    println!("Target:       {}:{}", options.host, options.port);
+    println!("Concurrency:  {}", options.concurrency);
+    println!("Keyspace:     {} keys ({}), {}, {:.0}% reads",
+        options.keyspace, distribution, options.value_size.describe(), options.read_ratio * 100.0);
+    println!("Duration:     {:.2}s", seconds);
+    println!("Requests:     {} ({} reads, {} writes, {} errors)", total, stats.reads, stats.writes, stats.errors);
+    println!("Throughput:   {:.0} ops/sec", total as f64 / seconds);
+    println!("Latency (us): p50={} p90={} p99={} p99.9={} max={}",
+        percentile(&latencies, 50.0),
+        percentile(&latencies, 90.0),
+        percentile(&latencies, 99.0),
+        percentile(&latencies, 99.9),
+        latencies.last().copied().unwrap_or(0),
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            if msg != USAGE {
+                eprintln!("{}", USAGE);
+            }
+            process::exit(2);
+        }
+    };
+
+    let keys = Arc::new(KeySampler::new(options.keyspace, &options.key_distribution));
+    let started = Instant::now();
+    let deadline = started + options.duration;
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|_| tokio::spawn(run_worker(options.clone(), Arc::clone(&keys), deadline)))
+        .collect();
+
+    let mut combined = WorkerStats::default();
+    let mut failed_workers = 0;
+    for worker in workers {
+        match worker.await {
+            Ok(Ok(stats)) => {
+                combined.reads += stats.reads;
+                combined.writes += stats.writes;
+                combined.errors += stats.errors;
+                combined.latencies_us.extend(stats.latencies_us);
+            }
+            Ok(Err(e)) => {
+                failed_workers += 1;
+                eprintln!("Worker failed: {}", e);
+            }
+            Err(e) => {
+                failed_workers += 1;
+                eprintln!("Worker panicked: {}", e);
+            }
+        }
+    }
+
+    if failed_workers == options.concurrency {
+        eprintln!("All workers failed, no results to report");
+        process::exit(1);
+    }
+    report(&options, started.elapsed(), combined);
+}
@@ -1,14 +1,20 @@
-use std::io::{self, Write, BufRead, BufReader};
+mod command_spec;
+
+use command_spec::CommandSpec;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write, BufRead, BufReader, IsTerminal};
 use std::net::TcpStream;
+use std::process;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 enum ParsedCommand {
     Quit,
-    Help,
-    Set { address: String, key: String, value: String },
-    Get { address: String, key: String },
-    Del { address: String, key: String },
-    Keys { address: String },
+    Help { topic: Option<String> },
+    Watch { address: String, key: String, interval: Duration },
+    Request { address: String, spec: &'static CommandSpec, request: String },
     Error(String),
 }
 
@@ -25,7 +31,8 @@ fn parse_command_line(input: &str) -> Option<ParsedCommand> {
 
     match parts[0] {
         "quit" | "exit" => Some(ParsedCommand::Quit),
-        "help" => Some(ParsedCommand::Help),
+        "help" if parts.len() == 1 => Some(ParsedCommand::Help { topic: None }),
+        "help" => Some(ParsedCommand::Help { topic: Some(parts[1..].join(" ")) }),
         _ => {
             // First part should be address
             let address = parts[0].to_string();
@@ -34,110 +41,823 @@ fn parse_command_line(input: &str) -> Option<ParsedCommand> {
             }
 
             let command = parts[1];
-            match command {
-                "set" => {
-                    if parts.len() < 4 {
-                        return Some(ParsedCommand::Error(format!("Usage: {} set <key> <value>", address)));
-                    }
+            if command == "watch" {
+                let usage = || ParsedCommand::Error(format!("Usage: {} watch <key> [interval seconds]", address));
+                if parts.len() != 3 && parts.len() != 4 {
+                    return Some(usage());
+                }
+                let interval = match parts.get(3) {
+                    Some(seconds) => match seconds.parse::<f64>() {
+                        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Duration::from_secs_f64(seconds),
+                        _ => return Some(usage()),
+                    },
+                    None => Duration::from_secs(1),
+                };
+                return Some(ParsedCommand::Watch { address, key: parts[2].to_string(), interval });
+            }
+
+            let typed = input[parts[0].len()..].trim_start();
+            let Some(spec) = command_spec::find(typed) else {
+                if command_spec::group(command).next().is_none() {
+                    return Some(ParsedCommand::Error(format!("Unknown command: {}", command)));
+                }
+                return Some(usage_error(&address, command));
+            };
+            let words = split_words(typed).split_off(spec.name.split(' ').count());
+            match request_line(spec, words) {
+                Some(request) => Some(ParsedCommand::Request { address, spec, request }),
+                None => Some(usage_error(&address, command)),
+            }
+        }
+    }
+}
+
+// Splits on whitespace, keeping a double-quoted word, which may contain
+// backslash escapes, together with its quotes
+fn split_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut word = String::new();
+        let mut quoted = false;
+        while let Some(&ch) = chars.peek() {
+            if ch.is_whitespace() && !quoted {
+                break;
+            }
+            word.push(ch);
+            chars.next();
+            match ch {
+                '"' => quoted = !quoted,
+                '\\' if quoted => word.extend(chars.next()),
+                _ => {}
+            }
+        }
+        words.push(word);
+    }
+    words
+}
+
+/// Builds the request for `spec` from the words typed after its name, as
+/// protocol.toml describes its arguments. The last argument takes the rest
+/// of the line, so "set greeting hello world" needs no quotes. Returns None
+/// when required arguments are missing or words are left over.
+fn request_line(spec: &CommandSpec, words: Vec<String>) -> Option<String> {
+    let mut request = spec.name.to_string();
+    let mut words = words.into_iter().peekable();
+    for (index, arg) in spec.args.iter().enumerate() {
+        if let Some(flag) = arg.flag {
+            if words.next_if(|word| word.eq_ignore_ascii_case(flag)).is_some() {
+                request.push_str(&format!(" {}", flag));
+            }
+            continue;
+        }
+        if let Some(keyword) = arg.keyword {
+            if words.next_if(|word| word.eq_ignore_ascii_case(keyword)).is_none() {
+                continue;
+            }
+            request.push_str(&format!(" {}", keyword));
+        }
+
+        let values: Vec<String> = if arg.repeated {
+            words.by_ref().collect()
+        } else if index + 1 == spec.args.len() {
+            let rest: Vec<String> = words.by_ref().collect();
+            if rest.is_empty() { Vec::new() } else { vec![rest.join(" ")] }
+        } else {
+            words.next().into_iter().collect()
+        };
+        if values.is_empty() {
+            if arg.optional && arg.keyword.is_none() {
+                continue;
+            }
+            return None;
+        }
+        for value in values {
+            request.push(' ');
+            if arg.quoted {
+                let unquoted = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(&value);
+                request.push_str(&format!("\"{}\"", unquoted));
+            } else {
+                request.push_str(&value);
+            }
+        }
+    }
+    words.next().is_none().then_some(request)
+}
+
+/// A command's syntax as typed into the CLI, such as
+/// "flushall [async]" or "cluster meet <address>".
+fn cli_usage(spec: &CommandSpec) -> String {
+    let mut usage = spec.name.to_lowercase();
+    for arg in spec.args {
+        match arg.flag {
+            Some(flag) => usage.push_str(&format!(" [{}]", flag.to_lowercase())),
+            None if arg.optional => match arg.keyword {
+                Some(keyword) => usage.push_str(&format!(" [{} <{}>]", keyword.to_lowercase(), arg.name)),
+                None => usage.push_str(&format!(" [{}]", arg.name)),
+            },
+            None if arg.repeated => usage.push_str(&format!(" <{}>...", arg.name)),
+            None => usage.push_str(&format!(" <{}>", arg.name)),
+        }
+    }
+    usage
+}
+
+// Commands with subcommands, like cluster, list one line per subcommand
+fn usage_error(address: &str, command: &str) -> ParsedCommand {
+    let lines: Vec<String> = command_spec::group(command)
+        .map(|spec| format!("{} {}", address, cli_usage(spec)))
+        .collect();
+    ParsedCommand::Error(format!("Usage: {}", lines.join("\n       ")))
+}
+
+const USAGE: &str = "Usage: hydrogen-cli [-h host] [-p port] [--raw] [--replay file [--speed factor]] [--smoke] [command [args...]]";
+
+struct CliOptions {
+    host: String,
+    port: u16,
+    raw: bool,
+    replay: Option<String>,
+    speed: f64,
+    smoke: bool,
+    command: Vec<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<CliOptions, String> {
+    let mut options = CliOptions {
+        host: "127.0.0.1".to_string(),
+        port: 1825,
+        raw: false,
+        replay: None,
+        speed: 1.0,
+        smoke: false,
+        command: Vec::new(),
+    };
+
+    let mut args = args.peekable();
+    while let Some(arg) = args.peek() {
+        match arg.as_str() {
+            "-h" | "--host" => {
+                args.next();
+                options.host = args.next().ok_or("Missing value for -h")?;
+            }
+            "-p" | "--port" => {
+                args.next();
+                let port = args.next().ok_or("Missing value for -p")?;
+                options.port = port.parse().map_err(|_| format!("Invalid port: {}", port))?;
+            }
+            "--raw" => {
+                args.next();
+                options.raw = true;
+            }
+            "--replay" => {
+                args.next();
+                options.replay = Some(args.next().ok_or("Missing value for --replay")?);
+            }
+            "--speed" => {
+                args.next();
+                let speed = args.next().ok_or("Missing value for --speed")?;
+                options.speed = speed.parse().ok().filter(|speed: &f64| *speed >= 0.0)
+                    .ok_or_else(|| format!("Invalid speed: {}", speed))?;
+            }
+            "--smoke" => {
+                args.next();
+                options.smoke = true;
+            }
+            "--help" => return Err(USAGE.to_string()),
+            _ => break,
+        }
+    }
+
+    // Re-quote arguments the shell unquoted so values with spaces survive.
+    // The first is left alone so a whole script line can be passed as one.
+    options.command = args
+        .enumerate()
+        .map(|(index, arg)| {
+            if index > 0 && arg.contains(char::is_whitespace) { format!("\"{}\"", arg) } else { arg }
+        })
+        .collect();
+
+    // `hydrogen-cli <addr> --smoke` names the server to test
+    if let [address, flag] = options.command.as_slice()
+        && flag == "--smoke"
+        && is_address(address)
+    {
+        let (host, port) = address.rsplit_once(':').ok_or("Invalid address")?;
+        options.host = host.to_string();
+        options.port = port.parse().map_err(|_| format!("Invalid port: {}", port))?;
+        options.smoke = true;
+        options.command.clear();
+    }
+    Ok(options)
+}
+
+/// One line of a server capture file, see `capture_enabled` in hydrogen.toml
+#[derive(Deserialize)]
+struct CaptureRecord {
+    timestamp_us: u64,
+    client: String,
+    line: String,
+}
+
+struct ReplayStats {
+    commands: usize,
+    clients: usize,
+    errors: usize,
+}
+
+// Each captured client gets its own connection so per-connection state such
+// as SELECT carries over. A speed of 0 sends everything without waiting.
+fn replay(path: &str, address: &str, speed: f64) -> io::Result<ReplayStats> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut connections: HashMap<String, Connection> = HashMap::new();
+    let mut stats = ReplayStats { commands: 0, clients: 0, errors: 0 };
+    let mut first_timestamp = None;
+    let started = Instant::now();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: CaptureRecord = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", index + 1, e))
+        })?;
+
+        let first = *first_timestamp.get_or_insert(record.timestamp_us);
+        if speed > 0.0 {
+            let offset = Duration::from_micros(record.timestamp_us.saturating_sub(first)).div_f64(speed);
+            if let Some(wait) = offset.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+
+        if !connections.contains_key(&record.client) {
+            let stream = TcpStream::connect(address)?;
+            let reader = BufReader::new(stream.try_clone()?);
+            connections.insert(record.client.clone(), Connection { reader, writer: stream });
+            stats.clients += 1;
+        }
+        let Some(connection) = connections.get_mut(&record.client) else {
+            continue;
+        };
+        connection.writer.write_all(format!("{}\n", record.line).as_bytes())?;
+        let mut response = String::new();
+        if connection.reader.read_line(&mut response)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+        }
+        stats.commands += 1;
+        if response.starts_with("ERROR") {
+            stats.errors += 1;
+        }
+    }
 
-                    let key = parts[2];
+    Ok(stats)
+}
 
-                    // Handle quoted values
-                    let value = if input.contains('"') {
-                        parse_quoted_value(input)?
-                    } else {
-                        parts[3..].join(" ")
-                    };
+enum Expect {
+    Reply(&'static str),
+    Integer,
+    Success,
+}
 
-                    Some(ParsedCommand::Set { address, key: key.to_string(), value })
+/// One feature of the smoke test: requests run in order, where `$ns` is
+/// the temporary namespace and `$last` the previous reply.
+struct SmokeCheck {
+    feature: &'static str,
+    steps: Vec<(&'static str, Expect)>,
+}
+
+fn smoke_checks() -> Vec<SmokeCheck> {
+    use Expect::*;
+    let check = |feature, steps| SmokeCheck { feature, steps };
+    vec![
+        check("ping", vec![("PING", Reply("PONG"))]),
+        check("set/get", vec![
+            ("SET $ns:k \"hello world\"", Reply("OK")),
+            ("GET $ns:k", Reply("hello world")),
+            ("GET $ns:missing", Reply("NULL")),
+        ]),
+        check("mget", vec![("MGET $ns:k $ns:missing", Reply("$ns:k \"hello world\""))]),
+        check("setnx", vec![("SETNX $ns:k x", Reply("0")), ("SETNX $ns:n x", Reply("1"))]),
+        check("getset", vec![("GETSET $ns:k v2", Reply("hello world")), ("GET $ns:k", Reply("v2"))]),
+        check("cas", vec![("CAS $ns:k v2 v3", Reply("1")), ("CAS $ns:k v2 v4", Reply("0"))]),
+        check("append/strlen/getrange", vec![
+            ("APPEND $ns:k xyz", Reply("5")),
+            ("STRLEN $ns:k", Reply("5")),
+            ("GETRANGE $ns:k 0 1", Reply("v3")),
+        ]),
+        check("setat/delat", vec![("SETAT 1 $ns:at now", Integer), ("DELAT 1 $ns:at", Integer)]),
+        check("exists/type", vec![("EXISTS $ns:k", Reply("1")), ("TYPE $ns:k", Reply("string"))]),
+        check("rename", vec![("RENAME $ns:k $ns:r", Reply("OK")), ("EXISTS $ns:k", Reply("0"))]),
+        check("del/delif", vec![
+            ("DELIF $ns:r nope", Reply("0")),
+            ("DELIF $ns:r v3xyz", Reply("1")),
+            ("SET $ns:d 42", Reply("OK")),
+            ("DEL $ns:d", Reply("1")),
+        ]),
+        check("getversion/delifversion", vec![
+            ("SET $ns:v lock", Reply("OK")),
+            ("DELIFVERSION $ns:v 0", Reply("0")),
+            ("GETVERSION $ns:v", Integer),
+            ("DELIFVERSION $ns:v $last", Reply("1")),
+            ("GETVERSION $ns:v", Reply("0")),
+        ]),
+        check("dump/restore", vec![
+            ("SET $ns:dump \"some value\"", Reply("OK")),
+            ("DUMP $ns:dump", Success),
+            ("RESTORE $ns:restored $last", Reply("OK")),
+            ("GET $ns:restored", Reply("some value")),
+        ]),
+        check("namespaces", vec![("NSKEYS $ns", Success), ("GETPREFIX $ns:n", Reply("$ns:n \"x\""))]),
+        check("dbsize", vec![("DBSIZE", Integer)]),
+        check("config", vec![("CONFIG GET compression_level", Integer)]),
+        check("info", vec![("INFO", Success)]),
+        check("help/command", vec![("HELP GET", Success), ("COMMAND COUNT", Integer), ("COMMAND STATS", Success)]),
+        check("slowlog", vec![("SLOWLOG LEN", Integer)]),
+        check("usage", vec![("USAGE REPORT today", Success)]),
+        check("client", vec![("CLIENT LIST", Success)]),
+        check("cluster", vec![("CLUSTER MYID", Success), ("CLUSTER SLOTS", Success)]),
+        check("nsflush", vec![("NSFLUSH $ns", Integer), ("NSKEYS $ns", Reply("(empty)"))]),
+    ]
+}
+
+// Runs every check against the server, printing PASS, FAIL or SKIP per
+// feature. Features the server has turned off, such as cluster mode, are
+// skipped. Returns whether nothing failed.
+fn smoke(session: &mut Session, address: &str) -> bool {
+    let namespace = format!("smoke_{}", process::id());
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+
+    for check in smoke_checks() {
+        let mut last = String::new();
+        let mut outcome = Ok(());
+        for (template, expect) in &check.steps {
+            let request = template.replace("$ns", &namespace).replace("$last", &last);
+            let response = match session.send(address, &request) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("Failed to reach {}: {}", address, e);
+                    return false;
                 }
-                "get" => {
-                    if parts.len() != 3 {
-                        return Some(ParsedCommand::Error(format!("Usage: {} get <key>", address)));
-                    }
-                    let key = parts[2];
-                    Some(ParsedCommand::Get { address, key: key.to_string() })
+            };
+            let ok = match expect {
+                Expect::Reply(expected) => response == expected.replace("$ns", &namespace),
+                Expect::Integer => response.parse::<i64>().is_ok(),
+                Expect::Success => !response.starts_with("ERROR"),
+            };
+            if !ok {
+                outcome = Err((request, response));
+                break;
+            }
+            last = response;
+        }
+
+        match outcome {
+            Ok(()) => {
+                println!("PASS  {}", check.feature);
+                passed += 1;
+            }
+            Err((_, response)) if response.contains("disabled") => {
+                println!("SKIP  {} ({})", check.feature, response.trim_start_matches("ERROR: "));
+                skipped += 1;
+            }
+            Err((request, response)) => {
+                println!("FAIL  {}: {} => {}", check.feature, request, response);
+                failed += 1;
+            }
+        }
+    }
+
+    // Leaves nothing behind even when nsflush itself failed
+    let _ = session.send(address, &format!("NSFLUSH {}", namespace));
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+    failed == 0
+}
+
+fn is_address(token: &str) -> bool {
+    matches!(token.rsplit_once(':'), Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+enum LineOutcome {
+    Success,
+    Failure,
+    Quit,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+/// Keeps one connection per server open across commands and formats replies
+/// for either people or scripts.
+struct Session {
+    default_address: String,
+    raw: bool,
+    connections: HashMap<String, Connection>,
+    variables: HashMap<String, String>,
+}
+
+impl Session {
+    fn new(default_address: String, raw: bool) -> Self {
+        Self {
+            default_address,
+            raw,
+            connections: HashMap::new(),
+            variables: HashMap::new(),
+        }
+    }
+
+    fn run_line(&mut self, input: &str) -> LineOutcome {
+        let input = input.trim();
+        if input.is_empty() {
+            return LineOutcome::Success;
+        }
+        if let Some(rest) = input.strip_prefix("for ") {
+            return self.run_loop(rest);
+        }
+        if let Some(rest) = input.strip_prefix("let ") {
+            return self.assign(rest);
+        }
+        let input = match expand_variables(input, &self.variables) {
+            Ok(expanded) => expanded,
+            Err(msg) => {
+                println!("{}", msg);
+                return LineOutcome::Failure;
+            }
+        };
+        let input = input.as_str();
+
+        // Commands without an explicit address go to the -h/-p server
+        let first = input.split_whitespace().next().unwrap_or_default();
+        let addressed = if is_address(first) || matches!(first, "quit" | "exit" | "help") {
+            input.to_string()
+        } else {
+            format!("{} {}", self.default_address, input)
+        };
+
+        let parsed = match parse_command_line(&addressed) {
+            Some(parsed) => parsed,
+            None => {
+                println!("Usage: [ip:port] <command> [args...]");
+                println!("Type 'help' for available commands.");
+                return LineOutcome::Failure;
+            }
+        };
+
+        let (address, request, split_lines) = match parsed {
+            ParsedCommand::Quit => return LineOutcome::Quit,
+            ParsedCommand::Help { topic: None } => {
+                print_help();
+                return LineOutcome::Success;
+            }
+            ParsedCommand::Help { topic: Some(topic) } => return print_command_help(&topic),
+            ParsedCommand::Error(msg) => {
+                println!("{}", msg);
+                return LineOutcome::Failure;
+            }
+            ParsedCommand::Watch { address, key, interval } => return self.watch(&address, &key, interval),
+            ParsedCommand::Request { address, spec, request } => {
+                (address, request, matches!(spec.name, "KEYS" | "NSKEYS"))
+            }
+        };
+
+        match self.send(&address, &request) {
+            Ok(response) => self.print_response(&response, split_lines),
+            Err(e) => {
+                eprintln!("Failed to reach {}: {}", address, e);
+                LineOutcome::Failure
+            }
+        }
+    }
+
+    // let <name> = <value>
+    fn assign(&mut self, definition: &str) -> LineOutcome {
+        let Some((name, value)) = definition.split_once('=') else {
+            println!("Usage: let <name> = <value>");
+            return LineOutcome::Failure;
+        };
+        let name = name.trim();
+        if !is_variable_name(name) {
+            println!("Invalid variable name: {}", name);
+            return LineOutcome::Failure;
+        }
+        match expand_variables(value.trim(), &self.variables) {
+            Ok(value) => {
+                self.variables.insert(name.to_string(), value);
+                LineOutcome::Success
+            }
+            Err(msg) => {
+                println!("{}", msg);
+                LineOutcome::Failure
+            }
+        }
+    }
+
+    // for <name> in <start>..<end>: <command>, with both bounds included.
+    // The body is expanded again on every pass and may itself be a loop.
+    fn run_loop(&mut self, definition: &str) -> LineOutcome {
+        let usage = || {
+            println!("Usage: for <name> in <start>..<end>: <command>");
+            LineOutcome::Failure
+        };
+        let Some((header, body)) = definition.split_once(':') else {
+            return usage();
+        };
+        let header = match expand_variables(header, &self.variables) {
+            Ok(header) => header,
+            Err(msg) => {
+                println!("{}", msg);
+                return LineOutcome::Failure;
+            }
+        };
+        let parts: Vec<&str> = header.split_whitespace().collect();
+        let [name, "in", range] = parts.as_slice() else {
+            return usage();
+        };
+        let Some((start, end)) = range.split_once("..") else {
+            return usage();
+        };
+        let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) else {
+            return usage();
+        };
+        if !is_variable_name(name) {
+            println!("Invalid variable name: {}", name);
+            return LineOutcome::Failure;
+        }
+
+        let previous = self.variables.get(*name).cloned();
+        let mut outcome = LineOutcome::Success;
+        for value in start..=end {
+            self.variables.insert(name.to_string(), value.to_string());
+            match self.run_line(body) {
+                LineOutcome::Quit => {
+                    outcome = LineOutcome::Quit;
+                    break;
                 }
-                "del" => {
-                    if parts.len() != 3 {
-                        return Some(ParsedCommand::Error(format!("Usage: {} del <key>", address)));
-                    }
-                    let key = parts[2];
-                    Some(ParsedCommand::Del { address, key: key.to_string() })
+                LineOutcome::Failure => outcome = LineOutcome::Failure,
+                LineOutcome::Success => {}
+            }
+        }
+        match previous {
+            Some(previous) => self.variables.insert(name.to_string(), previous),
+            None => self.variables.remove(*name),
+        };
+        outcome
+    }
+
+    // Polls the key and prints its value whenever it changes, until the
+    // process is interrupted or the server can't be reached
+    fn watch(&mut self, address: &str, key: &str, interval: Duration) -> LineOutcome {
+        let request = format!("GET {}", key);
+        let mut last: Option<String> = None;
+        loop {
+            let response = match self.send(address, &request) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("Failed to reach {}: {}", address, e);
+                    return LineOutcome::Failure;
                 }
-                "keys" => {
-                    if parts.len() != 2 {
-                        return Some(ParsedCommand::Error(format!("Usage: {} keys", address)));
-                    }
-                    Some(ParsedCommand::Keys { address })
+            };
+            if response.starts_with("ERROR") {
+                return self.print_response(&response, false);
+            }
+            if last.as_deref() != Some(response.as_str()) {
+                self.print_response(&response, false);
+                io::stdout().flush().ok();
+                last = Some(response);
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    fn send(&mut self, address: &str, request: &str) -> io::Result<String> {
+        // A pooled connection may have been closed by the server's idle
+        // timeout, so retry once on a fresh one
+        match self.send_on_pooled(address, request) {
+            Ok(response) => Ok(response),
+            Err(_) => {
+                self.connections.remove(address);
+                self.send_on_pooled(address, request)
+            }
+        }
+    }
+
+    fn send_on_pooled(&mut self, address: &str, request: &str) -> io::Result<String> {
+        if !self.connections.contains_key(address) {
+            let stream = TcpStream::connect(address)?;
+            let reader = BufReader::new(stream.try_clone()?);
+            self.connections.insert(address.to_string(), Connection { reader, writer: stream });
+        }
+
+        let connection = self.connections.get_mut(address)
+            .ok_or_else(|| io::Error::other("connection missing"))?;
+        connection.writer.write_all(format!("{}\n", request).as_bytes())?;
+
+        let mut response = String::new();
+        if connection.reader.read_line(&mut response)? == 0 {
+            self.connections.remove(address);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+        }
+        let response = response.trim_end_matches(['\r', '\n']);
+        Ok(strip_deprecation_warning(response).to_string())
+    }
+
+    fn print_response(&self, response: &str, split_lines: bool) -> LineOutcome {
+        if response.starts_with("ERROR") {
+            if self.raw {
+                eprintln!("{}", response);
+            } else {
+                println!("{}", response);
+            }
+            return LineOutcome::Failure;
+        }
+
+        if !self.raw {
+            if !response.is_empty() {
+                println!("{}", response);
+            }
+            return LineOutcome::Success;
+        }
+
+        match response {
+            "NULL" => println!(),
+            "(empty)" => {}
+            _ if split_lines => {
+                for item in response.split(' ') {
+                    println!("{}", item);
                 }
-                _ => Some(ParsedCommand::Error(format!("Unknown command: {}", command))),
             }
+            _ => println!("{}", response),
         }
+        LineOutcome::Success
     }
 }
 
-fn parse_quoted_value(input: &str) -> Option<String> {
-    if let Some(start) = input.find('"') {
-        if let Some(end) = input.rfind('"') {
-            if start != end {
-                return Some(input[start+1..end].to_string());
+// Prints the warning the server adds to replies of deprecated commands and
+// returns the reply without it
+fn strip_deprecation_warning(response: &str) -> &str {
+    let Some((reply, warning)) = response.rsplit_once(command_spec::DEPRECATED_MARKER) else {
+        return response;
+    };
+    let details: serde_json::Value = serde_json::from_str(warning).unwrap_or_default();
+    eprintln!(
+        "warning: {} is deprecated: {}",
+        details["command"].as_str().unwrap_or("command"),
+        details["message"].as_str().unwrap_or(warning)
+    );
+    reply
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Replaces `$name` and `${name}` with the value of a script variable. `$$`
+/// is a literal `$`, as is a `$` not followed by a name.
+fn expand_variables(input: &str, variables: &HashMap<String, String>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.split_once('}') {
+                Some((name, remainder)) => (name, remainder),
+                None => return Err("Unclosed ${ in command".to_string()),
+            },
+            None => {
+                let end = after
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(after.len());
+                after.split_at(end)
             }
+        };
+        if !is_variable_name(name) {
+            expanded.push('$');
+            rest = after;
+            continue;
         }
+        match variables.get(name) {
+            Some(value) => expanded.push_str(value),
+            None => return Err(format!("Undefined variable: ${}", name)),
+        }
+        rest = remainder;
     }
-    None
+    expanded.push_str(rest);
+    Ok(expanded)
 }
 
+fn print_help() {
+    println!("Available commands:");
+    for spec in command_spec::COMMANDS {
+        println!("  [ip:port] {:<22} - {}", cli_usage(spec), spec.summary);
+    }
+    println!("  [ip:port] watch <key> [seconds]  - Print a key's value whenever it changes, polling every second by default");
+    println!("  help [command]                   - Show this help message, or the syntax of one command");
+    println!("  quit/exit                        - Exit the CLI");
+    println!();
+    println!("Scripting:");
+    println!("  let <name> = <value>             - Define a variable, used as $name or ${{name}} ($$ for a literal $)");
+    println!("  for <name> in <start>..<end>: <command> - Run a command for every number from start to end");
+}
 
+fn print_command_help(topic: &str) -> LineOutcome {
+    let specs: Vec<&CommandSpec> = match command_spec::find(topic) {
+        Some(spec) => vec![spec],
+        None => command_spec::group(topic.split_whitespace().next().unwrap_or_default()).collect(),
+    };
+    if specs.is_empty() {
+        println!("Unknown command: {}", topic);
+        return LineOutcome::Failure;
+    }
+    for spec in specs {
+        println!("  [ip:port] {} - {}", cli_usage(spec), spec.summary);
+        if !spec.flags.is_empty() {
+            println!("      flags: {}", spec.flags.join(", "));
+        }
+        if let Some(message) = spec.deprecated {
+            println!("      deprecated: {}", message);
+        }
+    }
+    LineOutcome::Success
+}
 
 fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            if msg != USAGE {
+                eprintln!("{}", USAGE);
+            }
+            process::exit(2);
+        }
+    };
+
+    if let Some(path) = &options.replay {
+        let started = Instant::now();
+        match replay(path, &format!("{}:{}", options.host, options.port), options.speed) {
+            Ok(stats) => {
+                println!(
+                    "Replayed {} commands from {} clients in {:.2}s ({} errors)",
+                    stats.commands, stats.clients, started.elapsed().as_secs_f64(), stats.errors
+                );
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Replay failed: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut session = Session::new(format!("{}:{}", options.host, options.port), options.raw);
+
+    if options.smoke {
+        let address = session.default_address.clone();
+        process::exit(if smoke(&mut session, &address) { 0 } else { 1 });
+    }
+
+    if !options.command.is_empty() {
+        let outcome = session.run_line(&options.command.join(" "));
+        process::exit(if matches!(outcome, LineOutcome::Failure) { 1 } else { 0 });
+    }
+
     let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut failed = false;
     loop {
-        print!("hydrogen-cli> ");
-        io::stdout().flush().unwrap();
+        if interactive {
+            print!("hydrogen-cli> ");
+            io::stdout().flush().unwrap();
+        }
         
         let mut input = String::new();
         match stdin.lock().read_line(&mut input) {
+            Ok(0) => break,
             Ok(_) => {
-                let input = input.trim();
-                if input.is_empty() {
-                    continue;
-                }
-                
-                match parse_command_line(input) {
-                    Some(ParsedCommand::Quit) => {
-                        println!("Goodbye!");
+                match session.run_line(&input) {
+                    LineOutcome::Quit => {
+                        if interactive {
+                            println!("Goodbye!");
+                        }
                         break;
                     }
-                    Some(ParsedCommand::Help) => {
-                        println!("Available commands:");
-                        println!("  <ip:port> set <key> <value>      - Set a key-value pair");
-                        println!("  <ip:port> set <key> \"<value>\"    - Set a key-value pair with spaces");
-                        println!("  <ip:port> get <key>              - Get value for a key");
-                        println!("  <ip:port> del <key>              - Delete a key");
-                        println!("  <ip:port> keys                   - List all keys in the cache");
-                        println!("  help                             - Show this help message");
-                        println!("  quit/exit                        - Exit the CLI");
-                    }
-                    Some(ParsedCommand::Set { address, key, value }) => {
-                        execute_command(&address, &format!("SET {} \"{}\"", key, value));
-                    }
-                    Some(ParsedCommand::Get { address, key }) => {
-                        execute_command(&address, &format!("GET {}", key));
-                    }
-                    Some(ParsedCommand::Del { address, key }) => {
-                        execute_command(&address, &format!("DEL {}", key));
-                    }
-                    Some(ParsedCommand::Keys { address }) => {
-                        execute_command(&address, "KEYS");
-                    }
-                    Some(ParsedCommand::Error(msg)) => {
-                        println!("{}", msg);
-                    }
-                    None => {
-                        println!("Usage: <ip:port> <command> [args...]");
-                        println!("Type 'help' for available commands.");
-                    }
+                    LineOutcome::Failure => failed = true,
+                    LineOutcome::Success => {}
                 }
             }
             Err(e) => {
@@ -146,37 +866,8 @@ fn main() {
             }
         }
     }
-}
 
-fn execute_command(address: &str, command: &str) {
-    match TcpStream::connect(address) {
-        Ok(mut stream) => {
-            if let Err(e) = stream.write_all(command.as_bytes()) {
-                println!("Failed to send command: {}", e);
-                return;
-            }
-
-            if let Err(e) = stream.write_all(b"\n") {
-                println!("Failed to send newline: {}", e);
-                return;
-            }
-
-            let mut reader = BufReader::new(&mut stream);
-            let mut response = String::new();
-            match reader.read_line(&mut response) {
-                Ok(_) => {
-                    let trimmed = response.trim();
-                    if !trimmed.is_empty() {
-                        println!("{}", trimmed);
-                    }
-                }
-                Err(e) => {
-                    println!("Failed to read response: {}", e);
-                }
-            }
-        }
-        Err(e) => {
-            println!("Failed to connect to {}: {}", address, e);
-        }
+    if !interactive && failed {
+        process::exit(1);
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,657 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+mod command_spec;
+mod slots;
+
+use command_spec::CommandSpec;
+use slots::{key_slot, SLOT_COUNT};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info, warn};
+
+const USAGE: &str = "Usage: hydrogen-proxy [-l listen-address] [-n node-address] [--pool-size n] [--refresh seconds]";
+// Read from the environment rather than a flag so it stays out of `ps`
+const PASSWORD_ENV: &str = "HYDROGEN_PROXY_PASSWORD";
+const NOAUTH_REPLY: &str = "ERROR: Authentication required";
+const CLUSTER_DISABLED_REPLY: &str = "ERROR: Cluster mode is disabled";
+// Largest RESP argument or HTTP body accepted from a client
+const MAX_BULK_LEN: usize = 64 * 1024 * 1024;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ProxyOptions {
+    listen: String,
+    /// Node asked for the slot table, and sent every request without a key
+    node: String,
+    /// Idle connections kept open per node
+    pool_size: usize,
+    refresh: Duration,
+    password: Option<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<ProxyOptions, String> {
+    let mut options = ProxyOptions {
+        listen: "0.0.0.0:1826".to_string(),
+        node: "127.0.0.1:1825".to_string(),
+        pool_size: 16,
+        refresh: Duration::from_secs(10),
+        password: std::env::var(PASSWORD_ENV).ok().filter(|password| !password.is_empty()),
+    };
+
+    let mut args = args;
+    while let Some(flag) = args.next() {
+        if flag == "--help" {
+            return Err(USAGE.to_string());
+        }
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "-l" | "--listen" => options.listen = value.clone(),
+            "-n" | "--node" => options.node = value.clone(),
+            "--pool-size" => options.pool_size = value.parse().map_err(|_| invalid())?,
+            "--refresh" => options.refresh = Duration::from_secs(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option: {}", flag)),
+        }
+    }
+
+    if options.refresh.is_zero() {
+        return Err("Refresh interval must be greater than zero".to_string());
+    }
+    Ok(options)
+}
+
+/// Which node serves each slot, as CLUSTER SLOTS reports it. A node
+/// running without cluster mode serves every slot itself.
+#[derive(Debug)]
+struct Routes {
+    nodes: Vec<String>,
+    /// Index into `nodes` for every slot, `None` where no node owns it
+    slots: Vec<Option<usize>>,
+}
+
+impl Routes {
+    fn single(address: &str) -> Self {
+        Self { nodes: vec![address.to_string()], slots: vec![Some(0); SLOT_COUNT as usize] }
+    }
+
+    // "0-8191 id 10.0.0.1:1825, 8192-16383 id 10.0.0.2:1825"
+    fn parse(reply: &str) -> Option<Self> {
+        let mut routes = Self { nodes: Vec::new(), slots: vec![None; SLOT_COUNT as usize] };
+        if reply == "(empty)" {
+            return Some(routes);
+        }
+        for range in reply.split(", ") {
+            let mut parts = range.split_whitespace();
+            let (slots, _node_id, address) = (parts.next()?, parts.next()?, parts.next()?);
+            let (start, end) = slots.split_once('-')?;
+            let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+            if start > end || end >= SLOT_COUNT as usize {
+                return None;
+            }
+            let index = match routes.nodes.iter().position(|node| node == address) {
+                Some(index) => index,
+                None => {
+                    routes.nodes.push(address.to_string());
+                    routes.nodes.len() - 1
+                }
+            };
+            routes.slots[start..=end].fill(Some(index));
+        }
+        Some(routes)
+    }
+
+    fn node_for(&self, key: &str) -> Option<&str> {
+        self.slots[key_slot(key) as usize].map(|index| self.nodes[index].as_str())
+    }
+}
+
+/// Idle connections to one node. A connection only goes back to the pool
+/// after a whole reply was read, so requests never interleave on it.
+#[derive(Debug)]
+struct Pool {
+    address: String,
+    max_idle: usize,
+    idle: Mutex<Vec<BufReader<TcpStream>>>,
+}
+
+impl Pool {
+    async fn request(&self, line: &str) -> std::io::Result<String> {
+        let pooled = lock(&self.idle).pop();
+        if let Some(connection) = pooled {
+            match self.exchange(connection, line).await {
+                // The node closes connections that sat idle too long
+                // (idle_timeout_secs); the request never reached it, so it
+                // is safe to send again
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {}
+                result => return result,
+            }
+        }
+        let connection = BufReader::new(TcpStream::connect(&self.address).await?);
+        self.exchange(connection, line).await
+    }
+
+    async fn exchange(&self, mut connection: BufReader<TcpStream>, line: &str) -> std::io::Result<String> {
+        connection.get_mut().write_all(format!("{}\n", line).as_bytes()).await?;
+        let mut reply = String::new();
+        if connection.read_line(&mut reply).await? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "node closed the connection"));
+        }
+
+        let mut idle = lock(&self.idle);
+        if idle.len() < self.max_idle {
+            idle.push(connection);
+        }
+        Ok(reply.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+struct Proxy {
+    options: ProxyOptions,
+    routes: RwLock<Arc<Routes>>,
+    pools: Mutex<HashMap<String, Arc<Pool>>>,
+}
+
+impl Proxy {
+    fn new(options: ProxyOptions) -> Self {
+        let routes = Routes::single(&options.node);
+        Self { options, routes: RwLock::new(Arc::new(routes)), pools: Mutex::new(HashMap::new()) }
+    }
+
+    fn routes(&self) -> Arc<Routes> {
+        match self.routes.read() {
+            Ok(routes) => Arc::clone(&routes),
+            Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+        }
+    }
+
+    fn pool(&self, address: &str) -> Arc<Pool> {
+        let mut pools = lock(&self.pools);
+        let pool = pools.entry(address.to_string()).or_insert_with(|| {
+            Arc::new(Pool { address: address.to_string(), max_idle: self.options.pool_size, idle: Mutex::new(Vec::new()) })
+        });
+        Arc::clone(pool)
+    }
+
+    /// Reloads the slot table, asking the configured node first and then the
+    /// nodes the current table knows, so losing one node does not stop
+    /// refreshes.
+    async fn refresh_routes(&self) -> Result<(), String> {
+        let mut candidates = vec![self.options.node.clone()];
+        candidates.extend(self.routes().nodes.iter().filter(|node| **node != self.options.node).cloned());
+
+        for address in candidates {
+            let routes = match self.pool(&address).request("CLUSTER SLOTS").await {
+                Ok(reply) if reply == CLUSTER_DISABLED_REPLY => Some(Routes::single(&address)),
+                Ok(reply) if reply.starts_with("ERROR") => None,
+                Ok(reply) => Routes::parse(&reply),
+                Err(e) => {
+                    warn!("Failed to fetch slots from {}: {}", address, e);
+                    continue;
+                }
+            };
+            let Some(routes) = routes else {
+                warn!("Unexpected CLUSTER SLOTS reply from {}", address);
+                continue;
+            };
+            match self.routes.write() {
+                Ok(mut current) => *current = Arc::new(routes),
+                Err(poisoned) => *poisoned.into_inner() = Arc::new(routes),
+            }
+            return Ok(());
+        }
+        Err("no node returned a slot table".to_string())
+    }
+
+    /// Sends one text request to the node owning its key, or to the
+    /// configured node for requests without a key. Errors are the proxy's
+    /// own, such as an unreachable node; replies from a node, errors
+    /// included, are `Ok`.
+    async fn forward(&self, request: &str) -> Result<String, String> {
+        let command = strip_deadline(request);
+        let spec = command_spec::find(command);
+        // Pooled connections are shared between clients, so per-connection
+        // state cannot be carried over
+        if spec.is_some_and(|spec| spec.name == "SELECT") {
+            return Err("ERROR: SELECT is not supported through the proxy".to_string());
+        }
+
+        let address = match spec.and_then(|spec| routing_key(spec, command)) {
+            Some(key) => self.routes().node_for(&key).map(str::to_string).ok_or_else(|| {
+                format!("ERROR: Slot {} is not assigned to any node", key_slot(&key))
+            })?,
+            None => self.options.node.clone(),
+        };
+        self.pool(&address)
+            .request(request)
+            .await
+            .map_err(|e| format!("ERROR: Node {} is unavailable: {}", address, e))
+    }
+}
+
+fn strip_deadline(request: &str) -> &str {
+    if request.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("*TO=")) {
+        return request.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start());
+    }
+    request
+}
+
+// The key a request is routed by: the argument named "key", or the first of
+// "keys", whose node then gathers the rest itself (MGET)
+fn routing_key(spec: &CommandSpec, request: &str) -> Option<String> {
+    let position = spec.args.iter().position(|arg| arg.name == "key" || arg.name == "keys")?;
+    let mut rest = request.trim_start();
+    // Arguments before the key are plain words, such as SETAT's timestamp
+    for _ in 0..spec.name.split(' ').count() + position {
+        rest = rest.split_once(' ')?.1.trim_start();
+    }
+    Some(take_key(rest))
+}
+
+// Same rules as the server: a double-quoted key may use \" \\ \n \r \t and
+// \0 escapes, any other key ends at the first space
+fn take_key(args: &str) -> String {
+    let Some(quoted) = args.strip_prefix('"') else {
+        return args.split(' ').next().unwrap_or_default().to_string();
+    };
+    let mut key = String::new();
+    let mut chars = quoted.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => key.push('\n'),
+                Some('r') => key.push('\r'),
+                Some('t') => key.push('\t'),
+                Some('0') => key.push('\0'),
+                Some(escaped) => key.push(escaped),
+                None => break,
+            },
+            _ => key.push(ch),
+        }
+    }
+    key
+}
+
+// Arguments arriving as RESP bulk strings or URL segments, quoted when the
+// text protocol would otherwise split them
+fn text_argument(arg: &str) -> String {
+    if arg.is_empty() || arg.starts_with('"') || arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+fn passwords_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// One client connection. Every connection starts unauthenticated when a
+/// password is set.
+struct Session {
+    proxy: Arc<Proxy>,
+    authenticated: bool,
+}
+
+impl Session {
+    async fn execute(&mut self, request: &str) -> Result<String, String> {
+        let mut words = request.split_whitespace();
+        if words.next().is_some_and(|word| word.eq_ignore_ascii_case("AUTH")) {
+            let Some(expected) = &self.proxy.options.password else {
+                return Err("ERROR: No password is set on this proxy".to_string());
+            };
+            self.authenticated = words.next().is_some_and(|given| passwords_match(expected, given));
+            return if self.authenticated { Ok("OK".to_string()) } else { Err("ERROR: Invalid password".to_string()) };
+        }
+        if !self.authenticated {
+            return Err(NOAUTH_REPLY.to_string());
+        }
+        self.proxy.forward(request).await
+    }
+}
+
+// Picks the protocol from the first line a client sends: a RESP array
+// header, an HTTP request line, or else a Hydrogen text command
+async fn serve(stream: TcpStream, proxy: Arc<Proxy>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut first = String::new();
+    if reader.read_line(&mut first).await? == 0 {
+        return Ok(());
+    }
+
+    let authenticated = proxy.options.password.is_none();
+    let mut session = Session { proxy, authenticated };
+    let line = first.trim_end_matches(['\r', '\n']);
+    if line.len() > 1 && line.starts_with('*') && line[1..].bytes().all(|b| b.is_ascii_digit()) {
+        serve_resp(&mut reader, &mut writer, &mut session, line).await
+    } else if is_http_request(line) {
+        serve_http(&mut reader, &mut writer, &session, line).await
+    } else {
+        serve_text(&mut reader, &mut writer, &mut session, line).await
+    }
+}
+
+async fn serve_text(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    session: &mut Session,
+    first: &str,
+) -> std::io::Result<()> {
+    let mut line = first.to_string();
+    loop {
+        let request = line.trim();
+        if !request.is_empty() {
+            let reply = session.execute(request).await.unwrap_or_else(|e| e);
+            writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+        }
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// RESP clients such as redis-cli send arrays of bulk strings, which are
+/// joined into one text command. Replies come back as simple strings for OK
+/// and PONG, null for NULL, errors for ERROR and bulk strings otherwise.
+async fn serve_resp(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    session: &mut Session,
+    first: &str,
+) -> std::io::Result<()> {
+    let mut header = first.to_string();
+    loop {
+        let request = match header.strip_prefix('*') {
+            Some(count) => {
+                let count: usize = count.parse().map_err(|_| Error::new(ErrorKind::InvalidData, "invalid array header"))?;
+                let mut args = Vec::with_capacity(count.min(1024));
+                for _ in 0..count {
+                    args.push(read_bulk_string(reader).await?);
+                }
+                if args.iter().any(|arg| arg.contains(['\r', '\n'])) {
+                    Err("ERROR: Arguments with line breaks are not supported".to_string())
+                } else {
+                    Ok(args.iter().map(|arg| text_argument(arg)).collect::<Vec<String>>().join(" "))
+                }
+            }
+            // Inline commands, as typed into telnet
+            None => Ok(header.trim().to_string()),
+        };
+
+        let reply = match request {
+            Ok(request) if request.is_empty() => None,
+            Ok(request) => Some(session.execute(&request).await.unwrap_or_else(|e| e)),
+            Err(e) => Some(e),
+        };
+        if let Some(reply) = reply {
+            writer.write_all(resp_reply(&reply).as_bytes()).await?;
+        }
+
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(());
+        }
+        header.truncate(header.trim_end_matches(['\r', '\n']).len());
+    }
+}
+
+async fn read_bulk_string(reader: &mut (impl AsyncBufRead + Unpin)) -> std::io::Result<String> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut header = String::new();
+    reader.read_line(&mut header).await?;
+    let len: usize = header.trim_end()
+        .strip_prefix('$')
+        .and_then(|len| len.parse().ok())
+        .ok_or_else(|| invalid("expected a bulk string"))?;
+    if len > MAX_BULK_LEN {
+        return Err(invalid("bulk string too long"));
+    }
+    let mut data = vec![0; len + 2];
+    reader.read_exact(&mut data).await?;
+    data.truncate(len);
+    String::from_utf8(data).map_err(|_| invalid("bulk string is not UTF-8"))
+}
+
+// RESP has no place for warnings, so the one added for deprecated commands
+// is dropped
+fn resp_reply(reply: &str) -> String {
+    let reply = reply.rsplit_once(command_spec::DEPRECATED_MARKER).map_or(reply, |(reply, _)| reply);
+    if let Some(message) = reply.strip_prefix("ERROR") {
+        return format!("-ERR {}\r\n", message.trim_start_matches(':').trim_start());
+    }
+    match reply {
+        "OK" | "PONG" => format!("+{}\r\n", reply),
+        "NULL" => "$-1\r\n".to_string(),
+        _ => format!("${}\r\n{}\r\n", reply.len(), reply),
+    }
+}
+
+fn is_http_request(line: &str) -> bool {
+    let mut parts = line.split(' ');
+    matches!(parts.next(), Some("GET" | "POST" | "PUT"))
+        && parts.next().is_some_and(|path| path.starts_with('/'))
+        && parts.next().is_some_and(|version| version.starts_with("HTTP/"))
+}
+
+/// Maps `GET /get/user:1` to `GET user:1`: path segments are the command
+/// and its arguments, and a request body is sent as one more, quoted,
+/// argument, as in `POST /set/user:1` with the value as the body. Commands
+/// that change data or manage the server need POST or PUT. `GET /` lists
+/// the commands. With a password set, requests need an
+/// `Authorization: Bearer <password>` header. Replies to deprecated
+/// commands carry a `Warning` header. One request per connection.
+async fn serve_http(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    session: &Session,
+    request_line: &str,
+) -> std::io::Result<()> {
+    let mut content_length = 0usize;
+    let mut authorization = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(());
+        }
+        let line = header.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("Authorization") {
+                authorization = value.trim().strip_prefix("Bearer ").map(str::to_string);
+            }
+        }
+    }
+    if content_length > MAX_BULK_LEN {
+        return http_respond(writer, "413 Payload Too Large", "request body too large").await;
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    if let Some(expected) = &session.proxy.options.password
+        && !authorization.is_some_and(|given| passwords_match(expected, &given))
+    {
+        return http_respond(writer, "401 Unauthorized", NOAUTH_REPLY).await;
+    }
+
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let Some(segments) = path.split('/').filter(|segment| !segment.is_empty()).map(percent_decode).collect::<Option<Vec<String>>>() else {
+        return http_respond(writer, "400 Bad Request", "invalid percent-encoding in path").await;
+    };
+
+    if segments.is_empty() {
+        let listing: Vec<String> = command_spec::COMMANDS
+            .iter()
+            .map(|spec| match spec.deprecated {
+                Some(message) => format!("{} - {} (deprecated: {})", spec, spec.summary, message),
+                None => format!("{} - {}", spec, spec.summary),
+            })
+            .collect();
+        return http_respond(writer, "200 OK", &listing.join("\n")).await;
+    }
+    let words = segments.iter().take(3).map(String::as_str).collect::<Vec<&str>>().join(" ");
+    let Some(spec) = command_spec::find(&words) else {
+        let usages: Vec<String> = command_spec::group(&segments[0]).map(|spec| spec.to_string()).collect();
+        let body = if usages.is_empty() { "unknown command".to_string() } else { format!("usage: {}", usages.join(" | ")) };
+        return http_respond(writer, "404 Not Found", &body).await;
+    };
+    let changes_data = spec.flags.iter().any(|flag| *flag == "write" || *flag == "admin");
+    if changes_data && method == "GET" {
+        return http_respond(writer, "405 Method Not Allowed", &format!("{} needs POST or PUT", spec.name)).await;
+    }
+
+    let mut request = spec.name.to_string();
+    for arg in &segments[spec.name.split(' ').count()..] {
+        request.push(' ');
+        request.push_str(&text_argument(arg));
+    }
+    if !body.is_empty() {
+        let Ok(body) = String::from_utf8(body) else {
+            return http_respond(writer, "400 Bad Request", "request body is not UTF-8").await;
+        };
+        if body.contains(['\r', '\n']) {
+            return http_respond(writer, "400 Bad Request", "request body must be one line").await;
+        }
+        request.push_str(&format!(" \"{}\"", body));
+    }
+
+    let reply = match session.proxy.forward(&request).await {
+        Ok(reply) => reply,
+        Err(e) => return http_respond(writer, "502 Bad Gateway", &e).await,
+    };
+    let (reply, warning) = match reply.rsplit_once(command_spec::DEPRECATED_MARKER) {
+        Some((reply, warning)) => (reply, Some(warning_header(warning))),
+        None => (reply.as_str(), None),
+    };
+    let status = match reply {
+        _ if reply.starts_with("ERROR") => "400 Bad Request",
+        "NULL" => "404 Not Found",
+        _ => "200 OK",
+    };
+    http_send(writer, status, warning.as_deref().unwrap_or_default(), reply).await
+}
+
+// The server's deprecation warning as an HTTP Warning header, code 299
+// ("miscellaneous persistent warning")
+fn warning_header(warning: &str) -> String {
+    let details: serde_json::Value = serde_json::from_str(warning).unwrap_or_default();
+    let text = format!(
+        "{} is deprecated: {}",
+        details["command"].as_str().unwrap_or("command"),
+        details["message"].as_str().unwrap_or(warning)
+    );
+    format!("Warning: 299 hydrogen-proxy \"{}\"\r\n", text.replace(['"', '\\'], "'"))
+}
+
+async fn http_respond(writer: &mut (impl AsyncWrite + Unpin), status: &str, body: &str) -> std::io::Result<()> {
+    http_send(writer, status, "", body).await
+}
+
+// `headers` are extra header lines, each ending in CRLF
+async fn http_send(writer: &mut (impl AsyncWrite + Unpin), status: &str, headers: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        headers,
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            if msg != USAGE {
+                eprintln!("{}", USAGE);
+            }
+            process::exit(2);
+        }
+    };
+    tracing_subscriber::fmt().with_target(false).init();
+
+    let listener = match TcpListener::bind(&options.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", options.listen, e);
+            process::exit(1);
+        }
+    };
+    let proxy = Arc::new(Proxy::new(options));
+    if let Err(e) = proxy.refresh_routes().await {
+        warn!("Starting without a slot table, sending everything to {}: {}", proxy.options.node, e);
+    }
+
+    let refresher = Arc::clone(&proxy);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(refresher.options.refresh).await;
+            if let Err(e) = refresher.refresh_routes().await {
+                warn!("Slot table refresh failed, keeping the previous one: {}", e);
+            }
+        }
+    });
+
+    info!(
+        "hydrogen-proxy listening on {} for {} node(s), auth {}",
+        proxy.options.listen,
+        proxy.routes().nodes.len(),
+        if proxy.options.password.is_some() { "on" } else { "off" }
+    );
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let proxy = Arc::clone(&proxy);
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, proxy).await {
+                        error!("Proxy connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Failed to accept connection: {}", e),
+        }
+    }
+}
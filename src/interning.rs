@@ -0,0 +1,177 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Splits a key after its first ':', so "session:42" has the prefix
+/// "session:". Keys without one have an empty prefix.
+fn split(key: &str) -> (&str, &str) {
+    match key.find(':') {
+        Some(index) => key.split_at(index + 1),
+        None => ("", key),
+    }
+}
+
+/// The two halves of a key, whether stored or borrowed for a lookup. Both
+/// hash and compare the same way, which lets a map keyed by `InternedKey`
+/// be searched with a plain `&str`.
+pub trait KeyParts {
+    fn prefix(&self) -> &str;
+    fn suffix(&self) -> &str;
+}
+
+impl Hash for dyn KeyParts + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(self.prefix().as_bytes());
+        state.write(self.suffix().as_bytes());
+        state.write_u8(0xff);
+    }
+}
+
+impl PartialEq for dyn KeyParts + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefix() == other.prefix() && self.suffix() == other.suffix()
+    }
+}
+
+impl Eq for dyn KeyParts + '_ {}
+
+/// A borrowed key, split for a lookup.
+pub struct KeyRef<'a> {
+    prefix: &'a str,
+    suffix: &'a str,
+}
+
+impl<'a> KeyRef<'a> {
+    pub fn new(key: &'a str) -> Self {
+        let (prefix, suffix) = split(key);
+        Self { prefix, suffix }
+    }
+}
+
+impl KeyParts for KeyRef<'_> {
+    fn prefix(&self) -> &str {
+        self.prefix
+    }
+
+    fn suffix(&self) -> &str {
+        self.suffix
+    }
+}
+
+/// A stored key whose prefix is shared with every other key that has it.
+#[derive(Debug, Clone)]
+pub struct InternedKey {
+    prefix: Arc<str>,
+    suffix: Box<str>,
+}
+
+impl InternedKey {
+    /// The part before the first ':', like `namespace_of`.
+    pub fn namespace(&self) -> Option<&str> {
+        self.prefix.strip_suffix(':')
+    }
+
+    pub fn starts_with(&self, pattern: &str) -> bool {
+        match pattern.strip_prefix(&*self.prefix) {
+            Some(rest) => self.suffix.starts_with(rest),
+            None => self.prefix.starts_with(pattern),
+        }
+    }
+}
+
+impl KeyParts for InternedKey {
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn suffix(&self) -> &str {
+        &self.suffix
+    }
+}
+
+impl Hash for InternedKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self as &dyn KeyParts).hash(state)
+    }
+}
+
+impl PartialEq for InternedKey {
+    fn eq(&self, other: &Self) -> bool {
+        (self as &dyn KeyParts) == (other as &dyn KeyParts)
+    }
+}
+
+impl Eq for InternedKey {}
+
+impl<'a> Borrow<dyn KeyParts + 'a> for InternedKey {
+    fn borrow(&self) -> &(dyn KeyParts + 'a) {
+        self
+    }
+}
+
+impl fmt::Display for InternedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.prefix, self.suffix)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterningStats {
+    /// Distinct non-empty prefixes
+    pub prefixes: usize,
+    /// Keys that share one of them
+    pub keys: usize,
+    /// Prefix bytes that would have been stored again without interning
+    pub bytes_saved: usize,
+}
+
+/// The prefixes in use by one keyspace. A prefix is dropped from the table
+/// once no key refers to it any more.
+#[derive(Debug, Default)]
+pub struct PrefixTable {
+    prefixes: HashSet<Arc<str>>,
+}
+
+impl PrefixTable {
+    pub fn intern(&mut self, key: &str) -> InternedKey {
+        let (prefix, suffix) = split(key);
+        let prefix = match self.prefixes.get(prefix) {
+            Some(shared) => Arc::clone(shared),
+            None => {
+                let shared: Arc<str> = Arc::from(prefix);
+                self.prefixes.insert(Arc::clone(&shared));
+                shared
+            }
+        };
+        InternedKey { prefix, suffix: suffix.into() }
+    }
+
+    /// Drops `key`, and its prefix if this was the last key using it.
+    pub fn release(&mut self, key: InternedKey) {
+        let prefix = key.prefix;
+        // The table holds one reference and `prefix` the other
+        if Arc::strong_count(&prefix) == 2 {
+            self.prefixes.remove(&*prefix);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.prefixes.clear();
+    }
+
+    pub fn stats(&self) -> InterningStats {
+        let mut stats = InterningStats::default();
+        for prefix in self.prefixes.iter().filter(|prefix| !prefix.is_empty()) {
+            let users = Arc::strong_count(prefix) - 1;
+            stats.prefixes += 1;
+            stats.keys += users;
+            stats.bytes_saved += users.saturating_sub(1) * prefix.len();
+        }
+        stats
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use crate::cache::{CompressionStats, Databases, ValueType};
+use crate::command_spec::COMMANDS;
+use crate::supervisor::Supervisor;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+struct ClientEntry {
+    addr: String,
+    connected: Instant,
+    last_active: Instant,
+    last_command: &'static str,
+    db: usize,
+    kill: Arc<Notify>,
+}
+
+/// Every open connection, for CLIENT LIST and CLIENT KILL.
+#[derive(Default)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, ClientEntry>>,
+}
+
+impl ClientRegistry {
+    pub fn register(self: &Arc<Self>, addr: &str) -> ClientHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let kill = Arc::new(Notify::new());
+        let now = Instant::now();
+        lock(&self.clients).insert(id, ClientEntry {
+            addr: addr.to_string(),
+            connected: now,
+            last_active: now,
+            last_command: "NULL",
+            db: 0,
+            kill: Arc::clone(&kill),
+        });
+        ClientHandle { registry: Arc::clone(self), id, kill }
+    }
+
+    /// One `id=.. addr=..` entry per client, oldest connection first.
+    pub fn list(&self) -> Vec<String> {
+        let clients = lock(&self.clients);
+        let mut ids: Vec<&u64> = clients.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| {
+                let client = &clients[id];
+                format!(
+                    "id={} addr={} age={} idle={} db={} cmd={}",
+                    id,
+                    client.addr,
+                    client.connected.elapsed().as_secs(),
+                    client.last_active.elapsed().as_secs(),
+                    client.db,
+                    client.last_command,
+                )
+            })
+            .collect()
+    }
+
+    /// Asks the connection with this address to close. It is dropped the
+    /// next time it waits for a request.
+    pub fn kill(&self, addr: &str) -> bool {
+        let clients = lock(&self.clients);
+        match clients.values().find(|client| client.addr == addr) {
+            Some(client) => {
+                client.kill.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A connection's entry in the registry, removed again when dropped.
+pub struct ClientHandle {
+    registry: Arc<ClientRegistry>,
+    id: u64,
+    kill: Arc<Notify>,
+}
+
+impl ClientHandle {
+    pub fn record_command(&self, command: &'static str, db: usize) {
+        if let Some(client) = lock(&self.registry.clients).get_mut(&self.id) {
+            client.last_active = Instant::now();
+            client.last_command = command;
+            client.db = db;
+        }
+    }
+
+    pub async fn killed(&self) {
+        self.kill.notified().await;
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        lock(&self.registry.clients).remove(&self.id);
+    }
+}
+
+struct SlowLogEntry {
+    id: u64,
+    timestamp: u64,
+    duration: Duration,
+    client: String,
+    command: &'static str,
+    key: Option<String>,
+}
+
+/// The most recent commands that took longer than `slowlog_threshold_us`,
+/// newest first. Like the audit log it records keys but never values.
+#[derive(Default)]
+pub struct SlowLog {
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    pub fn record(&self, client: &str, command: &'static str, key: Option<&str>, duration: Duration, max_len: usize) {
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration,
+            client: client.to_string(),
+            command,
+            key: key.map(str::to_string),
+        };
+        let mut entries = lock(&self.entries);
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    pub fn get(&self, count: usize) -> Vec<String> {
+        lock(&self.entries)
+            .iter()
+            .take(count)
+            .map(|entry| {
+                let mut line = format!(
+                    "id={} time={} duration_us={} client={} cmd={}",
+                    entry.id, entry.timestamp, entry.duration.as_micros(), entry.client, entry.command
+                );
+                if let Some(key) = &entry.key {
+                    line.push_str(&format!(" key={}", key));
+                }
+                line
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.entries).len()
+    }
+
+    pub fn reset(&self) {
+        lock(&self.entries).clear();
+    }
+}
+
+#[derive(Default)]
+struct CommandCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    micros: AtomicU64,
+}
+
+/// Calls, errors and time spent for every command in protocol.toml, kept
+/// in the same order.
+pub struct CommandStats {
+    index: HashMap<&'static str, usize>,
+    counters: Vec<CommandCounters>,
+}
+
+impl Default for CommandStats {
+    fn default() -> Self {
+        Self {
+            index: COMMANDS.iter().enumerate().map(|(i, spec)| (spec.name, i)).collect(),
+            counters: COMMANDS.iter().map(|_| CommandCounters::default()).collect(),
+        }
+    }
+}
+
+impl CommandStats {
+    pub fn record(&self, command: &str, duration: Duration, failed: bool) {
+        let Some(&i) = self.index.get(command) else {
+            return;
+        };
+        let counters = &self.counters[i];
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        counters.micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        if failed {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// One line per command that has been called at least once.
+    pub fn lines(&self) -> Vec<String> {
+        COMMANDS
+            .iter()
+            .zip(&self.counters)
+            .filter_map(|(spec, counters)| {
+                let calls = counters.calls.load(Ordering::Relaxed);
+                if calls == 0 {
+                    return None;
+                }
+                let micros = counters.micros.load(Ordering::Relaxed);
+                Some(format!(
+                    "cmd={} calls={} errors={} usec={} usec_per_call={:.2}",
+                    spec.name,
+                    calls,
+                    counters.errors.load(Ordering::Relaxed),
+                    micros,
+                    micros as f64 / calls as f64
+                ))
+            })
+            .collect()
+    }
+}
+
+// Hosts remembered per deprecated command; the least recently seen is
+// dropped first
+const MAX_DEPRECATED_CLIENTS: usize = 64;
+
+#[derive(Default)]
+struct DeprecatedUsage {
+    calls: AtomicU64,
+    /// Client host, without the port, to when it last used the command
+    clients: Mutex<HashMap<String, u64>>,
+}
+
+/// Which clients still use commands marked deprecated in protocol.toml, so
+/// they can be found and updated before the commands are removed.
+pub struct DeprecationStats {
+    index: HashMap<&'static str, usize>,
+    usage: Vec<DeprecatedUsage>,
+}
+
+impl Default for DeprecationStats {
+    fn default() -> Self {
+        Self {
+            index: COMMANDS.iter().enumerate().map(|(i, spec)| (spec.name, i)).collect(),
+            usage: COMMANDS.iter().map(|_| DeprecatedUsage::default()).collect(),
+        }
+    }
+}
+
+impl DeprecationStats {
+    /// Counts a call of `command` if it is deprecated, returning the
+    /// deprecation message.
+    pub fn record(&self, command: &str, client: &str) -> Option<&'static str> {
+        let &i = self.index.get(command)?;
+        let message = COMMANDS[i].deprecated?;
+        let usage = &self.usage[i];
+        usage.calls.fetch_add(1, Ordering::Relaxed);
+
+        // Connections from one host differ only in their port
+        let host = client.rsplit_once(':').map_or(client, |(host, _)| host);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut clients = lock(&usage.clients);
+        if !clients.contains_key(host) && clients.len() >= MAX_DEPRECATED_CLIENTS {
+            let oldest = clients.iter().min_by_key(|(_, last_used)| **last_used).map(|(host, _)| host.clone());
+            if let Some(oldest) = oldest {
+                clients.remove(&oldest);
+            }
+        }
+        clients.insert(host.to_string(), now);
+        Some(message)
+    }
+
+    /// One line per deprecated command that has been called, with the hosts
+    /// that called it, most recent first.
+    pub fn lines(&self) -> Vec<String> {
+        COMMANDS
+            .iter()
+            .zip(&self.usage)
+            .filter_map(|(spec, usage)| {
+                let calls = usage.calls.load(Ordering::Relaxed);
+                if spec.deprecated.is_none() || calls == 0 {
+                    return None;
+                }
+                let mut clients: Vec<(String, u64)> = lock(&usage.clients).iter().map(|(host, time)| (host.clone(), *time)).collect();
+                clients.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+                let last_used = clients.first().map_or(0, |(_, time)| *time);
+                let hosts: Vec<String> = clients.into_iter().map(|(host, _)| host).collect();
+                Some(format!("cmd={} calls={} last_used={} clients={}", spec.name, calls, last_used, hosts.join("|")))
+            })
+            .collect()
+    }
+}
+
+/// A part of the INFO report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoSection {
+    Compression,
+    Types,
+    Memory,
+    Tasks,
+    Deprecated,
+}
+
+impl InfoSection {
+    pub const ALL: [InfoSection; 5] = [
+        InfoSection::Compression,
+        InfoSection::Types,
+        InfoSection::Memory,
+        InfoSection::Tasks,
+        InfoSection::Deprecated,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "compression" => Some(InfoSection::Compression),
+            "types" => Some(InfoSection::Types),
+            "memory" => Some(InfoSection::Memory),
+            "tasks" => Some(InfoSection::Tasks),
+            "deprecated" => Some(InfoSection::Deprecated),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InfoSection::Compression => "compression",
+            InfoSection::Types => "types",
+            InfoSection::Memory => "memory",
+            InfoSection::Tasks => "tasks",
+            InfoSection::Deprecated => "deprecated",
+        }
+    }
+
+    /// The lines of this section, each starting with the section name.
+    pub async fn render(&self, databases: &Databases, supervisor: &Supervisor, deprecations: &DeprecationStats) -> Vec<String> {
+        let lines = match self {
+            InfoSection::Compression => compression_info(databases).await,
+            InfoSection::Types => value_type_info(databases).await,
+            InfoSection::Memory => memory_info(databases).await,
+            InfoSection::Tasks => supervisor.lines(),
+            InfoSection::Deprecated => deprecations.lines(),
+        };
+        lines.into_iter().map(|line| format!("{} {}", self.name(), line)).collect()
+    }
+}
+
+// One line for all keys (prefix=*) followed by one per namespace, where
+// prefix=(none) covers keys without one
+async fn compression_info(databases: &Databases) -> Vec<String> {
+    let mut prefixes: Vec<(String, CompressionStats)> = databases.compression_stats().await.into_iter().collect();
+    prefixes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut total = CompressionStats::default();
+    for (_, stats) in &prefixes {
+        total.merge(stats);
+    }
+
+    let mut lines = vec![compression_line("*", &total)];
+    for (prefix, stats) in &prefixes {
+        let prefix = if prefix.is_empty() { "(none)" } else { prefix };
+        lines.push(compression_line(prefix, stats));
+    }
+    lines
+}
+
+// One line per value type, including types with no keys
+async fn value_type_info(databases: &Databases) -> Vec<String> {
+    let stats = databases.value_type_stats().await;
+    let total: usize = stats.values().map(|stats| stats.keys).sum();
+    ValueType::ALL
+        .iter()
+        .map(|value_type| {
+            let stats = stats.get(value_type).copied().unwrap_or_default();
+            let share = if total == 0 { 0.0 } else { stats.keys as f64 * 100.0 / total as f64 };
+            format!(
+                "type={} keys={} percent={:.1} original_bytes={} ratio={:.2}",
+                value_type.name(), stats.keys, share, stats.original_bytes, stats.ratio()
+            )
+        })
+        .collect()
+}
+
+async fn memory_info(databases: &Databases) -> Vec<String> {
+    let mut values = CompressionStats::default();
+    for stats in databases.compression_stats().await.values() {
+        values.merge(stats);
+    }
+    let interning = databases.interning_stats().await;
+    vec![format!(
+        "keys={} value_bytes={} interned_prefixes={} interned_keys={} prefix_bytes_saved={}",
+        values.keys, values.compressed_bytes, interning.prefixes, interning.keys, interning.bytes_saved
+    )]
+}
+
+fn compression_line(prefix: &str, stats: &CompressionStats) -> String {
+    format!(
+        "prefix={} keys={} original_bytes={} compressed_bytes={} ratio={:.2}",
+        prefix, stats.keys, stats.original_bytes, stats.compressed_bytes, stats.ratio()
+    )
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+use crate::cache::{CacheEntry, Databases};
+use crate::configuration::{HydrogenConfig, SharedConfig};
+use crate::supervisor::TaskHandle;
+
+/// Periodically recompresses large entries that have not been read for a
+/// while at `recompress_level`, trading a one-off CPU cost for memory. The
+/// settings are re-read before every pass, so the task follows CONFIG SET.
+/// Runs under the supervisor, which restarts it if a pass fails.
+pub async fn run_recompression(databases: Arc<Databases>, config: Arc<SharedConfig>, task: TaskHandle) -> Result<(), String> {
+    loop {
+        let interval = config.current().recompress_interval_secs.max(1);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let current = config.current();
+        if !current.recompress_enabled {
+            continue;
+        }
+        recompression_pass(&databases, &current).await?;
+        task.ran();
+    }
+}
+
+async fn recompression_pass(databases: &Databases, config: &HydrogenConfig) -> Result<(), String> {
+    // A budget of 0 lets a pass run until every candidate is done
+    let budget = Duration::from_millis(config.recompress_cpu_budget_ms);
+    let min_idle = Duration::from_secs(config.recompress_min_idle_secs);
+    let level = config.recompress_level;
+    let mut spent = Duration::ZERO;
+    let mut recompressed = 0usize;
+    let mut saved = 0usize;
+
+    for database in databases.iter() {
+        if !budget.is_zero() && spent >= budget {
+            break;
+        }
+        let candidates = database.cold_entries(config.recompress_min_size, min_idle, level).await;
+        if candidates.is_empty() {
+            continue;
+        }
+
+        let remaining = budget.saturating_sub(spent);
+        let unlimited = budget.is_zero();
+        let result = tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            let mut replacements: Vec<(String, CacheEntry, CacheEntry)> = Vec::new();
+            for (key, original) in candidates {
+                if !unlimited && started.elapsed() >= remaining {
+                    break;
+                }
+                match original.recompress(level) {
+                    Ok(replacement) => replacements.push((key, original, replacement)),
+                    Err(e) => debug!("Skipping recompression of {}: {}", key, e),
+                }
+            }
+            (replacements, started.elapsed())
+        })
+        .await;
+
+        let (replacements, elapsed) = result.map_err(|e| format!("recompression worker failed: {}", e))?;
+        spent += elapsed;
+
+        for (key, original, replacement) in replacements {
+            let before = original.stored_size();
+            let after = replacement.stored_size();
+            if database.replace_if_unchanged(&key, &original, replacement).await {
+                recompressed += 1;
+                saved += before.saturating_sub(after);
+            }
+        }
+    }
+
+    if recompressed > 0 {
+        info!(
+            "Recompressed {} entries at level {}, saved {} bytes in {} ms",
+            recompressed, level, saved, spent.as_millis()
+        );
+    }
+    Ok(())
+}
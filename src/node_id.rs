@@ -2,10 +2,21 @@
 // A scalable and lightweight Key Value Cache written in Rust
 
 use rand::Rng;
+use std::fs;
+use crate::configuration::{ConfigError, HydrogenConfig, NodeIdMode};
 
 const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
 const NODE_ID_LENGTH: usize = 7;
 
+/// Picks this node's ID according to `node_id_mode`.
+pub fn node_id_for(config: &HydrogenConfig) -> Result<String, ConfigError> {
+    match config.node_id_mode()? {
+        NodeIdMode::Random => Ok(generate_node_id()),
+        NodeIdMode::Hostname => Ok(derive_node_id(&format!("{}:{}", hostname()?, config.bind_port))),
+        NodeIdMode::Seed(seed) => Ok(derive_node_id(&seed)),
+    }
+}
+
 pub fn generate_node_id() -> String {
     let mut rng = rand::thread_rng();
     (0..NODE_ID_LENGTH)
@@ -14,4 +25,33 @@ pub fn generate_node_id() -> String {
             CHARSET[idx] as char
         })
         .collect()
-} 
\ No newline at end of file
+}
+
+/// The same input always gives the same ID: the 64-bit FNV-1a hash of the
+/// input written in base 36, least significant digit first, cut to
+/// `NODE_ID_LENGTH` characters. Tooling can compute IDs ahead of deployment.
+pub fn derive_node_id(input: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in input.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (0..NODE_ID_LENGTH)
+        .map(|_| {
+            let idx = (hash % CHARSET.len() as u64) as usize;
+            hash /= CHARSET.len() as u64;
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+fn hostname() -> Result<String, ConfigError> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .ok_or_else(|| ConfigError::InvalidValue(
+            "node_id_mode \"hostname\" could not read the hostname".to_string(),
+        ))
+}
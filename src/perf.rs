@@ -0,0 +1,118 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::fmt;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use tokio::runtime::Handle;
+use crate::cache::{CacheEntry, CacheError, Hydrogen};
+
+pub const DEFAULT_SELFTEST_ITERATIONS: usize = 10_000;
+pub const MAX_SELFTEST_ITERATIONS: usize = 1_000_000;
+const SELFTEST_VALUE_SIZE: usize = 1024;
+// Keys set/get cycle through, which bounds the scratch keyspace's memory
+const SELFTEST_KEYS: usize = 10_000;
+
+type CacheResult<T> = Result<T, CacheError>;
+
+/// Throughput measured by PERF SELFTEST on this machine.
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub iterations: usize,
+    pub value_size: usize,
+    pub compression_level: i32,
+    pub set: Duration,
+    pub get: Duration,
+    pub compress: Duration,
+    pub decompress: Duration,
+}
+
+impl SelfTestReport {
+    fn ops_per_sec(&self, elapsed: Duration) -> f64 {
+        self.iterations as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn megabytes_per_sec(&self, elapsed: Duration) -> f64 {
+        self.ops_per_sec(elapsed) * self.value_size as f64 / (1024.0 * 1024.0)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "set={:.0} ops/s get={:.0} ops/s compress={:.1} MB/s decompress={:.1} MB/s ({} iterations, {} byte values, level {})",
+            self.ops_per_sec(self.set),
+            self.ops_per_sec(self.get),
+            self.megabytes_per_sec(self.compress),
+            self.megabytes_per_sec(self.decompress),
+            self.iterations,
+            self.value_size,
+            self.compression_level,
+        )
+    }
+}
+
+/// Runs set/get against a scratch keyspace and times raw compression, so
+/// live data is never touched. Everything runs on a blocking thread to keep
+/// other clients responsive, and set/get cycle through at most
+/// `SELFTEST_KEYS` keys, so the scratch keyspace stays around 10 MB however
+/// many iterations are asked for.
+pub async fn selftest(iterations: usize, compression_level: i32) -> CacheResult<SelfTestReport> {
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || run_selftest(&runtime, iterations, compression_level))
+        .await
+        .map_err(|e| CacheError::CompressionError(e.to_string()))?
+}
+
+fn run_selftest(runtime: &Handle, iterations: usize, compression_level: i32) -> CacheResult<SelfTestReport> {
+    let value: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SELFTEST_VALUE_SIZE)
+        .map(char::from)
+        .collect();
+
+    let scratch = Hydrogen::new();
+    scratch.set_compression_level(compression_level);
+    let keys: Vec<String> = (0..iterations.min(SELFTEST_KEYS)).map(|i| format!("selftest-{}", i)).collect();
+
+    // The scratch keyspace is never contended, so its locks do not block
+    let (set, get) = runtime.block_on(async {
+        let started = Instant::now();
+        for key in keys.iter().cycle().take(iterations) {
+            scratch.set(key.clone(), value.clone()).await?;
+        }
+        let set = started.elapsed();
+
+        let started = Instant::now();
+        for key in keys.iter().cycle().take(iterations) {
+            scratch.get(key).await?;
+        }
+        Ok::<_, CacheError>((set, started.elapsed()))
+    })?;
+    drop(scratch);
+
+    let started = Instant::now();
+    let mut entry = CacheEntry::new(&value, compression_level)?;
+    for _ in 1..iterations {
+        entry = CacheEntry::new(&value, compression_level)?;
+    }
+    let compress = started.elapsed();
+
+    let started = Instant::now();
+    for _ in 0..iterations {
+        entry.get_value()?;
+    }
+    let decompress = started.elapsed();
+
+    Ok(SelfTestReport {
+        iterations,
+        value_size: SELFTEST_VALUE_SIZE,
+        compression_level,
+        set,
+        get,
+        compress,
+        decompress,
+    })
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tracing::{error, info};
+use crate::cache::Databases;
+use crate::supervisor::TaskHandle;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScheduledAction {
+    Set { key: String, value: String },
+    Delete { key: String },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ScheduledOp {
+    /// Seconds since the epoch
+    at: u64,
+    id: u64,
+    db: usize,
+    action: ScheduledAction,
+}
+
+// Ordered by time, then by the order they were scheduled in
+impl Ord for ScheduledOp {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.at, self.id).cmp(&(other.at, other.id))
+    }
+}
+
+impl PartialOrd for ScheduledOp {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Writes queued by SETAT and DELAT, applied by a background task once
+/// their time comes. Operations scheduled for the past run straight away.
+/// The queue is kept in memory only, so a restart drops it.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    queue: Mutex<BinaryHeap<Reverse<ScheduledOp>>>,
+    next_id: AtomicU64,
+    added: Notify,
+}
+
+impl Scheduler {
+    /// Queues `action` on database `db` and returns its ID.
+    pub fn schedule(&self, at: u64, db: usize, action: ScheduledAction) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        lock(&self.queue).push(Reverse(ScheduledOp { at, id, db, action }));
+        self.added.notify_one();
+        id
+    }
+
+    pub async fn run(self: Arc<Self>, databases: Arc<Databases>, task: TaskHandle) -> Result<(), String> {
+        loop {
+            for op in self.take_due() {
+                Self::apply(&databases, op).await;
+                task.ran();
+            }
+
+            let next = lock(&self.queue).peek().map(|Reverse(op)| Duration::from_secs(op.at));
+            match next {
+                Some(at) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(at.saturating_sub(now())) => {}
+                        _ = self.added.notified() => {}
+                    }
+                }
+                None => self.added.notified().await,
+            }
+        }
+    }
+
+    fn take_due(&self) -> Vec<ScheduledOp> {
+        let now = now().as_secs();
+        let mut queue = lock(&self.queue);
+        let mut due = Vec::new();
+        while queue.peek().is_some_and(|Reverse(op)| op.at <= now) {
+            if let Some(Reverse(op)) = queue.pop() {
+                due.push(op);
+            }
+        }
+        due
+    }
+
+    async fn apply(databases: &Databases, op: ScheduledOp) {
+        let Some(cache) = databases.get(op.db) else {
+            error!("Scheduled operation {} targets missing database {}", op.id, op.db);
+            return;
+        };
+        let result = match op.action {
+            ScheduledAction::Set { key, value } => cache.set(key, value).await,
+            ScheduledAction::Delete { key } => cache.delete(&key).await.map(|_| ()),
+        };
+        match result {
+            Ok(()) => info!("Applied scheduled operation {}", op.id),
+            Err(e) => error!("Scheduled operation {} failed: {}", op.id, e),
+        }
+    }
+}
@@ -3,35 +3,103 @@
 
 mod api;
 mod api_log;
+mod archive;
+mod backing_store;
 mod cache;
 mod cluster;
+mod command_spec;
+mod commands;
 mod configuration;
+mod health;
+mod interning;
+mod introspection;
+mod maintenance;
 mod node_id;
+mod perf;
+mod scheduler;
+mod slots;
+mod snapshot;
+mod spool;
 mod startup_log;
+mod supervisor;
+mod usage;
+mod write_batch;
 
 use api::TcpApiServer;
-use cache::Hydrogen;
-use configuration::HydrogenConfig;
+use api_log::{AuditLog, TrafficCapture};
+use cache::Databases;
+use cluster::Cluster;
+use configuration::{HydrogenConfig, SharedConfig, CONFIG_FILE};
+use health::Health;
 use startup_log::display_startup_info;
+use supervisor::{Supervisor, TaskHandle};
 use std::sync::Arc;
-use tracing::error;
-use tracing_subscriber;
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info, warn};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_level(true)
+    let (level_filter, level_handle) = reload::Layer::new(LevelFilter::INFO);
+    tracing_subscriber::registry()
+        .with(level_filter)
+        .with(fmt::layer()
+            .with_target(false)
+            .with_thread_ids(true)
+            .with_level(true))
         .init();
 
     let config = HydrogenConfig::load_or_create()?;
     let bind_addr = config.bind_address();
+
+    // Started first so probes can tell a slow start from a dead process
+    let health = Arc::new(Health::default());
+    if config.health_port != 0 {
+        health::serve_http(&format!("{}:{}", config.bind_ip, config.health_port), Arc::clone(&health)).await?;
+    }
     
-    let cache = Arc::new(Hydrogen::new());
-    let server = TcpApiServer::new(&bind_addr, cache.clone()).await?;
+    if config.standalone && config.cluster_enabled {
+        warn!("Running standalone, ignoring cluster_enabled");
+    }
+    let cluster = if config.cluster_active() {
+        Some(Arc::new(Cluster::load(&config)?))
+    } else {
+        None
+    };
+
+    let backing_store = backing_store::from_config(&config)?;
+    let databases = Arc::new(Databases::new(config.databases, backing_store, config.write_through));
+    let supervisor = Arc::new(Supervisor::default());
+    databases.start_write_batching(config.write_batch_queue_len, config.write_batch_max, &supervisor);
+    let audit_log = AuditLog::from_config(&config)?.map(Arc::new);
+    let capture = Arc::new(TrafficCapture::new(&config.capture_path));
+
+    let mut shared_config = SharedConfig::new(config);
+    let listener_databases = Arc::clone(&databases);
+    let listener_capture = Arc::clone(&capture);
+    shared_config.on_change(move |config| {
+        listener_databases.set_compression_level(config.compression_level);
+        listener_databases.set_max_value_bytes(config.max_line_length);
+        listener_databases.set_namespace_quotas(&config.namespace_quotas);
+        listener_capture.set_enabled(config.capture_enabled);
+        if let Ok(level) = config.level_filter() {
+            let _ = level_handle.modify(|filter| *filter = level);
+        }
+    });
+    let shared_config = Arc::new(shared_config);
+    let reload_config = Arc::clone(&shared_config);
+    supervisor.spawn("config_reload", move |task| reload_on_sighup(Arc::clone(&reload_config), task));
+    let recompression_databases = Arc::clone(&databases);
+    let recompression_config = Arc::clone(&shared_config);
+    supervisor.spawn("recompression", move |task| {
+        maintenance::run_recompression(Arc::clone(&recompression_databases), Arc::clone(&recompression_config), task)
+    });
+
+    let server = TcpApiServer::new(&bind_addr, databases, shared_config, cluster, audit_log, capture, supervisor).await?;
     
     display_startup_info(server.local_addr()?);
+    health.set_ready();
     tokio::select! {
         result = server.run() => {
             if let Err(e) = result {
@@ -41,6 +109,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _ = tokio::signal::ctrl_c() => {
         }
     }
+    health.set_shutting_down();
+
+    Ok(())
+}
 
+async fn reload_on_sighup(config: Arc<SharedConfig>, task: TaskHandle) -> Result<(), String> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())
+        .map_err(|e| format!("failed to install SIGHUP handler: {}", e))?;
+    while hangup.recv().await.is_some() {
+        match config.reload(CONFIG_FILE) {
+            Ok(changed) if changed.is_empty() => info!("Config reloaded, no hot-reloadable changes"),
+            Ok(changed) => info!("Config reloaded, updated: {}", changed.join(", ")),
+            Err(e) => error!("Config reload failed, keeping current settings: {}", e),
+        }
+        task.ran();
+    }
     Ok(())
-} 
\ No newline at end of file
+}
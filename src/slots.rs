@@ -0,0 +1,26 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+pub const SLOT_COUNT: u32 = 16384;
+
+// CRC16/XMODEM, the same slot hash Redis Cluster uses
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The cluster slot a key belongs to. Shared by the server and
+/// hydrogen-proxy so both place keys the same way.
+pub fn key_slot(key: &str) -> u32 {
+    crc16(key.as_bytes()) as u32 % SLOT_COUNT
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::path::{Component, Path};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use crate::cache::{CacheError, Hydrogen};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Path must be relative, without '..': {0}")]
+    InvalidPath(String),
+    #[error("Invalid export record on line {0}: {1}")]
+    InvalidRecord(usize, String),
+    #[error("Cache error: {0}")]
+    Cache(#[from] CacheError),
+}
+
+type SnapshotResult<T> = Result<T, SnapshotError>;
+
+/// One line of an export file. Values are stored uncompressed so exports can
+/// be read by other tools and by Hydrogen builds with different encodings.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportRecord {
+    key: String,
+    value: String,
+}
+
+pub fn encode_payload(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode_payload(payload: &str) -> Option<Vec<u8>> {
+    if !payload.len().is_multiple_of(2) || !payload.is_ascii() {
+        return None;
+    }
+    (0..payload.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&payload[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Places a client supplied file name under `dir`, so admin commands that
+/// read or write files on the server cannot reach outside it.
+pub fn resolve_path(dir: &str, path: &str) -> SnapshotResult<String> {
+    let relative = Path::new(path);
+    let inside = relative.components().all(|component| matches!(component, Component::Normal(_)));
+    if path.is_empty() || !inside {
+        return Err(SnapshotError::InvalidPath(path.to_string()));
+    }
+    Ok(Path::new(dir).join(relative).to_string_lossy().into_owned())
+}
+
+pub async fn export_to_file(cache: &Hydrogen, path: &str) -> SnapshotResult<usize> {
+    export_matching(cache, path, |_| true).await
+}
+
+/// Exports only the keys accepted by `predicate`, such as one cluster slot
+/// range.
+pub async fn export_matching(cache: &Hydrogen, path: &str, predicate: impl Fn(&str) -> bool) -> SnapshotResult<usize> {
+    let mut entries = cache.snapshot().await;
+    entries.retain(|(key, _)| predicate(key));
+
+    if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temp_path = format!("{}.tmp", path);
+    let mut writer = BufWriter::new(File::create(&temp_path).await?);
+    for (key, entry) in &entries {
+        let record = ExportRecord { key: key.clone(), value: entry.get_value()? };
+        let mut line = serde_json::to_string(&record).map_err(std::io::Error::other)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await?;
+    }
+    writer.flush().await?;
+    tokio::fs::rename(&temp_path, path).await?;
+
+    Ok(entries.len())
+}
+
+/// Imports every record. `check_key` refuses keys the server would not
+/// accept, as it does for the functions below.
+/// Whether `key` matches a glob `pattern`, where `*` matches any run of
+/// characters and `?` any single one, as in `EXPORT path PATTERN user:*`.
+pub fn matches_pattern(pattern: &str, key: &str) -> bool {
+    let (pattern, key): (Vec<char>, Vec<char>) = (pattern.chars().collect(), key.chars().collect());
+    let (mut p, mut k) = (0, 0);
+    // Where the last `*` was, and how much of the key it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match star {
+                Some((star_p, star_k)) => {
+                    star = Some((star_p, star_k + 1));
+                    p = star_p + 1;
+                    k = star_k + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+pub async fn import_from_file(
+    cache: &Hydrogen,
+    path: &str,
+    check_key: impl Fn(&str) -> Result<(), String>,
+) -> SnapshotResult<usize> {
+    import_matching(cache, path, |_| true, check_key).await
+}
+
+/// Imports only the records whose key is accepted by `predicate`; the rest
+/// of the file is skipped.
+pub async fn import_matching(
+    cache: &Hydrogen,
+    path: &str,
+    predicate: impl Fn(&str) -> bool,
+    check_key: impl Fn(&str) -> Result<(), String>,
+) -> SnapshotResult<usize> {
+    import_records(cache, path, |key| {
+        if !predicate(&key) {
+            return Ok(None);
+        }
+        check_key(&key)?;
+        Ok(Some(key))
+    }).await
+}
+
+/// Imports every record under `prefix` followed by its key, so an export can
+/// be loaded next to the keys it would otherwise overwrite. `check_key`
+/// sees the prefixed keys.
+pub async fn import_with_prefix(
+    cache: &Hydrogen,
+    path: &str,
+    prefix: &str,
+    check_key: impl Fn(&str) -> Result<(), String>,
+) -> SnapshotResult<usize> {
+    import_records(cache, path, |key| {
+        let key = format!("{}{}", prefix, key);
+        check_key(&key)?;
+        Ok(Some(key))
+    }).await
+}
+
+// Sets each record under the key `map_key` gives it, skipping records it
+// maps to None
+async fn import_records(
+    cache: &Hydrogen,
+    path: &str,
+    map_key: impl Fn(String) -> Result<Option<String>, String>,
+) -> SnapshotResult<usize> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut imported = 0;
+    let mut line_number = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ExportRecord = serde_json::from_str(&line)
+            .map_err(|e| SnapshotError::InvalidRecord(line_number, e.to_string()))?;
+        let Some(key) = map_key(record.key).map_err(|e| SnapshotError::InvalidRecord(line_number, e))? else {
+            continue;
+        };
+        cache.set(key, record.value).await?;
+        imported += 1;
+    }
+
+    Ok(imported)
+}
@@ -0,0 +1,250 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufWriter};
+use zstd::stream::{copy_decode, Encoder};
+use crate::cache::ValueType;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+static NEXT_SPOOL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A temporary file holding a request or reply body, removed when dropped.
+#[derive(Debug)]
+pub struct SpoolFile {
+    path: PathBuf,
+}
+
+impl SpoolFile {
+    // An empty `dir` means the system temp directory
+    fn create(dir: &str) -> io::Result<(Self, std::fs::File)> {
+        let dir = if dir.is_empty() { std::env::temp_dir() } else { PathBuf::from(dir) };
+        let id = NEXT_SPOOL_ID.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("hydrogen-spool-{}-{}", std::process::id(), id));
+        let file = std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok((Self { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// One request read from a connection.
+#[derive(Debug)]
+pub enum Request {
+    Eof,
+    TooLong,
+    Line(String),
+    Spooled(SpooledRequest),
+}
+
+/// A request line longer than `spool_threshold_bytes`. Only its start is
+/// kept in memory, enough to parse the command and key; the whole line is
+/// in the file.
+#[derive(Debug)]
+pub struct SpooledRequest {
+    pub header: String,
+    pub file: Arc<SpoolFile>,
+    /// Offset just past the last non-whitespace byte of the line
+    pub content_end: u64,
+    pub last_byte: u8,
+}
+
+/// Reads one line like `read_line`, but once it grows past `threshold`
+/// bytes the rest is written to a spool file in `dir` instead of memory. A
+/// threshold of 0 never spools.
+pub async fn read_request<R>(reader: &mut R, max_len: u64, threshold: usize, dir: &str) -> io::Result<Request>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut buffered = Vec::new();
+    let mut spool: Option<(SpoolFile, BufWriter<tokio::fs::File>)> = None;
+    let mut total = 0u64;
+    let mut content_end = 0u64;
+    let mut last_byte = 0u8;
+
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        let (len, done) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false),
+        };
+        let chunk = &available[..len];
+        if let Some(last) = chunk.iter().rposition(|byte| !byte.is_ascii_whitespace()) {
+            content_end = total + last as u64 + 1;
+            last_byte = chunk[last];
+        }
+        total += len as u64;
+        if total > max_len {
+            reader.consume(len);
+            return Ok(Request::TooLong);
+        }
+
+        match &mut spool {
+            Some((_, writer)) => writer.write_all(chunk).await?,
+            None => buffered.extend_from_slice(chunk),
+        }
+        reader.consume(len);
+
+        if spool.is_none() && threshold > 0 && buffered.len() > threshold && !done {
+            let (file, std_file) = SpoolFile::create(dir)?;
+            let mut writer = BufWriter::new(tokio::fs::File::from_std(std_file));
+            writer.write_all(&buffered).await?;
+            spool = Some((file, writer));
+        }
+        if done {
+            break;
+        }
+    }
+
+    if total == 0 {
+        return Ok(Request::Eof);
+    }
+    let Some((file, mut writer)) = spool else {
+        let line = String::from_utf8(buffered)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))?;
+        return Ok(Request::Line(line));
+    };
+    writer.flush().await?;
+
+    // The buffered start may end inside a character, which is cut off
+    let valid = match std::str::from_utf8(&buffered) {
+        Ok(header) => header.len(),
+        Err(e) => e.valid_up_to(),
+    };
+    buffered.truncate(valid);
+    Ok(Request::Spooled(SpooledRequest {
+        header: String::from_utf8(buffered).unwrap_or_default(),
+        file: Arc::new(file),
+        content_end,
+        last_byte,
+    }))
+}
+
+/// A spooled value after compression.
+#[derive(Debug)]
+pub struct CompressedValue {
+    pub data: Vec<u8>,
+    pub original_size: usize,
+    pub value_type: ValueType,
+}
+
+/// The value of a spooled SET: a byte range of the spool file. Quoted values
+/// are taken as they are, unquoted ones get their whitespace collapsed the
+/// same way as in a normal request.
+#[derive(Debug, Clone)]
+pub struct SpooledValue {
+    file: Arc<SpoolFile>,
+    start: u64,
+    end: u64,
+    quoted: bool,
+}
+
+impl SpooledValue {
+    pub fn new(file: Arc<SpoolFile>, start: u64, end: u64, quoted: bool) -> Self {
+        if quoted {
+            Self { file, start: start + 1, end: end - 1, quoted }
+        } else {
+            Self { file, start, end, quoted }
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Compresses the value straight from the file. Its type is sniffed
+    /// from the first chunk. This blocks, so it belongs on a blocking thread.
+    pub fn compress(&self, level: i32) -> io::Result<CompressedValue> {
+        let mut file = std::fs::File::open(self.file.path())?;
+        file.seek(SeekFrom::Start(self.start))?;
+        let mut reader = file.take(self.len());
+
+        let mut encoder = Encoder::new(Vec::new(), level)?;
+        if self.quoted {
+            encoder.set_pledged_src_size(Some(self.len()))?;
+            encoder.include_contentsize(true)?;
+        }
+
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let mut pending: Vec<u8> = Vec::new();
+        let mut collapsed = String::new();
+        let mut seen_text = false;
+        let mut pending_space = false;
+        let mut written = 0usize;
+        let mut value_type = None;
+        loop {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&chunk[..read]);
+
+            // A character split across chunks waits for the next read
+            let valid = match std::str::from_utf8(&pending) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "value is not valid UTF-8")),
+            };
+            if value_type.is_none() {
+                let start = std::str::from_utf8(&pending[..valid]).map_err(io::Error::other)?;
+                value_type = Some(ValueType::sniff_start(start));
+            }
+            if self.quoted {
+                encoder.write_all(&pending[..valid])?;
+                written += valid;
+            } else {
+                let text = std::str::from_utf8(&pending[..valid]).map_err(io::Error::other)?;
+                collapsed.clear();
+                for ch in text.chars() {
+                    if ch.is_whitespace() {
+                        pending_space = seen_text;
+                        continue;
+                    }
+                    if pending_space {
+                        collapsed.push(' ');
+                        pending_space = false;
+                    }
+                    collapsed.push(ch);
+                    seen_text = true;
+                }
+                encoder.write_all(collapsed.as_bytes())?;
+                written += collapsed.len();
+            }
+            pending.drain(..valid);
+        }
+
+        if !pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "value is not valid UTF-8"));
+        }
+        Ok(CompressedValue {
+            data: encoder.finish()?,
+            original_size: written,
+            value_type: value_type.unwrap_or(ValueType::Text),
+        })
+    }
+}
+
+/// Decompresses a value into a spool file so a large reply can be copied to
+/// the client without holding the whole value in memory. This blocks.
+pub fn decode_to_file(compressed: &[u8], dir: &str) -> io::Result<SpoolFile> {
+    let (file, std_file) = SpoolFile::create(dir)?;
+    let mut writer = io::BufWriter::new(std_file);
+    copy_decode(compressed, &mut writer)?;
+    writer.flush()?;
+    Ok(file)
+}
@@ -0,0 +1,154 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinError;
+use tracing::{error, info};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A task that stayed up this long before failing starts over at the
+// initial backoff
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+    Running,
+    /// Failed and waiting to be restarted
+    Backoff,
+    /// Returned without an error and will not run again
+    Finished,
+}
+
+impl TaskState {
+    fn name(&self) -> &'static str {
+        match self {
+            TaskState::Running => "running",
+            TaskState::Backoff => "backoff",
+            TaskState::Finished => "finished",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TaskStatus {
+    name: String,
+    state: TaskState,
+    restarts: u64,
+    /// Seconds since the epoch
+    last_run: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// Lets a supervised task report its progress.
+#[derive(Debug, Clone)]
+pub struct TaskHandle {
+    status: Arc<Mutex<TaskStatus>>,
+}
+
+impl TaskHandle {
+    /// Records that the task just finished a unit of work, such as one
+    /// recompression pass.
+    pub fn ran(&self) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        lock(&self.status).last_run = Some(now);
+    }
+}
+
+/// Runs the server's background tasks and keeps their status for
+/// `INFO tasks`. A task that panics or returns an error is started again
+/// after a backoff that doubles up to a minute; one that returns `Ok` is
+/// done.
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    tasks: Mutex<Vec<Arc<Mutex<TaskStatus>>>>,
+}
+
+impl Supervisor {
+    /// Starts `task`, calling it again for every restart.
+    pub fn spawn<F, Fut>(&self, name: impl Into<String>, task: F)
+    where
+        F: Fn(TaskHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let name = name.into();
+        let status = Arc::new(Mutex::new(TaskStatus {
+            name: name.clone(),
+            state: TaskState::Running,
+            restarts: 0,
+            last_run: None,
+            last_error: None,
+        }));
+        lock(&self.tasks).push(Arc::clone(&status));
+        let handle = TaskHandle { status };
+
+        tokio::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                lock(&handle.status).state = TaskState::Running;
+                let started = Instant::now();
+                // Run in its own task so a panic is caught here
+                let error = match tokio::spawn(task(handle.clone())).await {
+                    Ok(Ok(())) => {
+                        lock(&handle.status).state = TaskState::Finished;
+                        info!("Background task {} finished", name);
+                        return;
+                    }
+                    Ok(Err(e)) => e,
+                    Err(e) => panic_message(e),
+                };
+
+                if started.elapsed() >= HEALTHY_RUN {
+                    backoff = INITIAL_BACKOFF;
+                }
+                error!("Background task {} failed, restarting in {}s: {}", name, backoff.as_secs(), error);
+                {
+                    let mut status = lock(&handle.status);
+                    status.state = TaskState::Backoff;
+                    status.restarts += 1;
+                    status.last_error = Some(error);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    /// One line per task, in the order they were started.
+    pub fn lines(&self) -> Vec<String> {
+        lock(&self.tasks)
+            .iter()
+            .map(|status| {
+                let status = lock(status);
+                let last_run = status.last_run.map_or("never".to_string(), |time| time.to_string());
+                let last_error = status.last_error.as_deref().map_or("none".to_string(), |e| format!("{:?}", e));
+                format!(
+                    "name={} state={} restarts={} last_run={} last_error={}",
+                    status.name, status.state.name(), status.restarts, last_run, last_error
+                )
+            })
+            .collect()
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let payload = error.into_panic();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("panicked: {}", message)
+}
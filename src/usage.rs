@@ -0,0 +1,305 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use crate::cache::Databases;
+use crate::configuration::SharedConfig;
+use crate::snapshot;
+use crate::supervisor::TaskHandle;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+// A little over a year, so reports for the same month last year still work
+const KEPT_DAYS: i64 = 400;
+/// The tenant for commands without a key, or whose key has no namespace
+const NO_TENANT: &str = "(none)";
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn today() -> i64 {
+    (SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECS_PER_DAY) as i64
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date, and back
+// (http://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (month as i64, day as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn format_day(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// "2026-10-15" into days since the epoch
+fn parse_day(date: &str) -> Option<i64> {
+    let (year_month, day) = date.rsplit_once('-')?;
+    let (year, month) = parse_month(year_month)?;
+    let day: u32 = day.parse().ok().filter(|_| day.len() == 2)?;
+    let days = days_from_civil(year, month, day);
+    // Rejects dates like 2026-02-30 that roll over into the next month
+    (civil_from_days(days) == (year, month, day)).then_some(days)
+}
+
+// "2026-10" into its year and month
+fn parse_month(date: &str) -> Option<(i64, u32)> {
+    let (year, month) = date.split_once('-')?;
+    if year.len() != 4 || month.len() != 2 {
+        return None;
+    }
+    let year: i64 = year.parse().ok()?;
+    let month: u32 = month.parse().ok().filter(|month| (1..=12).contains(month))?;
+    Some((year, month))
+}
+
+/// The days a usage report covers, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsagePeriod {
+    /// Days since the epoch
+    Day(i64),
+    Month { year: i64, month: u32 },
+    All,
+}
+
+impl UsagePeriod {
+    /// Parses `today`, `all`, a month such as `2026-10` or a day such as
+    /// `2026-10-15`.
+    pub fn parse(period: &str) -> Option<Self> {
+        if period.eq_ignore_ascii_case("today") {
+            return Some(UsagePeriod::Day(today()));
+        }
+        if period.eq_ignore_ascii_case("all") {
+            return Some(UsagePeriod::All);
+        }
+        if let Some((year, month)) = parse_month(period) {
+            return Some(UsagePeriod::Month { year, month });
+        }
+        parse_day(period).map(UsagePeriod::Day)
+    }
+
+    pub fn current_month() -> Self {
+        let (year, month, _) = civil_from_days(today());
+        UsagePeriod::Month { year, month }
+    }
+
+    fn contains(&self, days: i64) -> bool {
+        match *self {
+            UsagePeriod::Day(day) => day == days,
+            UsagePeriod::Month { year, month } => {
+                let (day_year, day_month, _) = civil_from_days(days);
+                (day_year, day_month) == (year, month)
+            }
+            UsagePeriod::All => true,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match *self {
+            UsagePeriod::Day(day) => format_day(day),
+            UsagePeriod::Month { year, month } => format!("{:04}-{:02}", year, month),
+            UsagePeriod::All => "all".to_string(),
+        }
+    }
+}
+
+/// What one tenant used on one day.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct TenantUsage {
+    commands: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    /// Stored value bytes multiplied by the seconds they were held
+    byte_seconds: u64,
+}
+
+impl TenantUsage {
+    fn merge(&mut self, other: &TenantUsage) {
+        self.commands = self.commands.saturating_add(other.commands);
+        self.bytes_in = self.bytes_in.saturating_add(other.bytes_in);
+        self.bytes_out = self.bytes_out.saturating_add(other.bytes_out);
+        self.byte_seconds = self.byte_seconds.saturating_add(other.byte_seconds);
+    }
+}
+
+/// One tenant's line in a usage report.
+#[derive(Debug, Serialize)]
+pub struct UsageRow {
+    period: String,
+    tenant: String,
+    commands: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    storage_byte_days: u64,
+}
+
+impl UsageRow {
+    const CSV_HEADER: &'static str = "period,tenant,commands,bytes_in,bytes_out,storage_byte_days";
+
+    // Namespaces never contain commas or quotes, so nothing needs escaping
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.period, self.tenant, self.commands, self.bytes_in, self.bytes_out, self.storage_byte_days
+        )
+    }
+}
+
+/// Usage per tenant and UTC day for chargeback, kept while `usage_enabled`
+/// is on. A tenant is a key namespace, the same unit `namespace_quotas`
+/// limits. Commands and bytes in and out are counted as they happen;
+/// storage is sampled every `usage_sample_secs` from the stored size of each
+/// namespace's values. The counters are saved to `usage_path` after every
+/// sample, so a crash loses at most one interval. In cluster mode every node
+/// meters its own traffic and keys.
+#[derive(Debug)]
+pub struct UsageMeter {
+    path: String,
+    days: Mutex<BTreeMap<i64, BTreeMap<String, TenantUsage>>>,
+}
+
+impl UsageMeter {
+    pub async fn open(path: &str) -> std::io::Result<Self> {
+        let mut days = BTreeMap::new();
+        match tokio::fs::read(path).await {
+            Ok(content) => {
+                let saved: BTreeMap<String, BTreeMap<String, TenantUsage>> =
+                    serde_json::from_slice(&content).map_err(std::io::Error::other)?;
+                for (day, tenants) in saved {
+                    let days_since_epoch = parse_day(&day).ok_or_else(|| {
+                        std::io::Error::other(format!("invalid day '{}' in {}", day, path))
+                    })?;
+                    days.insert(days_since_epoch, tenants);
+                }
+                info!("Loaded {} days of usage from {}", days.len(), path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self { path: path.to_string(), days: Mutex::new(days) })
+    }
+
+    /// Counts one command against `tenant`.
+    pub fn record(&self, tenant: Option<&str>, bytes_in: u64, bytes_out: u64) {
+        let tenant = tenant.unwrap_or(NO_TENANT);
+        let mut days = lock(&self.days);
+        let tenants = days.entry(today()).or_default();
+        let usage = match tenants.get_mut(tenant) {
+            Some(usage) => usage,
+            None => tenants.entry(tenant.to_string()).or_default(),
+        };
+        usage.commands += 1;
+        usage.bytes_in += bytes_in;
+        usage.bytes_out += bytes_out;
+    }
+
+    /// Totals per tenant over `period`, sorted by tenant.
+    pub fn report(&self, period: UsagePeriod) -> Vec<UsageRow> {
+        let mut totals: BTreeMap<String, TenantUsage> = BTreeMap::new();
+        for (_, tenants) in lock(&self.days).iter().filter(|(day, _)| period.contains(**day)) {
+            for (tenant, usage) in tenants {
+                totals.entry(tenant.clone()).or_default().merge(usage);
+            }
+        }
+        let label = period.label();
+        totals
+            .into_iter()
+            .map(|(tenant, usage)| UsageRow {
+                period: label.clone(),
+                tenant,
+                commands: usage.commands,
+                bytes_in: usage.bytes_in,
+                bytes_out: usage.bytes_out,
+                storage_byte_days: usage.byte_seconds / SECS_PER_DAY,
+            })
+            .collect()
+    }
+
+    /// Samples storage and saves the counters every `usage_sample_secs`.
+    /// Storage is only charged for time the meter was on.
+    pub async fn run(self: Arc<Self>, databases: Arc<Databases>, config: Arc<SharedConfig>, task: TaskHandle) -> Result<(), String> {
+        let mut last_sample = Instant::now();
+        loop {
+            let interval = config.current().usage_sample_secs.max(1);
+            tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+
+            let held = last_sample.elapsed().as_secs();
+            last_sample = Instant::now();
+            if !config.current().usage_enabled {
+                continue;
+            }
+            let storage = databases.compression_stats().await;
+            {
+                let mut days = lock(&self.days);
+                let tenants = days.entry(today()).or_default();
+                for (prefix, stats) in storage {
+                    let tenant = if prefix.is_empty() { NO_TENANT.to_string() } else { prefix };
+                    let usage = tenants.entry(tenant).or_default();
+                    usage.byte_seconds = usage.byte_seconds.saturating_add(stats.compressed_bytes as u64 * held);
+                }
+                let oldest = today() - KEPT_DAYS;
+                days.retain(|day, _| *day > oldest);
+            }
+            self.save().await.map_err(|e| format!("Failed to save usage to {}: {}", self.path, e))?;
+            task.ran();
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        let saved: BTreeMap<String, BTreeMap<String, TenantUsage>> = lock(&self.days)
+            .iter()
+            .map(|(day, tenants)| (format_day(*day), tenants.clone()))
+            .collect();
+        let content = serde_json::to_vec(&saved).map_err(std::io::Error::other)?;
+        let temp_path = format!("{}.tmp", self.path);
+        tokio::fs::write(&temp_path, content).await?;
+        tokio::fs::rename(&temp_path, &self.path).await
+    }
+
+    /// Writes a report to `name` in the directory of `usage_path`, as JSON
+    /// when the name ends in `.json` and as CSV otherwise.
+    pub async fn export_report(&self, rows: &[UsageRow], name: &str) -> std::io::Result<()> {
+        let dir = Path::new(&self.path).parent().map(|dir| dir.to_string_lossy()).unwrap_or_default();
+        let path = snapshot::resolve_path(&dir, name).map_err(std::io::Error::other)?;
+        let content = if path.ends_with(".json") {
+            serde_json::to_string(rows).map_err(std::io::Error::other)?
+        } else {
+            let mut lines = vec![UsageRow::CSV_HEADER.to_string()];
+            lines.extend(rows.iter().map(UsageRow::to_csv));
+            lines.join("\n")
+        };
+        if let Some(parent) = Path::new(&path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = format!("{}.tmp", path);
+        tokio::fs::write(&temp_path, content + "\n").await?;
+        tokio::fs::rename(&temp_path, path).await
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright (c) 2025, TheByteSlayer, Hydrogen
+// A scalable and lightweight Key Value Cache written in Rust
+
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use crate::cache::{CacheEntry, CacheError, Hydrogen};
+use crate::supervisor::{Supervisor, TaskHandle};
+
+type CacheResult<T> = Result<T, CacheError>;
+
+struct QueuedSet {
+    key: String,
+    value: String,
+    entry: CacheEntry,
+    reply: oneshot::Sender<CacheResult<()>>,
+}
+
+/// Coalesces SETs on one database. Callers compress their value and queue
+/// it; a single applier task drains whatever has queued up and applies it
+/// under one write lock acquisition, so concurrent writers stop contending
+/// for the lock one SET at a time. The applier runs under the supervisor,
+/// which restarts it on the same queue if it panics.
+#[derive(Debug)]
+pub struct WriteBatcher {
+    database: Arc<Hydrogen>,
+    sender: mpsc::Sender<QueuedSet>,
+}
+
+impl WriteBatcher {
+    pub fn spawn(database: Arc<Hydrogen>, queue_len: usize, max_batch: usize, supervisor: &Supervisor, name: String) -> Self {
+        let (sender, receiver) = mpsc::channel(queue_len.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let applier_database = Arc::clone(&database);
+        let max_batch = max_batch.max(1);
+        supervisor.spawn(name, move |task| {
+            Self::apply(Arc::clone(&applier_database), Arc::clone(&receiver), max_batch, task)
+        });
+        Self { database, sender }
+    }
+
+    pub async fn set(&self, key: String, value: String) -> CacheResult<()> {
+        let entry = self.database.compress(&value)?;
+        let (reply, response) = oneshot::channel();
+        let queued = QueuedSet { key, value, entry, reply };
+        if self.sender.send(queued).await.is_err() {
+            return Err(CacheError::BatchingError("write applier stopped".to_string()));
+        }
+        response.await
+            .unwrap_or_else(|_| Err(CacheError::BatchingError("write dropped before it was applied".to_string())))
+    }
+
+    // Returns once every sender is gone, which only happens at shutdown
+    async fn apply(
+        database: Arc<Hydrogen>,
+        receiver: Arc<Mutex<mpsc::Receiver<QueuedSet>>>,
+        max_batch: usize,
+        task: TaskHandle,
+    ) -> Result<(), String> {
+        let mut receiver = receiver.lock().await;
+        let mut batch = Vec::with_capacity(max_batch);
+        while receiver.recv_many(&mut batch, max_batch).await > 0 {
+            let (writes, replies): (Vec<_>, Vec<_>) = batch
+                .drain(..)
+                .map(|queued| ((queued.key, queued.value, queued.entry), queued.reply))
+                .unzip();
+            let results = database.set_batch(writes).await;
+            for (reply, result) in replies.into_iter().zip(results) {
+                // The caller may have timed out and gone away
+                let _ = reply.send(result);
+            }
+            task.ran();
+        }
+        Ok(())
+    }
+}